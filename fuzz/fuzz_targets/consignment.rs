@@ -0,0 +1,7 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use utils::*;
+
+// run from the project root, since fixtures are loaded from tests/fixtures
+fuzz_target!(|data: &[u8]| fuzz_consignment(data));
@@ -0,0 +1,10 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use utils::*;
+
+// each run needs a chain, the simulated one unless CHAIN=docker is set
+fuzz_target!(|data: &[u8]| {
+    initialize();
+    fuzz_channel(data)
+});
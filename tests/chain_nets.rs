@@ -1,5 +1,3 @@
-pub mod utils;
-
 use rstest_reuse::{self, *};
 use utils::*;
 
@@ -0,0 +1,276 @@
+//! Tests in this module are about the encoding of invoices as `rgb:` URI strings.
+
+use utils::*;
+
+const GOLDEN_INVOICES_FPATH: &str = "tests/fixtures/invoices.json";
+const GOLDEN_OUTPOINT: &str = "8d54c98d4c29a1ec4fd90635f543f0f7a871a78eb6a6e706342f831d92e3ba19:0";
+const GOLDEN_BLINDING: u64 = 654321;
+const GOLDEN_WPKH_SCRIPT: &str = "00141111111111111111111111111111111111111111";
+const GOLDEN_TR_SCRIPT: &str =
+    "51202222222222222222222222222222222222222222222222222222222222222222";
+const GOLDEN_INTERNAL_KEY: &str =
+    "03b2dbebaf199c3e49bb18d2690f3d6777e566d6b075dce432c8f4f5cf2ffd3d8d";
+const GOLDEN_EXPIRY: i64 = 1713261744;
+const GOLDEN_ENDPOINTS: [&str; 2] = [
+    "rpc://127.0.0.1:3000/json-rpc",
+    "rpcs://proxy.example.xyz/json-rpc",
+];
+
+/// Contract IDs of the contracts issued by the `deterministic_contract_id` test
+fn golden_contract_id(asset_schema: AssetSchema) -> ContractId {
+    let contract_id = match asset_schema {
+        AssetSchema::Nia => "rgb:yZ4vYrcp-U0TGOKE-8OI9pIx-KI7kvYn-E~eZ6x7-HQedWw0",
+        AssetSchema::Cfa => "rgb:Nkm0naXJ-TLQjJbZ-z1PbBWm-N9ZSlrM-NHPsyM8-KUB~Pog",
+        AssetSchema::Uda => "rgb:AYzddSFf-K_6Piay-l_nnowW-YMDgUlJ-LniVRJP-C3b7uNQ",
+        AssetSchema::Pfa => "rgb:L9pgSVBV-SZak7SD-HG5YZbz-zQYuV01-oMKcHpO-RAQQ2zM",
+        AssetSchema::Ifa => "rgb:Klp~7R_Q-z3Fv1J5-Vd6NotS-eGBmIqT-_zx2cz3-shptV6w",
+    };
+    ContractId::from_str(contract_id).unwrap()
+}
+
+/// Beneficiary built from fixed data, so that it doesn't need a wallet
+fn golden_beneficiary(invoice_type: InvoiceType) -> Beneficiary {
+    let pay2vout = |script: &str| {
+        let script_pubkey = script_buf_to_script_pubkey(ScriptBuf::from_hex(script).unwrap());
+        Pay2Vout::new(address_payload_bitcoin_from_script_pubkey(&script_pubkey))
+    };
+    match invoice_type {
+        InvoiceType::Blinded(outpoint) => {
+            let outpoint = outpoint.unwrap_or_else(|| Outpoint::from_str(GOLDEN_OUTPOINT).unwrap());
+            let seal = GraphSeal::from(BlindSeal::with_blinding(
+                outpoint.txid,
+                outpoint.vout,
+                GOLDEN_BLINDING,
+            ));
+            Beneficiary::BlindedSeal(seal.to_secret_seal())
+        }
+        InvoiceType::Witness => Beneficiary::WitnessVout(pay2vout(GOLDEN_WPKH_SCRIPT), None),
+        InvoiceType::WitnessTapret => {
+            let internal_key = CompressedPublicKey::from_str(GOLDEN_INTERNAL_KEY)
+                .unwrap()
+                .0
+                .x_only_public_key()
+                .0;
+            Beneficiary::WitnessVout(pay2vout(GOLDEN_TR_SCRIPT), Some(internal_key))
+        }
+    }
+}
+
+fn xchain_nets(beneficiary: Beneficiary) -> Vec<XChainNet<Beneficiary>> {
+    vec![
+        XChainNet::BitcoinMainnet(beneficiary),
+        XChainNet::BitcoinTestnet3(beneficiary),
+        XChainNet::BitcoinTestnet4(beneficiary),
+        XChainNet::BitcoinSignet(beneficiary),
+        XChainNet::BitcoinSignetCustom(beneficiary),
+        XChainNet::BitcoinRegtest(beneficiary),
+        XChainNet::LiquidMainnet(beneficiary),
+        XChainNet::LiquidTestnet(beneficiary),
+    ]
+}
+
+fn golden_invoice(
+    asset_schema: AssetSchema,
+    invoice_type: InvoiceType,
+    beneficiary: impl FnOnce(Beneficiary) -> XChainNet<Beneficiary>,
+) -> RgbInvoice {
+    let amount = match asset_schema {
+        AssetSchema::Uda => 1,
+        _ => 100,
+    };
+    build_invoice(
+        beneficiary(golden_beneficiary(invoice_type)),
+        golden_contract_id(asset_schema),
+        asset_schema.schema_id(),
+        Some(amount),
+    )
+}
+
+fn with_expiry_and_endpoints(mut invoice: RgbInvoice) -> RgbInvoice {
+    invoice.expiry = Some(GOLDEN_EXPIRY);
+    invoice.transports = GOLDEN_ENDPOINTS
+        .iter()
+        .map(|e| RgbTransport::from_str(e).unwrap())
+        .collect();
+    invoice
+}
+
+/// Invoices whose string encoding is pinned by the golden fixture
+fn golden_invoices() -> Vec<(String, RgbInvoice)> {
+    let mut invoices = vec![];
+    for asset_schema in AssetSchema::iter() {
+        for (name, invoice_type) in [
+            ("blinded", InvoiceType::Blinded(None)),
+            ("witness", InvoiceType::Witness),
+            ("tapret", InvoiceType::WitnessTapret),
+        ] {
+            invoices.push((
+                format!("{asset_schema}_{name}"),
+                golden_invoice(asset_schema, invoice_type, XChainNet::BitcoinRegtest),
+            ));
+        }
+    }
+    invoices.push((
+        s!("nia_witness_mainnet"),
+        golden_invoice(AS::Nia, InvoiceType::Witness, XChainNet::BitcoinMainnet),
+    ));
+    invoices.push((
+        s!("nia_blinded_liquid_testnet"),
+        golden_invoice(
+            AS::Nia,
+            InvoiceType::Blinded(None),
+            XChainNet::LiquidTestnet,
+        ),
+    ));
+    let mut invoice = golden_invoice(AS::Ifa, InvoiceType::Witness, XChainNet::BitcoinRegtest);
+    invoice.assignment_name = Some(fname!("inflationAllowance"));
+    invoices.push((s!("ifa_inflation_allowance"), invoice));
+    invoices.push((
+        s!("nia_blinded_expiry_endpoints"),
+        with_expiry_and_endpoints(golden_invoice(
+            AS::Nia,
+            InvoiceType::Blinded(None),
+            XChainNet::BitcoinRegtest,
+        )),
+    ));
+    invoices
+}
+
+fn check_roundtrip(invoice: &RgbInvoice) {
+    let invoice_str = invoice.to_string();
+    assert!(invoice_str.starts_with("rgb:"), "{invoice_str}");
+    let parsed = RgbInvoice::from_str(&invoice_str)
+        .unwrap_or_else(|e| panic!("cannot parse invoice {invoice_str}: {e}"));
+    assert_eq!(&parsed, invoice, "{invoice_str}");
+    assert_eq!(parsed.to_string(), invoice_str);
+}
+
+#[cfg(not(feature = "altered"))]
+#[rstest]
+fn invoice_roundtrip(
+    #[values(AS::Nia, AS::Uda, AS::Cfa, AS::Pfa, AS::Ifa)] asset_schema: AssetSchema,
+    #[values(
+        InvoiceType::Blinded(None),
+        InvoiceType::Witness,
+        InvoiceType::WitnessTapret
+    )]
+    invoice_type: InvoiceType,
+) {
+    println!("asset_schema {asset_schema:?} invoice_type {invoice_type:?}");
+
+    for beneficiary in xchain_nets(golden_beneficiary(invoice_type)) {
+        let invoice = golden_invoice(asset_schema, invoice_type, |_| beneficiary);
+        check_roundtrip(&invoice);
+        let invoice = with_expiry_and_endpoints(invoice);
+        check_roundtrip(&invoice);
+        assert_eq!(invoice.expiry, Some(GOLDEN_EXPIRY));
+        assert_eq!(invoice.transports.len(), GOLDEN_ENDPOINTS.len());
+    }
+}
+
+#[cfg(not(feature = "altered"))]
+#[rstest]
+#[case(AS::Nia, "assetOwner")]
+#[case(AS::Ifa, "assetOwner")]
+#[case(AS::Ifa, "inflationAllowance")]
+fn invoice_roundtrip_assignment_name(#[case] asset_schema: AssetSchema, #[case] name: &str) {
+    println!("asset_schema {asset_schema:?} name {name}");
+
+    let mut invoice = golden_invoice(
+        asset_schema,
+        InvoiceType::Witness,
+        XChainNet::BitcoinRegtest,
+    );
+    invoice.assignment_name = Some(FieldName::from_str(name).unwrap());
+    check_roundtrip(&invoice);
+}
+
+#[cfg(not(feature = "altered"))]
+#[rstest]
+#[case(InvoiceType::Blinded(None))]
+#[case(InvoiceType::Witness)]
+fn invoice_roundtrip_open_amount(#[case] invoice_type: InvoiceType) {
+    println!("invoice_type {invoice_type:?}");
+
+    let mut invoice = build_invoice(
+        XChainNet::BitcoinRegtest(golden_beneficiary(invoice_type)),
+        golden_contract_id(AS::Nia),
+        AS::Nia.schema_id(),
+        None,
+    );
+    check_roundtrip(&invoice);
+    invoice.expiry = Some(GOLDEN_EXPIRY);
+    check_roundtrip(&invoice);
+    check_roundtrip(&with_invoice_amount(invoice, 100));
+}
+
+#[cfg(not(feature = "altered"))]
+#[test]
+fn invoice_roundtrip_wallet() {
+    initialize();
+
+    let mut wlt_1 = BpTestWallet::with_descriptor(&DescriptorType::Wpkh);
+    let mut wlt_2 = BpTestWallet::with_descriptor(&DescriptorType::Tr);
+
+    let contract_id = wlt_1.issue_nia(600, None);
+    let schema_id = wlt_1.schema_id(contract_id);
+
+    for invoice_type in [
+        InvoiceType::Blinded(None),
+        InvoiceType::Witness,
+        InvoiceType::WitnessTapret,
+    ] {
+        let invoice = wlt_2.invoice(contract_id, schema_id, 100, invoice_type);
+        check_roundtrip(&invoice);
+
+        // the parsed invoice can be paid
+        let invoice = RgbInvoice::from_str(&invoice.to_string()).unwrap();
+        wlt_1.send_to_invoice(&mut wlt_2, invoice, Some(1000), None, None);
+    }
+    wlt_2.check_allocations(contract_id, schema_id, vec![100, 100, 100], false);
+}
+
+#[cfg(not(feature = "altered"))]
+#[test]
+fn invoice_golden_strings() {
+    let file = std::fs::File::open(GOLDEN_INVOICES_FPATH).unwrap_or_else(|_| {
+        panic!("missing {GOLDEN_INVOICES_FPATH}, generate it with the invoice_golden_generate test")
+    });
+    let golden: BTreeMap<String, String> = serde_json::from_reader(file).unwrap();
+    let invoices = golden_invoices();
+    assert_eq!(
+        golden.keys().collect::<BTreeSet<_>>(),
+        invoices
+            .iter()
+            .map(|(name, _)| name)
+            .collect::<BTreeSet<_>>()
+    );
+    for (name, invoice) in invoices {
+        let expected = &golden[&name];
+        assert_eq!(
+            &invoice.to_string(),
+            expected,
+            "invoice {name} changed encoding"
+        );
+        assert_eq!(
+            RgbInvoice::from_str(expected).unwrap(),
+            invoice,
+            "invoice {name} changed decoding"
+        );
+    }
+}
+
+// run once to generate tests/fixtures/invoices.json, only when invoice encoding is meant to change
+// cargo test --test invoices invoice_golden_generate -- --ignored
+#[cfg(not(feature = "altered"))]
+#[test]
+#[ignore = "one-shot"]
+fn invoice_golden_generate() {
+    let golden = golden_invoices()
+        .into_iter()
+        .map(|(name, invoice)| (name, invoice.to_string()))
+        .collect::<BTreeMap<_, _>>();
+    let json = serde_json::to_string_pretty(&golden).unwrap();
+    std::fs::write(GOLDEN_INVOICES_FPATH, json).unwrap();
+    println!("written invoices in: {GOLDEN_INVOICES_FPATH}");
+}
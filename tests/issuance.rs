@@ -1,5 +1,3 @@
-pub mod utils;
-
 use rstest_reuse::{self, *};
 use utils::*;
 
@@ -423,7 +421,7 @@ fn issue_on_different_layers(#[case] scenario: &str) {
                 .set_contract(contract_id)
                 .set_amount_raw(issued_amt);
             let invoice = builder.finish();
-            let (_, _, consignment) = wlt_1.pay_invoice(invoice, Some(500), Some(100));
+            let (_, _, consignment) = wlt_1.pay_invoice(invoice, None, Some(500), Some(100));
             wlt_2.accept_transfer(consignment.clone(), None);
         }
         _ => unreachable!(),
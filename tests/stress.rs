@@ -1,5 +1,3 @@
-pub mod utils;
-
 use utils::*;
 
 type TT = TransferType;
@@ -1,137 +1,137 @@
-pub mod utils;
-
 use utils::*;
 
+// one value per shard
+const _: () = assert!(TRANSFER_SHARDS == 8);
+
 #[cfg(not(feature = "altered"))]
 #[rstest]
-// blinded: nia - nia
-#[case(TT::Blinded, DT::Wpkh, DT::Wpkh, AS::Nia, AS::Nia)]
-#[case(TT::Blinded, DT::Wpkh, DT::Tr, AS::Nia, AS::Nia)]
-#[case(TT::Blinded, DT::Tr, DT::Wpkh, AS::Nia, AS::Nia)]
-#[case(TT::Blinded, DT::Tr, DT::Tr, AS::Nia, AS::Nia)]
-// blinded: nia - cfa
-#[case(TT::Blinded, DT::Wpkh, DT::Wpkh, AS::Nia, AS::Cfa)]
-#[case(TT::Blinded, DT::Wpkh, DT::Tr, AS::Nia, AS::Cfa)]
-#[case(TT::Blinded, DT::Tr, DT::Wpkh, AS::Nia, AS::Cfa)]
-#[case(TT::Blinded, DT::Tr, DT::Tr, AS::Nia, AS::Cfa)]
-// blinded: nia - uda
-#[case(TT::Blinded, DT::Wpkh, DT::Wpkh, AS::Nia, AS::Uda)]
-#[case(TT::Blinded, DT::Wpkh, DT::Tr, AS::Nia, AS::Uda)]
-#[case(TT::Blinded, DT::Tr, DT::Wpkh, AS::Nia, AS::Uda)]
-#[case(TT::Blinded, DT::Tr, DT::Tr, AS::Nia, AS::Uda)]
-// blinded: cfa - cfa
-#[case(TT::Blinded, DT::Wpkh, DT::Wpkh, AS::Cfa, AS::Cfa)]
-#[case(TT::Blinded, DT::Wpkh, DT::Tr, AS::Cfa, AS::Cfa)]
-#[case(TT::Blinded, DT::Tr, DT::Wpkh, AS::Cfa, AS::Cfa)]
-#[case(TT::Blinded, DT::Tr, DT::Tr, AS::Cfa, AS::Cfa)]
-// blinded: cfa - nia
-#[case(TT::Blinded, DT::Wpkh, DT::Wpkh, AS::Cfa, AS::Nia)]
-#[case(TT::Blinded, DT::Wpkh, DT::Tr, AS::Cfa, AS::Nia)]
-#[case(TT::Blinded, DT::Tr, DT::Wpkh, AS::Cfa, AS::Nia)]
-#[case(TT::Blinded, DT::Tr, DT::Tr, AS::Cfa, AS::Nia)]
-// blinded: cfa - uda
-#[case(TT::Blinded, DT::Wpkh, DT::Wpkh, AS::Cfa, AS::Uda)]
-#[case(TT::Blinded, DT::Wpkh, DT::Tr, AS::Cfa, AS::Uda)]
-#[case(TT::Blinded, DT::Tr, DT::Wpkh, AS::Cfa, AS::Uda)]
-#[case(TT::Blinded, DT::Tr, DT::Tr, AS::Cfa, AS::Uda)]
-// blinded: uda - uda
-#[case(TT::Blinded, DT::Wpkh, DT::Wpkh, AS::Uda, AS::Uda)]
-#[case(TT::Blinded, DT::Wpkh, DT::Tr, AS::Uda, AS::Uda)]
-#[case(TT::Blinded, DT::Tr, DT::Wpkh, AS::Uda, AS::Uda)]
-#[case(TT::Blinded, DT::Tr, DT::Tr, AS::Uda, AS::Uda)]
-// blinded: uda - nia
-#[case(TT::Blinded, DT::Wpkh, DT::Wpkh, AS::Uda, AS::Nia)]
-#[case(TT::Blinded, DT::Wpkh, DT::Tr, AS::Uda, AS::Nia)]
-#[case(TT::Blinded, DT::Tr, DT::Wpkh, AS::Uda, AS::Nia)]
-#[case(TT::Blinded, DT::Tr, DT::Tr, AS::Uda, AS::Nia)]
-// blinded: uda - cfa
-#[case(TT::Blinded, DT::Wpkh, DT::Wpkh, AS::Uda, AS::Cfa)]
-#[case(TT::Blinded, DT::Wpkh, DT::Tr, AS::Uda, AS::Cfa)]
-#[case(TT::Blinded, DT::Tr, DT::Wpkh, AS::Uda, AS::Cfa)]
-#[case(TT::Blinded, DT::Tr, DT::Tr, AS::Uda, AS::Cfa)]
-// witness: nia - nia
-#[case(TT::Witness, DT::Wpkh, DT::Wpkh, AS::Nia, AS::Nia)]
-#[case(TT::Witness, DT::Wpkh, DT::Tr, AS::Nia, AS::Nia)]
-#[case(TT::Witness, DT::Tr, DT::Wpkh, AS::Nia, AS::Nia)]
-#[case(TT::Witness, DT::Tr, DT::Tr, AS::Nia, AS::Nia)]
-// witness: nia - cfa
-#[case(TT::Witness, DT::Wpkh, DT::Wpkh, AS::Nia, AS::Cfa)]
-#[case(TT::Witness, DT::Wpkh, DT::Tr, AS::Nia, AS::Cfa)]
-#[case(TT::Witness, DT::Tr, DT::Wpkh, AS::Nia, AS::Cfa)]
-#[case(TT::Witness, DT::Tr, DT::Tr, AS::Nia, AS::Cfa)]
-// witness: nia - uda
-#[case(TT::Witness, DT::Wpkh, DT::Wpkh, AS::Nia, AS::Uda)]
-#[case(TT::Witness, DT::Wpkh, DT::Tr, AS::Nia, AS::Uda)]
-#[case(TT::Witness, DT::Tr, DT::Wpkh, AS::Nia, AS::Uda)]
-#[case(TT::Witness, DT::Tr, DT::Tr, AS::Nia, AS::Uda)]
-// witness: cfa - cfa
-#[case(TT::Witness, DT::Wpkh, DT::Wpkh, AS::Cfa, AS::Cfa)]
-#[case(TT::Witness, DT::Wpkh, DT::Tr, AS::Cfa, AS::Cfa)]
-#[case(TT::Witness, DT::Tr, DT::Wpkh, AS::Cfa, AS::Cfa)]
-#[case(TT::Witness, DT::Tr, DT::Tr, AS::Cfa, AS::Cfa)]
-// witness: cfa - nia
-#[case(TT::Witness, DT::Wpkh, DT::Wpkh, AS::Cfa, AS::Nia)]
-#[case(TT::Witness, DT::Wpkh, DT::Tr, AS::Cfa, AS::Nia)]
-#[case(TT::Witness, DT::Tr, DT::Wpkh, AS::Cfa, AS::Nia)]
-#[case(TT::Witness, DT::Tr, DT::Tr, AS::Cfa, AS::Nia)]
-// witness: cfa - uda
-#[case(TT::Witness, DT::Wpkh, DT::Wpkh, AS::Cfa, AS::Uda)]
-#[case(TT::Witness, DT::Wpkh, DT::Tr, AS::Cfa, AS::Uda)]
-#[case(TT::Witness, DT::Tr, DT::Wpkh, AS::Cfa, AS::Uda)]
-#[case(TT::Witness, DT::Tr, DT::Tr, AS::Cfa, AS::Uda)]
-// witness: uda - uda
-#[case(TT::Witness, DT::Wpkh, DT::Wpkh, AS::Uda, AS::Uda)]
-#[case(TT::Witness, DT::Wpkh, DT::Tr, AS::Uda, AS::Uda)]
-#[case(TT::Witness, DT::Tr, DT::Wpkh, AS::Uda, AS::Uda)]
-#[case(TT::Witness, DT::Tr, DT::Tr, AS::Uda, AS::Uda)]
-// witness: uda - nia
-#[case(TT::Witness, DT::Wpkh, DT::Wpkh, AS::Uda, AS::Nia)]
-#[case(TT::Witness, DT::Wpkh, DT::Tr, AS::Uda, AS::Nia)]
-#[case(TT::Witness, DT::Tr, DT::Wpkh, AS::Uda, AS::Nia)]
-#[case(TT::Witness, DT::Tr, DT::Tr, AS::Uda, AS::Nia)]
-// witness: uda - cfa
-#[case(TT::Witness, DT::Wpkh, DT::Wpkh, AS::Uda, AS::Cfa)]
-#[case(TT::Witness, DT::Wpkh, DT::Tr, AS::Uda, AS::Cfa)]
-#[case(TT::Witness, DT::Tr, DT::Wpkh, AS::Uda, AS::Cfa)]
-#[case(TT::Witness, DT::Tr, DT::Tr, AS::Uda, AS::Cfa)]
-fn transfer_loop(
-    #[case] transfer_type: TransferType,
-    #[case] wlt_1_desc: DescriptorType,
-    #[case] wlt_2_desc: DescriptorType,
-    #[case] asset_schema_1: AssetSchema,
-    #[case] asset_schema_2: AssetSchema,
-) {
-    println!(
-        "transfer_type {transfer_type:?} wlt_1_desc {wlt_1_desc:?} wlt_2_desc {wlt_2_desc:?} \
-        asset_schema_1 {asset_schema_1:?} asset_schema_2 {asset_schema_2:?}"
-    );
-
+fn transfer_loop(#[values(0, 1, 2, 3, 4, 5, 6, 7)] shard: usize) {
     initialize();
 
-    match (wlt_1_desc, wlt_2_desc) {
-        (DescriptorType::Wpkh, DescriptorType::Wpkh) => {
-            let wlt_1 = BdkTestWallet::with_descriptor(&wlt_1_desc);
-            let wlt_2 = BdkTestWallet::with_descriptor(&wlt_2_desc);
-            transfer_loop_impl(wlt_1, wlt_2, transfer_type, asset_schema_1, asset_schema_2);
-        }
-        (DescriptorType::Wpkh, DescriptorType::Tr) => {
-            let wlt_1 = BdkTestWallet::with_descriptor(&wlt_1_desc);
-            let wlt_2 = BpTestWallet::with_descriptor(&wlt_2_desc);
-            transfer_loop_impl(wlt_1, wlt_2, transfer_type, asset_schema_1, asset_schema_2);
-        }
-        (DescriptorType::Tr, DescriptorType::Wpkh) => {
-            let wlt_1 = BpTestWallet::with_descriptor(&wlt_1_desc);
-            let wlt_2 = BdkTestWallet::with_descriptor(&wlt_2_desc);
-            transfer_loop_impl(wlt_1, wlt_2, transfer_type, asset_schema_1, asset_schema_2);
-        }
-        (DescriptorType::Tr, DescriptorType::Tr) => {
-            let wlt_1 = BpTestWallet::with_descriptor(&wlt_1_desc);
-            let wlt_2 = BpTestWallet::with_descriptor(&wlt_2_desc);
-            transfer_loop_impl(wlt_1, wlt_2, transfer_type, asset_schema_1, asset_schema_2);
-        }
+    for case in transfer_shard(shard) {
+        println!("transfer case {case}");
+        run_transfer_case(case);
+    }
+}
+
+fn run_transfer_case(case: TransferCase) {
+    let TransferCase {
+        transfer_type,
+        wlt_1,
+        wlt_2,
+        asset_schema_1,
+        asset_schema_2,
+    } = case;
+    match (wlt_1.backend, wlt_2.backend) {
+        (WalletBackend::Bp, WalletBackend::Bp) => transfer_loop_impl(
+            BpTestWallet::with_descriptor(&wlt_1.descriptor_type),
+            BpTestWallet::with_descriptor(&wlt_2.descriptor_type),
+            transfer_type,
+            asset_schema_1,
+            asset_schema_2,
+        ),
+        (WalletBackend::Bp, WalletBackend::Bdk) => transfer_loop_impl(
+            BpTestWallet::with_descriptor(&wlt_1.descriptor_type),
+            BdkTestWallet::with_descriptor(&wlt_2.descriptor_type),
+            transfer_type,
+            asset_schema_1,
+            asset_schema_2,
+        ),
+        (WalletBackend::Bdk, WalletBackend::Bp) => transfer_loop_impl(
+            BdkTestWallet::with_descriptor(&wlt_1.descriptor_type),
+            BpTestWallet::with_descriptor(&wlt_2.descriptor_type),
+            transfer_type,
+            asset_schema_1,
+            asset_schema_2,
+        ),
+        (WalletBackend::Bdk, WalletBackend::Bdk) => transfer_loop_impl(
+            BdkTestWallet::with_descriptor(&wlt_1.descriptor_type),
+            BdkTestWallet::with_descriptor(&wlt_2.descriptor_type),
+            transfer_type,
+            asset_schema_1,
+            asset_schema_2,
+        ),
     }
 }
 
+#[cfg(not(feature = "altered"))]
+#[test]
+fn bdk_tapret_tweak_reopen() {
+    initialize();
+
+    let mut wlt_1 = BdkTestWallet::with_descriptor(&DescriptorType::Tr);
+    let mut wlt_2 = BdkTestWallet::with_descriptor(&DescriptorType::Tr);
+    assert!(wlt_2.is_taproot());
+
+    let sats = 3000;
+    let issued_amt = 600;
+    let utxo = wlt_1.get_utxo(Some(sats));
+    let contract_id = wlt_1.issue_nia(issued_amt, Some(&utxo));
+    let schema_id = wlt_1.schema_id(contract_id);
+
+    // tapret commitment on the beneficiary output, as there's no bitcoin change
+    let invoice = wlt_2.invoice(
+        contract_id,
+        schema_id,
+        issued_amt,
+        InvoiceType::WitnessTapret,
+    );
+    let (consignment, tx) = wlt_1.send_to_invoice(
+        &mut wlt_2,
+        invoice,
+        Some(sats - DEFAULT_FEE_ABS),
+        None,
+        None,
+    );
+    assert_eq!(tx.outputs.len(), 1);
+    assert!(wlt_1.tapret_tweaks().is_empty());
+    wlt_2.try_add_tapret_tweak(consignment, &txid_bp_to_bitcoin(tx.txid()));
+    wlt_2.sync();
+    let tweaks = wlt_2.tapret_tweaks();
+    assert_eq!(tweaks.len(), 1);
+    let balance = wlt_2.balance();
+
+    // tweaks are added back when the wallet is reopened
+    let mut wlt_2 = wlt_2.reopen();
+    assert_eq!(wlt_2.tapret_tweaks(), tweaks);
+    wlt_2.sync();
+    assert_eq!(wlt_2.balance(), balance);
+    wlt_2.check_allocations(contract_id, schema_id, vec![issued_amt], false);
+
+    // spend the tweaked output, putting the tapret commitment on the sender change
+    let amt = 200;
+    wlt_2.send(
+        &mut wlt_1,
+        TransferType::Blinded,
+        contract_id,
+        amt,
+        1000,
+        None,
+    );
+    assert!(wlt_1.tapret_tweaks().is_empty());
+    let tweaks = wlt_2.tapret_tweaks();
+    assert_eq!(tweaks.len(), 2);
+    let balance = wlt_2.balance();
+
+    let mut wlt_2 = wlt_2.reopen();
+    assert_eq!(wlt_2.tapret_tweaks(), tweaks);
+    wlt_2.sync();
+    assert_eq!(wlt_2.balance(), balance);
+    wlt_2.check_allocations(contract_id, schema_id, vec![issued_amt - amt], false);
+
+    // spend the tweaked change
+    wlt_2.send(
+        &mut wlt_1,
+        TransferType::Witness,
+        contract_id,
+        issued_amt - amt,
+        1000,
+        None,
+    );
+    wlt_1.check_allocations(contract_id, schema_id, vec![amt, issued_amt - amt], false);
+    wlt_2.check_allocations(contract_id, schema_id, vec![], false);
+}
+
 fn transfer_loop_impl<W1, D1, W2, D2>(
     mut wlt_1: TestWallet<W1, D1>,
     mut wlt_2: TestWallet<W2, D2>,
@@ -148,6 +148,7 @@ fn transfer_loop_impl<W1, D1, W2, D2>(
 {
     let issued_supply_1 = 999;
     let issued_supply_2 = 666;
+    let inflation_allowance = 333;
 
     let mut sats = 9000;
 
@@ -157,30 +158,50 @@ fn transfer_loop_impl<W1, D1, W2, D2>(
         AssetSchema::Nia => wlt_1.issue_nia(issued_supply_1, Some(&utxo)),
         AssetSchema::Uda => wlt_1.issue_uda(Some(&utxo)),
         AssetSchema::Cfa => wlt_1.issue_cfa(issued_supply_1, Some(&utxo)),
-        _ => unreachable!(),
+        AssetSchema::Pfa => wlt_1.issue_pfa_with_signer(issued_supply_1, Some(&utxo)),
+        AssetSchema::Ifa => wlt_1.issue_ifa(
+            issued_supply_1,
+            Some(&utxo),
+            vec![(utxo, inflation_allowance)],
+        ),
     };
     let contract_id_2 = match asset_schema_2 {
         AssetSchema::Nia => wlt_1.issue_nia(issued_supply_2, Some(&utxo)),
         AssetSchema::Uda => wlt_1.issue_uda(Some(&utxo)),
         AssetSchema::Cfa => wlt_1.issue_cfa(issued_supply_2, Some(&utxo)),
-        _ => unreachable!(),
+        AssetSchema::Pfa => wlt_1.issue_pfa_with_signer(issued_supply_2, Some(&utxo)),
+        AssetSchema::Ifa => wlt_1.issue_ifa(
+            issued_supply_2,
+            Some(&utxo),
+            vec![(utxo, inflation_allowance)],
+        ),
     };
     wlt_1.check_allocations(contract_id_1, asset_schema_1, vec![issued_supply_1], true);
     wlt_1.check_allocations(contract_id_2, asset_schema_2, vec![issued_supply_2], true);
 
+    // the issuer authorizes the PFA transfers of wlt_2
+    for (contract_id, asset_schema) in [
+        (contract_id_1, asset_schema_1),
+        (contract_id_2, asset_schema_2),
+    ] {
+        if asset_schema == AssetSchema::Pfa {
+            wlt_2.add_pfa_key(contract_id, wlt_1.pfa_key(contract_id).unwrap());
+        }
+    }
+
     // wlt_1 spends asset 1, automatically moving the others
     let amount_1 = if asset_schema_1 == AssetSchema::Uda {
         1
     } else {
         99
     };
-    wlt_1.send(
+    send_with_inflation_rights(
+        &mut wlt_1,
         &mut wlt_2,
         transfer_type,
         contract_id_1,
         amount_1,
         sats,
-        None,
     );
     wlt_1.check_allocations(
         contract_id_1,
@@ -194,13 +215,13 @@ fn transfer_loop_impl<W1, D1, W2, D2>(
     // wlt_1 spends asset 1 change (only if possible)
     let amount_2 = 33;
     if asset_schema_1 != AssetSchema::Uda {
-        wlt_1.send(
+        send_with_inflation_rights(
+            &mut wlt_1,
             &mut wlt_2,
             transfer_type,
             contract_id_1,
             amount_2,
             sats,
-            None,
         );
         wlt_1.check_allocations(
             contract_id_1,
@@ -223,13 +244,13 @@ fn transfer_loop_impl<W1, D1, W2, D2>(
     } else {
         22
     };
-    wlt_1.send(
+    send_with_inflation_rights(
+        &mut wlt_1,
         &mut wlt_2,
         transfer_type,
         contract_id_2,
         amount_3,
         sats,
-        None,
     );
     wlt_1.check_allocations(
         contract_id_1,
@@ -258,13 +279,13 @@ fn transfer_loop_impl<W1, D1, W2, D2>(
         111
     };
     sats -= 1000;
-    wlt_2.send(
+    send_with_inflation_rights(
+        &mut wlt_2,
         &mut wlt_1,
         transfer_type,
         contract_id_1,
         amount_4,
         sats,
-        None,
     );
     wlt_1.check_allocations(
         contract_id_1,
@@ -293,13 +314,13 @@ fn transfer_loop_impl<W1, D1, W2, D2>(
         11
     };
     sats -= 1000;
-    wlt_2.send(
+    send_with_inflation_rights(
+        &mut wlt_2,
         &mut wlt_1,
         transfer_type,
         contract_id_2,
         amount_5,
         sats,
-        None,
     );
     wlt_1.check_allocations(
         contract_id_1,
@@ -333,13 +354,13 @@ fn transfer_loop_impl<W1, D1, W2, D2>(
         issued_supply_1 - amount_1 - amount_2 + amount_4
     };
     sats -= 1000;
-    wlt_1.send(
+    send_with_inflation_rights(
+        &mut wlt_1,
         &mut wlt_2,
         transfer_type,
         contract_id_1,
         amount_6,
         sats,
-        None,
     );
     wlt_1.check_allocations(contract_id_1, asset_schema_1, vec![], false);
     wlt_1.check_allocations(
@@ -368,13 +389,13 @@ fn transfer_loop_impl<W1, D1, W2, D2>(
         issued_supply_2 - amount_3 + amount_5
     };
     sats -= 1000;
-    wlt_1.send(
+    send_with_inflation_rights(
+        &mut wlt_1,
         &mut wlt_2,
         transfer_type,
         contract_id_2,
         amount_7,
         sats,
-        None,
     );
     wlt_1.check_allocations(contract_id_1, asset_schema_1, vec![], false);
     wlt_1.check_allocations(contract_id_2, asset_schema_2, vec![], false);
@@ -390,6 +411,46 @@ fn transfer_loop_impl<W1, D1, W2, D2>(
         vec![amount_3 - amount_5, amount_7],
         true,
     );
+
+    // inflation rights followed the last transfers to wlt_2
+    for (contract_id, asset_schema) in [
+        (contract_id_1, asset_schema_1),
+        (contract_id_2, asset_schema_2),
+    ] {
+        if asset_schema == AssetSchema::Ifa {
+            assert_eq!(wlt_1.inflation_allowance(contract_id), 0);
+            assert_eq!(wlt_2.inflation_allowance(contract_id), inflation_allowance);
+        }
+    }
+}
+
+/// Send the asset, then the IFA inflation rights left to the sender, so that they follow the asset
+fn send_with_inflation_rights<W1, D1, W2, D2>(
+    wlt_1: &mut TestWallet<W1, D1>,
+    wlt_2: &mut TestWallet<W2, D2>,
+    transfer_type: TransferType,
+    contract_id: ContractId,
+    amount: u64,
+    sats: u64,
+) where
+    W1: WalletProvider,
+    W2: WalletProvider,
+    TestWallet<W1, D1>: TestWalletExt,
+    <TestWallet<W1, D1> as TestWalletExt>::Psbt: Serialize,
+    TestWallet<W2, D2>: TestWalletExt,
+    <TestWallet<W2, D2> as TestWalletExt>::Psbt: Serialize,
+{
+    wlt_1.send(wlt_2, transfer_type, contract_id, amount, sats, None);
+    let schema_id = wlt_1.schema_id(contract_id);
+    if AssetSchema::from(schema_id) != AssetSchema::Ifa {
+        return;
+    }
+    let allowance = wlt_1.inflation_allowance(contract_id);
+    if allowance > 0 {
+        let mut invoice = wlt_2.invoice(contract_id, schema_id, allowance, transfer_type);
+        invoice.assignment_name = Some(fname!("inflationAllowance"));
+        wlt_1.send_to_invoice(wlt_2, invoice, Some(sats), None, None);
+    }
 }
 
 #[cfg(not(feature = "altered"))]
@@ -413,7 +474,7 @@ fn unknown_kit(#[case] asset_schema: AssetSchema) {
         AssetSchema::Cfa => (wlt_1.issue_cfa(600, None), None),
         AssetSchema::Pfa => {
             let (secret_key, public_key) =
-                Secp256k1::new().generate_keypair(&mut rand::thread_rng());
+                with_test_rng(|rng| Secp256k1::new().generate_keypair(rng));
             let pubkey = CompressedPublicKey::from_slice(&public_key.serialize()).unwrap();
             (wlt_1.issue_pfa(600, None, pubkey), Some(secret_key))
         }
@@ -440,6 +501,88 @@ fn unknown_kit(#[case] asset_schema: AssetSchema) {
     }
 }
 
+#[cfg(not(feature = "altered"))]
+#[rstest]
+#[case(WalletBackend::Bp, DescriptorType::Wpkh)]
+#[case(WalletBackend::Bp, DescriptorType::Tr)]
+#[case(WalletBackend::Bdk, DescriptorType::Wpkh)]
+#[case(WalletBackend::Bdk, DescriptorType::Tr)]
+fn send_btc_keeps_assets(#[case] backend: WalletBackend, #[case] descriptor_type: DescriptorType) {
+    println!("backend {backend:?} descriptor_type {descriptor_type:?}");
+
+    initialize();
+
+    let wlt_2 = BpTestWallet::with_descriptor(&DescriptorType::Wpkh);
+    match backend {
+        WalletBackend::Bp => {
+            send_btc_keeps_assets_impl(BpTestWallet::with_descriptor(&descriptor_type), wlt_2)
+        }
+        WalletBackend::Bdk => {
+            send_btc_keeps_assets_impl(BdkTestWallet::with_descriptor(&descriptor_type), wlt_2)
+        }
+    }
+}
+
+fn send_btc_keeps_assets_impl<W1, D1, W2, D2>(
+    mut wlt_1: TestWallet<W1, D1>,
+    mut wlt_2: TestWallet<W2, D2>,
+) where
+    W1: WalletProvider,
+    W2: WalletProvider,
+    TestWallet<W1, D1>: TestWalletExt,
+    <TestWallet<W1, D1> as TestWalletExt>::Psbt: Serialize,
+    TestWallet<W2, D2>: TestWalletExt,
+    <TestWallet<W2, D2> as TestWalletExt>::Psbt: Serialize,
+{
+    // colored UTXOs are the biggest ones, so a naive selection would spend them first
+    let issue_supply = 600;
+    let nia_id = wlt_1.issue_nia(issue_supply, None);
+    let uda_id = wlt_1.issue_uda(None);
+    let nia_schema_id = wlt_1.schema_id(nia_id);
+    wlt_1.get_utxo(Some(30_000));
+    wlt_1.get_utxo(Some(40_000));
+    let colored = wlt_1.colored_outpoints();
+    assert_eq!(colored.len(), 2);
+
+    let address = address_bp_to_bitcoin(wlt_2.get_address());
+    let tx = wlt_1.send_btc(&address, 50_000, None, ColoredCoins::Exclude);
+    let spent = tx_bp_to_bitcoin(tx.clone())
+        .input
+        .iter()
+        .map(|txin| txin.previous_output)
+        .collect::<BTreeSet<_>>();
+    assert_eq!(spent.len(), 2);
+    assert!(spent.is_disjoint(&colored));
+    wlt_1.mine_tx(&txid_bp_to_bitcoin(tx.txid()), false);
+    wlt_1.sync();
+    assert_eq!(wlt_1.colored_outpoints(), colored);
+    wlt_1.check_allocations(nia_id, nia_schema_id, vec![issue_supply], false);
+
+    // assets are still spendable
+    wlt_1.send(
+        &mut wlt_2,
+        TransferType::Witness,
+        nia_id,
+        issue_supply,
+        1000,
+        None,
+    );
+    wlt_2.check_allocations(nia_id, nia_schema_id, vec![issue_supply], false);
+
+    // deliberately spending a colored UTXO burns its assets
+    let uda_outpoint = *wlt_1.contract_outpoints(uda_id).first().unwrap();
+    let tx = wlt_1.send_btc(
+        &address,
+        1000,
+        None,
+        ColoredCoins::Include(vec![uda_outpoint]),
+    );
+    wlt_1.mine_tx(&txid_bp_to_bitcoin(tx.txid()), false);
+    wlt_1.sync();
+    assert!(!wlt_1.colored_outpoints().contains(&uda_outpoint));
+    assert!(wlt_1.contract_outpoints(uda_id).is_empty());
+}
+
 #[cfg(not(feature = "altered"))]
 #[test]
 fn rbf_transfer() {
@@ -488,102 +631,596 @@ fn rbf_transfer() {
     );
 }
 
-#[cfg(feature = "altered")]
+#[cfg(not(feature = "altered"))]
 #[rstest]
-#[should_panic(expected = "InvalidConsignment")]
-#[case(TransferType::Blinded)]
-#[should_panic(expected = "Composition(InsufficientState)")]
-#[case(TransferType::Witness)]
-fn same_transfer_twice_no_update_witnesses(#[case] transfer_type: TransferType) {
-    println!("transfer_type {transfer_type:?}");
+#[case(DescriptorType::Wpkh, TransferType::Witness)]
+#[case(DescriptorType::Wpkh, TransferType::Blinded)]
+#[case(DescriptorType::Tr, TransferType::Witness)]
+#[case(DescriptorType::Tr, TransferType::Blinded)]
+fn rbf_bump_fee(#[case] descriptor_type: DescriptorType, #[case] transfer_type: TransferType) {
+    println!("descriptor_type {descriptor_type:?} transfer_type {transfer_type:?}");
 
     initialize();
 
-    let mut wlt_1 = BpTestWallet::with_descriptor(&DescriptorType::Wpkh);
-    let mut wlt_2 = BpTestWallet::with_descriptor(&DescriptorType::Wpkh);
+    let mut wlt_1 = BpTestWallet::with_descriptor(&descriptor_type);
+    let mut wlt_2 = BpTestWallet::with_descriptor(&descriptor_type);
 
-    let issue_supply = 2000;
+    let issue_supply = 600;
     let contract_id = wlt_1.issue_nia(issue_supply, None);
     let schema_id = wlt_1.schema_id(contract_id);
 
-    let amount = 100;
+    let amount = 400;
     let invoice = wlt_2.invoice(contract_id, schema_id, amount, transfer_type);
-    let _ = wlt_1.pay_full(invoice.clone(), None, Some(500), false, None);
-
-    let (consignment, _, _, _) = wlt_1.pay_full(invoice, None, Some(1000), true, None);
 
+    stop_mining();
+    let (consignment, tx, _, _) =
+        wlt_1.pay_full_with_fee(invoice, None, TxFee::Rate(1.0), true, None);
     wlt_2.accept_transfer(consignment, None);
 
-    // with TransferType::Blinded this shows duplicated allocations
-    wlt_2.debug_logs(contract_id, AllocationFilter::WalletAll);
+    // replace the witness TX twice, the receiver gets a new consignment each time
+    let mut txid = txid_bp_to_bitcoin(tx.txid());
+    let mut replaced = vec![];
+    for feerate in [3.0, 10.0] {
+        let (consignment, tx) = wlt_1.bump_fee(txid, feerate);
+        wlt_2.accept_transfer(consignment, None);
+        replaced.push(txid);
+        txid = txid_bp_to_bitcoin(tx.txid());
+        assert_ne!(replaced.last(), Some(&txid));
+    }
+    assert!(wlt_1.sent_transfers().contains_key(&txid));
+    assert!(
+        replaced
+            .iter()
+            .all(|txid| !wlt_1.sent_transfers().contains_key(txid))
+    );
 
-    let allocations = match transfer_type {
-        TransferType::Blinded => vec![amount, amount],
-        TransferType::Witness => vec![amount],
-    };
-    wlt_2.check_allocations(contract_id, schema_id, allocations, false);
+    wlt_1.mine_tx(&txid, true);
+    wlt_1.sync_and_update_witnesses(None);
+    wlt_2.sync_and_update_witnesses(None);
+    for txid in replaced {
+        for wlt in [&wlt_1, &wlt_2] {
+            assert_eq!(
+                wlt.stock_witness_ord(contract_id, txid),
+                Some(WitnessOrd::Archived)
+            );
+        }
+    }
+    wlt_1.check_allocations(contract_id, schema_id, vec![issue_supply - amount], false);
+    wlt_2.check_allocations(contract_id, schema_id, vec![amount], false);
 
-    // with TransferType::Blinded the receiver will detect a double spend, to avoid this the
-    // sendert should call update_witnesses when retrying the same transfer twice
+    // the allocation from the replacement is spendable
     wlt_2.send(
         &mut wlt_1,
         TransferType::Blinded,
         contract_id,
-        amount * 2,
+        amount,
         1000,
         None,
     );
-
-    if transfer_type == TransferType::Blinded {
-        unreachable!("should have panicked at previous send");
-    }
-
-    // with TransferType::Blinded this shows 1900+200 as owned, but we issued 2000
-    wlt_1.debug_logs(contract_id, AllocationFilter::WalletAll);
-
-    let mut wlt_3 = BpTestWallet::with_descriptor(&DescriptorType::Wpkh);
-
-    // with TransferType::Blinded this works but should fail
-    wlt_1.send(
-        &mut wlt_3,
-        TransferType::Blinded,
+    wlt_1.check_allocations(
         contract_id,
-        issue_supply + amount,
-        1000,
-        None,
+        schema_id,
+        vec![issue_supply - amount, amount],
+        false,
     );
-    // with TransferType::Blinded this shows 2100 as owned, but we issued 2000
-    wlt_3.debug_logs(contract_id, AllocationFilter::WalletAll);
 }
 
 #[cfg(not(feature = "altered"))]
 #[rstest]
-#[case(TransferType::Blinded)]
-#[case(TransferType::Witness)]
-fn same_transfer_twice_update_witnesses(#[case] transfer_type: TransferType) {
-    println!("transfer_type {transfer_type:?}");
+#[case(DescriptorType::Wpkh, false)]
+#[case(DescriptorType::Wpkh, true)]
+#[case(DescriptorType::Tr, false)]
+#[case(DescriptorType::Tr, true)]
+fn cpfp_transfer(#[case] descriptor_type: DescriptorType, #[case] from_receiver: bool) {
+    println!("descriptor_type {descriptor_type:?} from_receiver {from_receiver}");
 
     initialize();
 
-    let mut wlt_1 = BpTestWallet::with_descriptor(&DescriptorType::Wpkh);
-    let mut wlt_2 = BpTestWallet::with_descriptor(&DescriptorType::Wpkh);
+    let mut wlt_1 = BpTestWallet::with_descriptor(&descriptor_type);
+    let mut wlt_2 = BpTestWallet::with_descriptor(&descriptor_type);
+    let mut wlt_3 = BpTestWallet::with_descriptor(&descriptor_type);
 
-    let issue_supply = 2000;
-    let contract_id = wlt_1.issue_nia(issue_supply, None);
+    // a second contract on the same UTXO ends up on the change of the transfer
+    let issue_supply = 600;
+    let utxo = wlt_1.get_utxo(None);
+    let contract_id = wlt_1.issue_nia(issue_supply, Some(&utxo));
+    let other_id = wlt_1.issue_cfa(issue_supply, Some(&utxo));
     let schema_id = wlt_1.schema_id(contract_id);
+    let other_schema_id = wlt_1.schema_id(other_id);
+    // uncolored coins for the receiver to pay the child fee
+    wlt_2.get_utxo(None);
 
-    let amount = 100;
-    let invoice = wlt_2.invoice(contract_id, schema_id, amount, transfer_type);
-    let _ = wlt_1.pay_full(invoice.clone(), None, Some(500), false, None);
-
-    wlt_1.sync_and_update_witnesses(None);
-
-    // with TransferType::Blinded this fails with an AbsentValidWitness error
-    let (consignment, tx, _, _) = wlt_1.pay_full(invoice, None, Some(1000), true, None);
+    let amount = 400;
+    let invoice = wlt_2.invoice(contract_id, schema_id, amount, InvoiceType::Witness);
 
-    wlt_1.mine_tx(&txid_bp_to_bitcoin(tx.txid()), false);
+    stop_mining();
+    let (consignment, tx, _, _) =
+        wlt_1.pay_full_with_fee(invoice, None, TxFee::Rate(1.0), true, None);
     wlt_2.accept_transfer(consignment, None);
-    wlt_1.sync();
+    let txid = txid_bp_to_bitcoin(tx.txid());
+    let parent = wlt_1.sent_transfers()[&txid].clone();
+
+    // accelerate the transfer spending the witness output or the change
+    let package_feerate = 20.0;
+    let wlt = if from_receiver {
+        &mut wlt_2
+    } else {
+        &mut wlt_1
+    };
+    wlt.sync();
+    let outpoint = wlt
+        .list_unspent_outpoints()
+        .into_iter()
+        .find(|o| o.txid == txid)
+        .unwrap();
+    let prevouts = wlt.list_unspents();
+    let child = wlt.cpfp(outpoint, &parent.tx, parent.fee, package_feerate);
+    let child_txid = txid_bp_to_bitcoin(child.txid());
+    let vsize = |tx: &Tx| tx_bp_to_bitcoin(tx.clone()).vsize() as f64;
+    let realized =
+        (parent.fee + tx_fee(&child, &prevouts)) as f64 / (vsize(&parent.tx) + vsize(&child));
+    println!("package feerate {realized}");
+    assert!(realized >= package_feerate);
+
+    // parent and child get mined together
+    wlt.mine_tx(&child_txid, true);
+    assert_eq!(wlt.get_tx_height(&txid), wlt.get_tx_height(&child_txid));
+    wlt_1.sync_and_update_witnesses(None);
+    wlt_2.sync_and_update_witnesses(None);
+
+    // all the state spent by the child has been moved to its output
+    if from_receiver {
+        assert!(
+            wlt_2
+                .contract_outpoints(contract_id)
+                .iter()
+                .all(|o| o.txid == child_txid)
+        );
+    } else {
+        for id in [contract_id, other_id] {
+            assert!(
+                wlt_1
+                    .contract_outpoints(id)
+                    .iter()
+                    .all(|o| o.txid == child_txid)
+            );
+        }
+    }
+    wlt_1.check_allocations(contract_id, schema_id, vec![issue_supply - amount], false);
+    wlt_1.check_allocations(other_id, other_schema_id, vec![issue_supply], false);
+    wlt_2.check_allocations(contract_id, schema_id, vec![amount], false);
+
+    // nothing has been burned and the history through the child validates
+    wlt_2.send(
+        &mut wlt_3,
+        TransferType::Witness,
+        contract_id,
+        amount,
+        1000,
+        None,
+    );
+    wlt_1.send(
+        &mut wlt_3,
+        TransferType::Witness,
+        contract_id,
+        issue_supply - amount,
+        1000,
+        None,
+    );
+    wlt_1.send(
+        &mut wlt_3,
+        TransferType::Blinded,
+        other_id,
+        issue_supply,
+        1000,
+        None,
+    );
+    wlt_3.check_allocations(
+        contract_id,
+        schema_id,
+        vec![amount, issue_supply - amount],
+        false,
+    );
+    wlt_3.check_allocations(other_id, other_schema_id, vec![issue_supply], false);
+}
+
+#[cfg(not(feature = "altered"))]
+#[rstest]
+#[case(DescriptorType::Wpkh)]
+#[case(DescriptorType::Tr)]
+fn consolidate_allocations(#[case] descriptor_type: DescriptorType) {
+    println!("descriptor_type {descriptor_type:?}");
+
+    initialize();
+
+    let mut wlt_1 = BpTestWallet::with_descriptor(&descriptor_type);
+    let mut wlt_2 = BpTestWallet::with_descriptor(&descriptor_type);
+
+    let issue_supply = 1000;
+    let nia_id = wlt_1.issue_nia(issue_supply, None);
+    let cfa_id = wlt_1.issue_cfa(issue_supply, None);
+    let nia_schema_id = wlt_1.schema_id(nia_id);
+    let cfa_schema_id = wlt_1.schema_id(cfa_id);
+    let consignment_size = |consignment: &Transfer| {
+        let mut buff = vec![];
+        consignment.save(&mut buff).unwrap();
+        buff.len()
+    };
+
+    // fragment the balances of the receiver
+    let fragments = 5;
+    let amt = 20;
+    let mut nia_txids = vec![];
+    let mut fragment_sizes = 0;
+    for i in 0..fragments {
+        let transfer_type = if i % 2 == 0 {
+            TransferType::Witness
+        } else {
+            TransferType::Blinded
+        };
+        let (consignment, tx) = wlt_1.send(&mut wlt_2, transfer_type, nia_id, amt, 1000, None);
+        fragment_sizes += consignment_size(&consignment);
+        nia_txids.push(tx.txid());
+        wlt_1.send(&mut wlt_2, transfer_type, cfa_id, amt, 1000, None);
+    }
+    wlt_2.check_allocations(nia_id, nia_schema_id, vec![amt; fragments], false);
+    wlt_2.check_allocations(cfa_id, cfa_schema_id, vec![amt; fragments], false);
+
+    // merge only some of the allocations of a single contract
+    let tx = wlt_2.consolidate(&[nia_id], 2);
+    wlt_2.mine_tx(&txid_bp_to_bitcoin(tx.txid()), false);
+    wlt_2.sync_and_update_witnesses(None);
+    wlt_2.check_allocations(nia_id, nia_schema_id, vec![2 * amt, amt, amt, amt], false);
+    wlt_2.check_allocations(cfa_id, cfa_schema_id, vec![amt; fragments], false);
+
+    // merge everything left of both contracts into a single output
+    let tx = wlt_2.consolidate(&[nia_id, cfa_id], 16);
+    let txid = txid_bp_to_bitcoin(tx.txid());
+    wlt_2.mine_tx(&txid, false);
+    wlt_2.sync_and_update_witnesses(None);
+    let total = fragments as u64 * amt;
+    wlt_2.check_allocations(nia_id, nia_schema_id, vec![total], false);
+    wlt_2.check_allocations(cfa_id, cfa_schema_id, vec![total], false);
+    let outpoints = wlt_2.contract_outpoints(nia_id);
+    assert_eq!(outpoints.len(), 1);
+    assert_eq!(outpoints, wlt_2.contract_outpoints(cfa_id));
+    assert_eq!(outpoints.first().unwrap().txid, txid);
+
+    // history keeps the received fragments, while the consolidation pays nobody so it's not
+    // listed as an operation
+    for fragment_txid in &nia_txids {
+        wlt_2.check_history_operation(&nia_id, Some(fragment_txid), OpDirection::Received, amt);
+    }
+    for contract_id in [nia_id, cfa_id] {
+        assert!(
+            wlt_2
+                .history(contract_id)
+                .iter()
+                .all(|op| op.witness.is_none_or(|w| w.id != txid))
+        );
+    }
+
+    // spending the merged allocation needs a single input and doesn't repeat shared history
+    let (consignment, tx) =
+        wlt_2.send(&mut wlt_1, TransferType::Witness, nia_id, total, 1000, None);
+    let size = consignment_size(&consignment);
+    println!("consignment size {size}, fragment consignments {fragment_sizes}");
+    assert!(size < fragment_sizes);
+    let spend_txid = txid_bp_to_bitcoin(tx.txid());
+    let wbundle = consignment
+        .bundles
+        .iter()
+        .find(|wb| wb.witness_id() == spend_txid)
+        .unwrap();
+    assert!(
+        wbundle
+            .bundle
+            .known_transitions
+            .iter()
+            .all(|kt| kt.transition.inputs().len() == 1)
+    );
+    wlt_1.check_allocations(
+        nia_id,
+        nia_schema_id,
+        vec![issue_supply - total, total],
+        false,
+    );
+}
+
+#[cfg(not(feature = "altered"))]
+#[rstest]
+#[case(DescriptorType::Wpkh)]
+#[case(DescriptorType::Tr)]
+fn pay_many_invoices(#[case] descriptor_type: DescriptorType) {
+    println!("descriptor_type {descriptor_type:?}");
+
+    initialize();
+
+    let mut wlt_1 = BpTestWallet::with_descriptor(&descriptor_type);
+    let mut wlt_2 = BpTestWallet::with_descriptor(&DescriptorType::Wpkh);
+    let mut wlt_3 = BpTestWallet::with_descriptor(&DescriptorType::Tr);
+    let mut wlt_4 = BdkTestWallet::with_descriptor(&DescriptorType::Wpkh);
+
+    let issue_supply = 1000;
+    let nia_id = wlt_1.issue_nia(issue_supply, None);
+    let cfa_id = wlt_1.issue_cfa(issue_supply, None);
+    let nia_schema_id = wlt_1.schema_id(nia_id);
+    let cfa_schema_id = wlt_1.schema_id(cfa_id);
+
+    // each recipient gets invoices of both kinds, wlt_4 has two for the same contract
+    let invoices = vec![
+        wlt_2.invoice(nia_id, nia_schema_id, 100, InvoiceType::Blinded(None)),
+        wlt_2.invoice(cfa_id, cfa_schema_id, 200, InvoiceType::Witness),
+        wlt_3.invoice(nia_id, nia_schema_id, 150, InvoiceType::WitnessTapret),
+        wlt_3.invoice(cfa_id, cfa_schema_id, 50, InvoiceType::Blinded(None)),
+        wlt_4.invoice(nia_id, nia_schema_id, 30, InvoiceType::Witness),
+        wlt_4.invoice(nia_id, nia_schema_id, 20, InvoiceType::Blinded(None)),
+        wlt_4.invoice(cfa_id, cfa_schema_id, 40, InvoiceType::Witness),
+    ];
+    let others_blinded = invoices[..4]
+        .iter()
+        .filter_map(|i| match i.beneficiary.into_inner() {
+            Beneficiary::BlindedSeal(secret_seal) => Some(secret_seal),
+            Beneficiary::WitnessVout(..) => None,
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(others_blinded.len(), 2);
+    let (consignments, tx) = wlt_1.pay_many(invoices, None, None);
+    assert_eq!(consignments.len(), 7);
+    let txid = txid_bp_to_bitcoin(tx.txid());
+    for consignment in &consignments {
+        assert!(consignment.bundles.iter().any(|wb| wb.witness_id() == txid));
+    }
+    wlt_1.mine_tx(&txid, false);
+
+    // wlt_4 gets the seals blinded by the other recipients only in concealed form
+    let wlt_4_seals = |reveal: bool| -> HashSet<SecretSeal> {
+        consignments[4..]
+            .iter()
+            .flat_map(|c| c.bundles.iter())
+            .flat_map(|wb| wb.bundle.known_transitions.iter())
+            .flat_map(|kt| kt.transition.assignments.values())
+            .flat_map(|assigns| {
+                (0..assigns.len_u16()).filter_map(move |i| match reveal {
+                    true => assigns
+                        .revealed_seal_at(i)
+                        .unwrap()
+                        .map(|seal| seal.to_secret_seal()),
+                    false => Some(assigns.confidential_seal_at(i).unwrap()),
+                })
+            })
+            .collect()
+    };
+    let (wlt_4_revealed, wlt_4_all) = (wlt_4_seals(true), wlt_4_seals(false));
+    for secret_seal in others_blinded {
+        assert!(wlt_4_all.contains(&secret_seal));
+        assert!(!wlt_4_revealed.contains(&secret_seal));
+    }
+
+    // every recipient validates only its own consignments
+    let mut consignments = consignments.into_iter();
+    for _ in 0..2 {
+        wlt_2.accept_transfer(consignments.next().unwrap(), None);
+    }
+    for _ in 0..2 {
+        wlt_3.accept_transfer(consignments.next().unwrap(), None);
+    }
+    for _ in 0..3 {
+        wlt_4.accept_transfer(consignments.next().unwrap(), None);
+    }
+    wlt_1.sync();
+
+    wlt_1.check_allocations(nia_id, nia_schema_id, vec![700], false);
+    wlt_1.check_allocations(cfa_id, cfa_schema_id, vec![710], false);
+    wlt_2.check_allocations(nia_id, nia_schema_id, vec![100], false);
+    wlt_2.check_allocations(cfa_id, cfa_schema_id, vec![200], false);
+    wlt_3.check_allocations(nia_id, nia_schema_id, vec![150], false);
+    wlt_3.check_allocations(cfa_id, cfa_schema_id, vec![50], false);
+    wlt_4.check_allocations(nia_id, nia_schema_id, vec![30, 20], false);
+    wlt_4.check_allocations(cfa_id, cfa_schema_id, vec![40], false);
+
+    // received allocations can be spent
+    wlt_2.send(&mut wlt_1, TransferType::Blinded, cfa_id, 200, 1000, None);
+    wlt_3.send(&mut wlt_1, TransferType::Witness, nia_id, 150, 1000, None);
+    wlt_4.send(&mut wlt_1, TransferType::Witness, nia_id, 50, 1000, None);
+    wlt_1.check_allocations(nia_id, nia_schema_id, vec![700, 150, 50], false);
+    wlt_1.check_allocations(cfa_id, cfa_schema_id, vec![710, 200], false);
+}
+
+#[cfg(not(feature = "altered"))]
+#[rstest]
+// opret
+#[case(DescriptorType::Wpkh, TransferType::Witness, 1)]
+#[case(DescriptorType::Wpkh, TransferType::Witness, 3)]
+#[case(DescriptorType::Wpkh, TransferType::Blinded, 3)]
+// tapret
+#[case(DescriptorType::Tr, TransferType::Witness, 1)]
+#[case(DescriptorType::Tr, TransferType::Witness, 3)]
+#[case(DescriptorType::Tr, TransferType::Blinded, 3)]
+fn feerate_transfer(
+    #[case] descriptor_type: DescriptorType,
+    #[case] transfer_type: TransferType,
+    #[case] contracts: usize,
+) {
+    println!(
+        "descriptor_type {descriptor_type:?} transfer_type {transfer_type:?} contracts {contracts}"
+    );
+
+    initialize();
+
+    let mut wlt_1 = BpTestWallet::with_descriptor(&descriptor_type);
+    let mut wlt_2 = BpTestWallet::with_descriptor(&descriptor_type);
+
+    // all contracts on the same UTXO, so they all get moved by each transfer
+    let issue_supply = 600;
+    let utxo = wlt_1.get_utxo(None);
+    let contract_ids = (0..contracts)
+        .map(|_| wlt_1.issue_nia(issue_supply, Some(&utxo)))
+        .collect::<Vec<_>>();
+    let contract_id = contract_ids[0];
+    let schema_id = wlt_1.schema_id(contract_id);
+
+    let amount = 100;
+    for feerate in [1.0, 2.5, 10.0, 42.0] {
+        let invoice = wlt_2.invoice(contract_id, schema_id, amount, transfer_type);
+        let prevouts = wlt_1.list_unspents();
+        let (consignment, tx, _, _) =
+            wlt_1.pay_full_with_fee(invoice, None, TxFee::Rate(feerate), true, None);
+        let realized = tx_feerate(&tx, &prevouts);
+        println!("feerate {feerate} realized {realized}");
+        assert!(realized >= feerate);
+        // estimation is tight, rounding aside
+        assert!(realized <= feerate * 1.02 + 0.02);
+        wlt_1.mine_tx(&txid_bp_to_bitcoin(tx.txid()), false);
+        wlt_2.accept_transfer(consignment, None);
+        wlt_1.sync();
+    }
+
+    wlt_1.check_allocations(
+        contract_id,
+        schema_id,
+        vec![issue_supply - 4 * amount],
+        false,
+    );
+    for other_id in &contract_ids[1..] {
+        wlt_1.check_allocations(*other_id, schema_id, vec![issue_supply], false);
+    }
+}
+
+#[cfg(not(feature = "altered"))]
+#[rstest]
+#[case(DescriptorType::Wpkh)]
+#[case(DescriptorType::WshMulti)]
+fn feerate_transfer_extra_inputs(#[case] descriptor_type: DescriptorType) {
+    println!("descriptor_type {descriptor_type:?}");
+
+    initialize();
+
+    let mut wlt_1 = BdkTestWallet::with_descriptor(&descriptor_type);
+    let mut wlt_2 = BdkTestWallet::with_descriptor(&descriptor_type);
+
+    // the colored UTXO can't pay for the beneficiary sats and the fee alone
+    let issue_supply = 600;
+    let utxo = wlt_1.get_utxo(Some(3000));
+    let contract_id = wlt_1.issue_nia(issue_supply, Some(&utxo));
+    let schema_id = wlt_1.schema_id(contract_id);
+    wlt_1.get_utxo(None);
+
+    let amount = 100;
+    let feerate = 10.0;
+    let invoice = wlt_2.invoice(contract_id, schema_id, amount, TransferType::Witness);
+    let prevouts = wlt_1.list_unspents();
+    let (consignment, tx, _, _) =
+        wlt_1.pay_full_with_fee(invoice, Some(2000), TxFee::Rate(feerate), true, None);
+    assert_eq!(tx_bp_to_bitcoin(tx.clone()).input.len(), 2);
+    let realized = tx_feerate(&tx, &prevouts);
+    println!("feerate {feerate} realized {realized}");
+    assert!(realized >= feerate);
+    assert!(realized <= feerate * 1.02 + 0.02);
+    wlt_1.mine_tx(&txid_bp_to_bitcoin(tx.txid()), false);
+    wlt_2.accept_transfer(consignment, None);
+    wlt_1.sync();
+
+    wlt_1.check_allocations(contract_id, schema_id, vec![issue_supply - amount], false);
+    wlt_2.check_allocations(contract_id, schema_id, vec![amount], false);
+}
+
+#[cfg(feature = "altered")]
+#[rstest]
+#[should_panic(expected = "InvalidConsignment")]
+#[case(TransferType::Blinded)]
+#[should_panic(expected = "Composition(InsufficientState)")]
+#[case(TransferType::Witness)]
+fn same_transfer_twice_no_update_witnesses(#[case] transfer_type: TransferType) {
+    println!("transfer_type {transfer_type:?}");
+
+    initialize();
+
+    let mut wlt_1 = BpTestWallet::with_descriptor(&DescriptorType::Wpkh);
+    let mut wlt_2 = BpTestWallet::with_descriptor(&DescriptorType::Wpkh);
+
+    let issue_supply = 2000;
+    let contract_id = wlt_1.issue_nia(issue_supply, None);
+    let schema_id = wlt_1.schema_id(contract_id);
+
+    let amount = 100;
+    let invoice = wlt_2.invoice(contract_id, schema_id, amount, transfer_type);
+    let _ = wlt_1.pay_full(invoice.clone(), None, Some(500), false, None);
+
+    let (consignment, _, _, _) = wlt_1.pay_full(invoice, None, Some(1000), true, None);
+
+    wlt_2.accept_transfer(consignment, None);
+
+    // with TransferType::Blinded this shows duplicated allocations
+    wlt_2.debug_logs(contract_id, AllocationFilter::WalletAll);
+
+    let allocations = match transfer_type {
+        TransferType::Blinded => vec![amount, amount],
+        TransferType::Witness => vec![amount],
+    };
+    wlt_2.check_allocations(contract_id, schema_id, allocations, false);
+
+    // with TransferType::Blinded the receiver will detect a double spend, to avoid this the
+    // sendert should call update_witnesses when retrying the same transfer twice
+    wlt_2.send(
+        &mut wlt_1,
+        TransferType::Blinded,
+        contract_id,
+        amount * 2,
+        1000,
+        None,
+    );
+
+    if transfer_type == TransferType::Blinded {
+        unreachable!("should have panicked at previous send");
+    }
+
+    // with TransferType::Blinded this shows 1900+200 as owned, but we issued 2000
+    wlt_1.debug_logs(contract_id, AllocationFilter::WalletAll);
+
+    let mut wlt_3 = BpTestWallet::with_descriptor(&DescriptorType::Wpkh);
+
+    // with TransferType::Blinded this works but should fail
+    wlt_1.send(
+        &mut wlt_3,
+        TransferType::Blinded,
+        contract_id,
+        issue_supply + amount,
+        1000,
+        None,
+    );
+    // with TransferType::Blinded this shows 2100 as owned, but we issued 2000
+    wlt_3.debug_logs(contract_id, AllocationFilter::WalletAll);
+}
+
+#[cfg(not(feature = "altered"))]
+#[rstest]
+#[case(TransferType::Blinded)]
+#[case(TransferType::Witness)]
+fn same_transfer_twice_update_witnesses(#[case] transfer_type: TransferType) {
+    println!("transfer_type {transfer_type:?}");
+
+    initialize();
+
+    let mut wlt_1 = BpTestWallet::with_descriptor(&DescriptorType::Wpkh);
+    let mut wlt_2 = BpTestWallet::with_descriptor(&DescriptorType::Wpkh);
+
+    let issue_supply = 2000;
+    let contract_id = wlt_1.issue_nia(issue_supply, None);
+    let schema_id = wlt_1.schema_id(contract_id);
+
+    let amount = 100;
+    let invoice = wlt_2.invoice(contract_id, schema_id, amount, transfer_type);
+    let _ = wlt_1.pay_full(invoice.clone(), None, Some(500), false, None);
+
+    wlt_1.sync_and_update_witnesses(None);
+
+    // with TransferType::Blinded this fails with an AbsentValidWitness error
+    let (consignment, tx, _, _) = wlt_1.pay_full(invoice, None, Some(1000), true, None);
+
+    wlt_1.mine_tx(&txid_bp_to_bitcoin(tx.txid()), false);
+    wlt_2.accept_transfer(consignment, None);
+    wlt_1.sync();
 
     wlt_1.check_allocations(contract_id, schema_id, vec![issue_supply - amount], false);
     wlt_2.check_allocations(contract_id, schema_id, vec![amount], false);
@@ -626,6 +1263,95 @@ fn invoice_reuse(#[case] transfer_type: TransferType) {
     assert_eq!(consignment.bundles.len(), 1);
 }
 
+#[cfg(not(feature = "altered"))]
+#[rstest]
+#[case(false)]
+#[should_panic(expected = "Composition(InvoiceExpired)")]
+#[case(true)]
+fn invoice_expiry(#[case] expired: bool) {
+    println!("expired {expired}");
+
+    initialize();
+
+    let mut wlt_1 = BpTestWallet::with_descriptor(&DescriptorType::Wpkh);
+    let mut wlt_2 = BpTestWallet::with_descriptor(&DescriptorType::Wpkh);
+
+    let issue_supply = 600;
+    let contract_id = wlt_1.issue_nia(issue_supply, None);
+    let schema_id = wlt_1.schema_id(contract_id);
+
+    let amount = 100;
+    let expiry = match expired {
+        true => Utc::now().timestamp() - 3600,
+        false => Utc::now().timestamp() + 3600,
+    };
+    let invoice = wlt_2.invoice_with(
+        contract_id,
+        schema_id,
+        Some(amount),
+        InvoiceType::Witness,
+        Some(expiry),
+    );
+    assert_eq!(invoice.expiry, Some(expiry));
+    let invoice = RgbInvoice::from_str(&invoice.to_string()).unwrap();
+    assert_eq!(invoice.expiry, Some(expiry));
+
+    wlt_1.send_to_invoice(&mut wlt_2, invoice, Some(1000), None, None);
+
+    wlt_1.check_allocations(contract_id, schema_id, vec![issue_supply - amount], false);
+    wlt_2.check_allocations(contract_id, schema_id, vec![amount], false);
+}
+
+#[cfg(not(feature = "altered"))]
+#[rstest]
+#[case(TransferType::Blinded)]
+#[case(TransferType::Witness)]
+fn open_amount_invoice(#[case] transfer_type: TransferType) {
+    println!("transfer_type {transfer_type:?}");
+
+    initialize();
+
+    let mut wlt_1 = BpTestWallet::with_descriptor(&DescriptorType::Wpkh);
+    let mut wlt_2 = BpTestWallet::with_descriptor(&DescriptorType::Wpkh);
+
+    let issue_supply = 600;
+    let contract_id = wlt_1.issue_nia(issue_supply, None);
+    let schema_id = wlt_1.schema_id(contract_id);
+
+    let invoice = wlt_2.invoice_with(contract_id, schema_id, None, transfer_type, None);
+    assert!(invoice.assignment_state.is_none());
+    let invoice = RgbInvoice::from_str(&invoice.to_string()).unwrap();
+    assert!(invoice.assignment_state.is_none());
+
+    // the sender chooses the amount
+    let amount = 150;
+    let (mut psbt, _, consignment) = wlt_1.pay_invoice(invoice, Some(amount), Some(1000), None);
+    let tx = wlt_1.sign_finalize_extract(&mut psbt);
+    wlt_1.broadcast_tx(&tx);
+    wlt_1.mine_tx(&txid_bp_to_bitcoin(tx.txid()), false);
+    wlt_2.accept_transfer(consignment, None);
+    wlt_1.sync();
+
+    wlt_1.check_allocations(contract_id, schema_id, vec![issue_supply - amount], false);
+    wlt_2.check_allocations(contract_id, schema_id, vec![amount], false);
+}
+
+#[cfg(not(feature = "altered"))]
+#[test]
+#[should_panic(expected = "Composition(NoAssignmentState)")]
+fn open_amount_invoice_unset() {
+    initialize();
+
+    let mut wlt_1 = BpTestWallet::with_descriptor(&DescriptorType::Wpkh);
+    let mut wlt_2 = BpTestWallet::with_descriptor(&DescriptorType::Wpkh);
+
+    let contract_id = wlt_1.issue_nia(600, None);
+    let schema_id = wlt_1.schema_id(contract_id);
+
+    let invoice = wlt_2.invoice_with(contract_id, schema_id, None, InvoiceType::Witness, None);
+    wlt_1.send_to_invoice(&mut wlt_2, invoice, Some(1000), None, None);
+}
+
 #[cfg(not(feature = "altered"))]
 #[test]
 fn accept_0conf() {
@@ -671,6 +1397,151 @@ fn accept_0conf() {
     wlt_1.check_allocations(contract_id, schema_id, vec![wlt_1_change_amt], false);
 }
 
+#[cfg(not(feature = "altered"))]
+#[rstest]
+#[case(IndexerFault::Delay(Duration::from_secs(2)))]
+#[case(IndexerFault::Drop)]
+#[case(IndexerFault::WrongChain)]
+#[case(IndexerFault::WrongTip(5))]
+#[case(IndexerFault::MismatchedTx)]
+#[case(IndexerFault::Unconfirm)]
+fn accept_transfer_indexer_faults(#[case] fault: IndexerFault) {
+    println!("fault {fault:?}");
+
+    initialize();
+
+    let mut wlt_1 = BpTestWallet::with_descriptor(&DescriptorType::Wpkh);
+    let mut wlt_2 = BpTestWallet::with_descriptor(&DescriptorType::Wpkh);
+
+    let contract_id = wlt_1.issue_nia(600, None);
+    let schema_id = wlt_1.schema_id(contract_id);
+
+    let amt = 200;
+    let invoice = wlt_2.invoice(contract_id, schema_id, amt, InvoiceType::Witness);
+    let (consignment, tx, _, _) = wlt_1.pay_full(invoice, None, None, true, None);
+    let txid = txid_bp_to_bitcoin(tx.txid());
+    wlt_1.mine_tx(&txid, false);
+
+    let proxy = IndexerProxy::for_instance(INSTANCE_1);
+    wlt_2.use_indexer_proxy(Some(&proxy));
+    let resolver = wlt_2.get_resolver();
+    proxy.set_faults(vec![fault.clone()]);
+    let res = wlt_2.try_accept_transfer_custom(consignment.clone(), None, &resolver);
+    assert!(proxy.requests() > 0);
+    match fault {
+        IndexerFault::Delay(_) | IndexerFault::WrongTip(_) => {
+            assert_eq!(res.unwrap().validity(), Validity::Valid);
+            assert!(matches!(
+                wlt_2.stock_witness_ord(contract_id, txid),
+                Some(WitnessOrd::Mined(_))
+            ));
+        }
+        IndexerFault::Unconfirm => {
+            // a mined witness reported as unconfirmed is still acceptable, as tentative
+            assert_eq!(res.unwrap().validity(), Validity::Valid);
+            assert_eq!(
+                wlt_2.stock_witness_ord(contract_id, txid),
+                Some(WitnessOrd::Tentative)
+            );
+        }
+        IndexerFault::Drop => assert!(matches!(
+            res,
+            Err(ValidationError::ResolverError(
+                WitnessResolverError::ResolverIssue(..)
+            ))
+        )),
+        IndexerFault::WrongChain => assert!(matches!(
+            res,
+            Err(ValidationError::ResolverError(
+                WitnessResolverError::WrongChainNet
+            ))
+        )),
+        IndexerFault::MismatchedTx => assert!(matches!(
+            res,
+            Err(ValidationError::ResolverError(
+                WitnessResolverError::IdMismatch { .. }
+            ))
+        )),
+    }
+
+    // once the indexer behaves the transfer gets accepted with the right witness status
+    proxy.clear_faults();
+    wlt_2.accept_transfer(consignment, None);
+    wlt_2.update_witnesses(1, vec![txid]);
+    assert!(matches!(
+        wlt_2.stock_witness_ord(contract_id, txid),
+        Some(WitnessOrd::Mined(_))
+    ));
+    wlt_2.check_allocations(contract_id, schema_id, vec![amt], false);
+}
+
+#[cfg(not(feature = "altered"))]
+#[test]
+fn update_witnesses_indexer_faults() {
+    initialize();
+
+    let mut wlt_1 = BpTestWallet::with_descriptor(&DescriptorType::Wpkh);
+    let mut wlt_2 = BpTestWallet::with_descriptor(&DescriptorType::Wpkh);
+
+    let issued_supply = 600;
+    let contract_id = wlt_1.issue_nia(issued_supply, None);
+    let schema_id = wlt_1.schema_id(contract_id);
+
+    let amt = 200;
+    let (_, tx) = wlt_1.send(
+        &mut wlt_2,
+        InvoiceType::Witness,
+        contract_id,
+        amt,
+        1000,
+        None,
+    );
+    let txid = txid_bp_to_bitcoin(tx.txid());
+    wlt_1.update_witnesses(1, vec![txid]);
+    assert!(matches!(
+        wlt_1.stock_witness_ord(contract_id, txid),
+        Some(WitnessOrd::Mined(_))
+    ));
+
+    let proxy = IndexerProxy::for_instance(INSTANCE_1);
+    wlt_1.use_indexer_proxy(Some(&proxy));
+
+    // an unreachable indexer makes the update fail, leaving the stock untouched
+    proxy.set_faults(vec![IndexerFault::Drop]);
+    let res = wlt_1.try_update_witnesses(1, vec![txid]).unwrap();
+    assert!(res.failed.contains_key(&txid));
+    assert!(matches!(
+        wlt_1.stock_witness_ord(contract_id, txid),
+        Some(WitnessOrd::Mined(_))
+    ));
+
+    // a slow indexer only delays the update
+    proxy.set_faults(vec![IndexerFault::Delay(Duration::from_secs(1))]);
+    wlt_1.update_witnesses(1, vec![txid]);
+    assert!(matches!(
+        wlt_1.stock_witness_ord(contract_id, txid),
+        Some(WitnessOrd::Mined(_))
+    ));
+
+    // an indexer flipping confirmations brings the witness back to tentative
+    proxy.set_faults(vec![IndexerFault::Unconfirm]);
+    wlt_1.update_witnesses(1, vec![txid]);
+    assert_eq!(
+        wlt_1.stock_witness_ord(contract_id, txid),
+        Some(WitnessOrd::Tentative)
+    );
+
+    // the witness gets mined again as soon as the indexer is honest
+    proxy.clear_faults();
+    wlt_1.update_witnesses(1, vec![txid]);
+    assert!(matches!(
+        wlt_1.stock_witness_ord(contract_id, txid),
+        Some(WitnessOrd::Mined(_))
+    ));
+    wlt_1.use_indexer_proxy(None);
+    wlt_1.check_allocations(contract_id, schema_id, vec![issued_supply - amt], false);
+}
+
 #[cfg(not(feature = "altered"))]
 #[rstest]
 #[case(false)]
@@ -1018,6 +1889,104 @@ fn ln_transfers(#[case] update_witnesses_before_htlc: bool) {
     wlt_1.debug_logs(contract_id, AllocationFilter::WalletAll);
 }
 
+#[cfg(not(feature = "altered"))]
+#[rstest]
+#[case(false)]
+#[case(true)]
+fn ln_channel_force_close(#[case] pending_htlc: bool) {
+    initialize();
+
+    let mut wlt_1 = BpTestWallet::with_descriptor(&DescriptorType::Wpkh);
+    let mut wlt_2 = BpTestWallet::with_descriptor(&DescriptorType::Wpkh);
+
+    let funding = wlt_1.get_utxo(Some(30_000));
+    let contract_id = wlt_1.issue_nia(600, Some(&funding));
+    let schema_id = wlt_1.schema_id(contract_id);
+
+    let mut channel = LnChannel::open(&mut wlt_1, &mut wlt_2, contract_id, funding);
+    channel.pay(&mut wlt_1, 100);
+    let htlc_1 = channel.add_htlc(&mut wlt_1, 200);
+    let htlc_2 = channel.add_htlc(&mut wlt_1, 50);
+    channel.settle_htlc(&mut wlt_1, htlc_1);
+    channel.fail_htlc(&mut wlt_1, htlc_2);
+    if pending_htlc {
+        channel.add_htlc(&mut wlt_1, 80);
+    }
+    assert_eq!(channel.commitments().len(), 6 + pending_htlc as usize);
+    assert_eq!(channel.remote_balance(), 300);
+
+    let tx = channel.force_close(&mut wlt_1, &mut wlt_2);
+    // the latest commitment is the only one getting mined
+    assert!(
+        wlt_1
+            .get_tx_height(&txid_bp_to_bitcoin(tx.txid()))
+            .is_some()
+    );
+    let local_amounts = if pending_htlc {
+        vec![220, 80]
+    } else {
+        vec![300]
+    };
+    wlt_1.check_allocations(contract_id, schema_id, local_amounts, false);
+    wlt_2.check_allocations(contract_id, schema_id, vec![300], false);
+    wlt_2.send(
+        &mut wlt_1,
+        TransferType::Witness,
+        contract_id,
+        300,
+        1000,
+        None,
+    );
+}
+
+#[cfg(not(feature = "altered"))]
+#[test]
+fn ln_channel_cooperative_close() {
+    initialize();
+
+    let mut wlt_1 = BpTestWallet::with_descriptor(&DescriptorType::Wpkh);
+    let mut wlt_2 = BpTestWallet::with_descriptor(&DescriptorType::Wpkh);
+
+    let funding = wlt_1.get_utxo(Some(30_000));
+    let contract_id = wlt_1.issue_nia(600, Some(&funding));
+    let schema_id = wlt_1.schema_id(contract_id);
+
+    let mut channel = LnChannel::open(&mut wlt_1, &mut wlt_2, contract_id, funding);
+    for op in [
+        ChannelOp::Pay(150),
+        ChannelOp::AddHtlc(100),
+        ChannelOp::FailHtlc(0),
+        ChannelOp::AddHtlc(50),
+        ChannelOp::SettleHtlc(1),
+    ] {
+        channel.apply(&mut wlt_1, op);
+    }
+    channel.cooperative_close(&mut wlt_1, &mut wlt_2);
+
+    // none of the commitments is valid anymore
+    wlt_1.check_allocations(contract_id, schema_id, vec![400], false);
+    wlt_2.check_allocations(contract_id, schema_id, vec![200], false);
+    wlt_1.send(
+        &mut wlt_2,
+        TransferType::Blinded,
+        contract_id,
+        400,
+        1000,
+        None,
+    );
+}
+
+proptest! {
+    // channels need a chain, so only a few cases run by default
+    #![proptest_config(ProptestConfig::with_cases(4))]
+    #[cfg(not(feature = "altered"))]
+    #[test]
+    fn ln_channel_fuzz(data in prop::collection::vec(any::<u8>(), 0..128)) {
+        initialize();
+        fuzz_channel(&data);
+    }
+}
+
 #[cfg(not(feature = "altered"))]
 #[rstest]
 #[should_panic(expected = "InvoiceBeneficiaryWrongChainNet(BitcoinMainnet, BitcoinRegtest)")]
@@ -1156,16 +2125,218 @@ fn collaborative_transfer() {
         sats - 4 * DEFAULT_FEE_ABS,
         None,
     );
-    wlt_1.send(
-        &mut wlt_2,
-        TransferType::Witness,
-        contract_id,
-        600,
-        sats - 6 * DEFAULT_FEE_ABS,
-        None,
+    wlt_1.send(
+        &mut wlt_2,
+        TransferType::Witness,
+        contract_id,
+        600,
+        sats - 6 * DEFAULT_FEE_ABS,
+        None,
+    );
+}
+
+#[cfg(not(feature = "altered"))]
+#[rstest]
+#[case(DescriptorType::Wpkh, false)]
+#[case(DescriptorType::Wpkh, true)]
+#[case(DescriptorType::Tr, false)]
+#[case(DescriptorType::Tr, true)]
+fn asset_swap(#[case] descriptor_type: DescriptorType, #[case] for_sats: bool) {
+    println!("descriptor_type {descriptor_type:?} for_sats {for_sats}");
+
+    initialize();
+
+    let mut maker = BpTestWallet::with_descriptor(&descriptor_type);
+    let mut taker = BpTestWallet::with_descriptor(&descriptor_type);
+
+    let issue_supply = 600;
+    let offer_id = maker.issue_nia(issue_supply, None);
+    let offer_schema_id = maker.schema_id(offer_id);
+    let ask_id = taker.issue_cfa(issue_supply, None);
+    let ask_schema_id = taker.schema_id(ask_id);
+    // uncolored coins for the taker to pay the asked sats
+    if for_sats {
+        taker.get_utxo(None);
+    }
+
+    let offer_amt = 200;
+    let ask_amt = 300;
+    let ask = if for_sats {
+        SwapAsk::Sats(50_000)
+    } else {
+        SwapAsk::Asset(ask_id, ask_amt)
+    };
+    let mut swap = Swap::offer(&mut maker, offer_id, offer_amt, ask);
+    swap.take(&mut taker, DEFAULT_FEE_ABS);
+    swap.color(SwapParty::Maker, &mut maker);
+    if !for_sats {
+        swap.color(SwapParty::Taker, &mut taker);
+    }
+    assert!(swap.is_committed());
+
+    // consignments are exchanged and verified before signing
+    let maker_consignment = swap.consignment(SwapParty::Maker, &maker);
+    let taker_consignment = swap.consignment(SwapParty::Taker, &taker);
+    assert_eq!(taker_consignment.is_none(), for_sats);
+    swap.verify(SwapParty::Taker, maker_consignment.as_ref());
+    swap.verify(SwapParty::Maker, taker_consignment.as_ref());
+    swap.sign(SwapParty::Maker, &maker);
+    swap.sign(SwapParty::Taker, &taker);
+    let tx = swap.complete(&taker);
+    taker.mine_tx(&txid_bp_to_bitcoin(tx.txid()), false);
+
+    swap.settle(SwapParty::Maker, &mut maker, taker_consignment);
+    swap.settle(SwapParty::Taker, &mut taker, maker_consignment);
+    maker.check_allocations(
+        offer_id,
+        offer_schema_id,
+        vec![issue_supply - offer_amt],
+        false,
+    );
+    taker.check_allocations(offer_id, offer_schema_id, vec![offer_amt], false);
+    if for_sats {
+        taker.check_allocations(ask_id, ask_schema_id, vec![issue_supply], false);
+        let maker_outpoint = *maker.contract_outpoints(offer_id).first().unwrap();
+        let maker_sats = maker.list_unspents()[&maker_outpoint];
+        assert!(maker_sats >= 50_000);
+    } else {
+        taker.check_allocations(ask_id, ask_schema_id, vec![issue_supply - ask_amt], false);
+        maker.check_allocations(ask_id, ask_schema_id, vec![ask_amt], false);
+        // the bought asset lands on the same output as the change of the sold one
+        assert_eq!(
+            maker.contract_outpoints(ask_id),
+            maker.contract_outpoints(offer_id)
+        );
+        maker.send(
+            &mut taker,
+            TransferType::Witness,
+            ask_id,
+            ask_amt,
+            1000,
+            None,
+        );
+    }
+    taker.send(
+        &mut maker,
+        TransferType::Blinded,
+        offer_id,
+        offer_amt,
+        1000,
+        None,
+    );
+    maker.check_allocations(
+        offer_id,
+        offer_schema_id,
+        vec![issue_supply - offer_amt, offer_amt],
+        false,
     );
 }
 
+#[cfg(not(feature = "altered"))]
+#[test]
+fn swap_abort() {
+    initialize();
+
+    let mut maker = BpTestWallet::with_descriptor(&DescriptorType::Wpkh);
+    let mut taker_1 = BpTestWallet::with_descriptor(&DescriptorType::Wpkh);
+    let mut taker_2 = BpTestWallet::with_descriptor(&DescriptorType::Wpkh);
+
+    let issue_supply = 600;
+    let offer_id = maker.issue_nia(issue_supply, None);
+    let offer_schema_id = maker.schema_id(offer_id);
+    let ask_id = taker_1.issue_cfa(issue_supply, None);
+    let ask_schema_id = taker_1.schema_id(ask_id);
+
+    // the maker walks away after the swap has been committed and verified
+    let mut swap = Swap::offer(&mut maker, offer_id, 100, SwapAsk::Asset(ask_id, 100));
+    swap.take(&mut taker_1, DEFAULT_FEE_ABS);
+    swap.color(SwapParty::Maker, &mut maker);
+    swap.color(SwapParty::Taker, &mut taker_1);
+    let maker_consignment = swap.consignment(SwapParty::Maker, &maker);
+    swap.verify(SwapParty::Taker, maker_consignment.as_ref());
+    swap.sign(SwapParty::Taker, &taker_1);
+    drop(swap);
+
+    // nothing moved, both parties can still use their assets
+    maker.check_allocations(offer_id, offer_schema_id, vec![issue_supply], false);
+    taker_1.check_allocations(ask_id, ask_schema_id, vec![issue_supply], false);
+    taker_1.send(&mut taker_2, TransferType::Witness, ask_id, 100, 1000, None);
+
+    // the same allocation can be offered again, to someone else
+    taker_2.get_utxo(None);
+    let mut swap = Swap::offer(&mut maker, offer_id, 100, SwapAsk::Sats(10_000));
+    swap.take(&mut taker_2, DEFAULT_FEE_ABS);
+    swap.color(SwapParty::Maker, &mut maker);
+    let maker_consignment = swap.consignment(SwapParty::Maker, &maker);
+    swap.verify(SwapParty::Taker, maker_consignment.as_ref());
+    swap.verify(SwapParty::Maker, None);
+    swap.sign(SwapParty::Maker, &maker);
+    swap.sign(SwapParty::Taker, &taker_2);
+    let tx = swap.complete(&maker);
+    maker.mine_tx(&txid_bp_to_bitcoin(tx.txid()), false);
+    swap.settle(SwapParty::Maker, &mut maker, None);
+    swap.settle(SwapParty::Taker, &mut taker_2, maker_consignment);
+    maker.check_allocations(offer_id, offer_schema_id, vec![issue_supply - 100], false);
+    taker_2.check_allocations(offer_id, offer_schema_id, vec![100], false);
+    taker_2.check_allocations(ask_id, ask_schema_id, vec![100], false);
+}
+
+#[cfg(not(feature = "altered"))]
+#[rstest]
+#[should_panic(expected = "swap already colored by Maker")]
+#[case(false)]
+#[should_panic(expected = "swap PSBT already committed")]
+#[case(true)]
+fn swap_double_commit(#[case] for_sats: bool) {
+    initialize();
+
+    let mut maker = BpTestWallet::with_descriptor(&DescriptorType::Wpkh);
+    let mut taker = BpTestWallet::with_descriptor(&DescriptorType::Wpkh);
+
+    let offer_id = maker.issue_nia(600, None);
+    let ask_id = taker.issue_cfa(600, None);
+    let ask = if for_sats {
+        // uncolored coins for the taker to pay the asked sats
+        taker.get_utxo(None);
+        SwapAsk::Sats(10_000)
+    } else {
+        SwapAsk::Asset(ask_id, 100)
+    };
+    let mut swap = Swap::offer(&mut maker, offer_id, 100, ask);
+    swap.take(&mut taker, DEFAULT_FEE_ABS);
+    swap.color(SwapParty::Maker, &mut maker);
+    // coloring again would commit the maker allocation to different transitions
+    swap.color(SwapParty::Maker, &mut maker);
+}
+
+#[cfg(not(feature = "altered"))]
+#[test]
+#[should_panic(expected = "consignment doesn't pay the swap amount")]
+fn swap_wrong_consignment() {
+    initialize();
+
+    let mut maker = BpTestWallet::with_descriptor(&DescriptorType::Wpkh);
+    let mut taker = BpTestWallet::with_descriptor(&DescriptorType::Wpkh);
+
+    let offer_id = maker.issue_nia(600, None);
+    let ask_id = taker.issue_cfa(600, None);
+    // uncolored coins for the taker to pay the asked sats
+    taker.get_utxo(None);
+
+    // the maker commits its allocation to two swaps, handing the same consignment to both
+    let mut swap_1 = Swap::offer(&mut maker, offer_id, 100, SwapAsk::Sats(10_000));
+    let mut swap_2 = Swap::offer(&mut maker, offer_id, 100, SwapAsk::Asset(ask_id, 100));
+    swap_1.take(&mut taker, DEFAULT_FEE_ABS);
+    swap_2.take(&mut taker, DEFAULT_FEE_ABS);
+    swap_1.color(SwapParty::Maker, &mut maker);
+    swap_2.color(SwapParty::Maker, &mut maker);
+    swap_2.color(SwapParty::Taker, &mut taker);
+    let consignment_1 = swap_1.consignment(SwapParty::Maker, &maker);
+    swap_1.verify(SwapParty::Taker, consignment_1.as_ref());
+    // the taker notices the consignment doesn't commit to the PSBT it's about to sign
+    swap_2.verify(SwapParty::Taker, consignment_1.as_ref());
+}
+
 #[cfg(not(feature = "altered"))]
 #[test]
 fn receive_from_unbroadcasted_transfer_to_blinded() {
@@ -1190,30 +2361,6 @@ fn receive_from_unbroadcasted_transfer_to_blinded() {
     let (consignment, tx, _, _) = wlt_1.pay_full(invoice.clone(), None, Some(500), false, None);
     let witness_id = tx.txid();
 
-    struct OffchainResolver<'a, 'cons, const TRANSFER: bool> {
-        witness_id: Txid,
-        consignment: &'cons Consignment<TRANSFER>,
-        fallback: &'a AnyResolver,
-    }
-    impl<const TRANSFER: bool> ResolveWitness for OffchainResolver<'_, '_, TRANSFER> {
-        fn resolve_witness(&self, witness_id: Txid) -> Result<WitnessStatus, WitnessResolverError> {
-            if witness_id != self.witness_id {
-                return self.fallback.resolve_witness(witness_id);
-            }
-            self.consignment
-                .bundled_witnesses()
-                .find(|bw| bw.witness_id() == witness_id)
-                .and_then(|p| p.pub_witness.tx().cloned())
-                .map_or_else(
-                    || self.fallback.resolve_witness(witness_id),
-                    |tx| Ok(WitnessStatus::Resolved(tx, WitnessOrd::Tentative)),
-                )
-        }
-        fn check_chain_net(&self, _: ChainNet) -> Result<(), WitnessResolverError> {
-            Ok(())
-        }
-    }
-
     let resolver = OffchainResolver {
         witness_id: txid_bp_to_bitcoin(witness_id),
         consignment: &consignment,
@@ -1244,6 +2391,438 @@ fn receive_from_unbroadcasted_transfer_to_blinded() {
     ));
 }
 
+#[cfg(not(feature = "altered"))]
+#[rstest]
+#[case(TransferType::Blinded)]
+#[case(TransferType::Witness)]
+fn proxy_transfer_ack(#[case] transfer_type: TransferType) {
+    println!("transfer_type {transfer_type:?}");
+
+    initialize();
+
+    let proxy = RgbProxy::start();
+    let client = proxy.client();
+    assert_eq!(
+        client.server_info().unwrap()["protocol_version"],
+        RGB_PROXY_PROTOCOL_VERSION
+    );
+
+    let mut wlt_1 = BpTestWallet::with_descriptor(&DescriptorType::Wpkh);
+    let mut wlt_2 = BpTestWallet::with_descriptor(&DescriptorType::Wpkh);
+
+    let issue_supply = 600;
+    let contract_id = wlt_1.issue_nia(issue_supply, None);
+    let schema_id = wlt_1.schema_id(contract_id);
+
+    let issue_outpoint = *wlt_1.contract_outpoints(contract_id).iter().next().unwrap();
+
+    let amount = 100;
+    let invoice = wlt_2.invoice(contract_id, schema_id, amount, transfer_type);
+    let pending = wlt_1.post_transfer(&client, invoice.clone(), None, Some(500));
+    assert_eq!(
+        pending.vout.is_some(),
+        transfer_type == TransferType::Witness
+    );
+
+    // nothing is broadcast before the recipient answers
+    assert_eq!(client.get_ack(&pending.recipient_id).unwrap(), None);
+    assert_eq!(wlt_1.get_witness_ord(&pending.txid()), WitnessOrd::Archived);
+
+    // the same consignment can be posted again, a different one can't
+    assert!(
+        !client
+            .post_consignment(
+                &pending.recipient_id,
+                &pending.consignment,
+                pending.txid(),
+                pending.vout
+            )
+            .unwrap()
+    );
+    let other_consignment = wlt_1.consign_transfer(
+        contract_id,
+        [ExplicitSeal::new(issue_outpoint)],
+        [],
+        [],
+        None,
+    );
+    let err = client
+        .post_consignment(
+            &pending.recipient_id,
+            &other_consignment,
+            pending.txid(),
+            None,
+        )
+        .unwrap_err();
+    assert_eq!(err.rpc_code(), Some(ERR_CANNOT_CHANGE_UPLOADED_FILE));
+
+    let (consignment, ack) = wlt_2.review_transfer(&client, &invoice);
+    assert!(ack);
+    let err = client.post_ack(&pending.recipient_id, false).unwrap_err();
+    assert_eq!(err.rpc_code(), Some(ERR_CANNOT_CHANGE_ACK));
+
+    let outcome = wlt_1.complete_transfer(&client, &pending, Duration::from_secs(5));
+    assert_eq!(outcome, TransferOutcome::Accepted);
+    wlt_1.mine_tx(&pending.txid(), false);
+    wlt_2.accept_transfer(consignment, None);
+    wlt_1.sync();
+
+    wlt_1.check_allocations(contract_id, schema_id, vec![issue_supply - amount], false);
+    wlt_2.check_allocations(contract_id, schema_id, vec![amount], false);
+}
+
+#[cfg(not(feature = "altered"))]
+#[test]
+fn proxy_transfer_nack() {
+    initialize();
+
+    let proxy = RgbProxy::start();
+    let client = proxy.client();
+
+    let mut wlt_1 = BpTestWallet::with_descriptor(&DescriptorType::Wpkh);
+    let mut wlt_2 = BpTestWallet::with_descriptor(&DescriptorType::Wpkh);
+
+    let issue_supply = 600;
+    let contract_id_1 = wlt_1.issue_nia(issue_supply, None);
+    let contract_id_2 = wlt_1.issue_nia(issue_supply, None);
+    let schema_id = wlt_1.schema_id(contract_id_1);
+
+    // wlt_2 asks for an asset but wlt_1 pays with another one
+    let amount = 100;
+    let invoice = wlt_2.invoice(contract_id_2, schema_id, amount, TransferType::Witness);
+    let mut wrong_invoice = invoice.clone();
+    wrong_invoice.contract = Some(contract_id_1);
+    let pending = wlt_1.post_transfer(&client, wrong_invoice, Some(2000), None);
+
+    let (_, ack) = wlt_2.review_transfer(&client, &invoice);
+    assert!(!ack);
+    assert_eq!(client.get_ack(&pending.recipient_id).unwrap(), Some(false));
+
+    let outcome = wlt_1.complete_transfer(&client, &pending, Duration::from_secs(5));
+    assert_eq!(outcome, TransferOutcome::Rejected);
+    assert!(wlt_1.get_tx_height(&pending.txid()).is_none());
+
+    // the rejected transfer has been abandoned, wlt_2 never imported the contract
+    wlt_1.check_allocations(contract_id_1, schema_id, vec![issue_supply], false);
+    assert!(wlt_2.list_contracts().iter().all(|c| c.id != contract_id_1));
+}
+
+#[cfg(not(feature = "altered"))]
+#[test]
+fn proxy_transfer_retries() {
+    initialize();
+
+    let proxy = RgbProxy::start();
+
+    let mut wlt_1 = BpTestWallet::with_descriptor(&DescriptorType::Wpkh);
+    let mut wlt_2 = BpTestWallet::with_descriptor(&DescriptorType::Wpkh);
+
+    let issue_supply = 600;
+    let contract_id = wlt_1.issue_nia(issue_supply, None);
+    let schema_id = wlt_1.schema_id(contract_id);
+
+    let amount = 100;
+    let invoice = wlt_2.invoice(contract_id, schema_id, amount, TransferType::Blinded);
+
+    // the sender goes through a proxy unavailable for a while
+    proxy.set_faults(vec![RgbProxyFault::Unavailable(2)]);
+    let client = proxy.client().with_retries(3);
+    let requests = proxy.requests();
+    let pending = wlt_1.post_transfer(&client, invoice.clone(), None, Some(500));
+    assert_eq!(proxy.requests(), requests + 3);
+
+    // without retries the first failure is returned
+    proxy.set_faults(vec![RgbProxyFault::Drop]);
+    let err = proxy
+        .client()
+        .get_consignment(&pending.recipient_id)
+        .err()
+        .unwrap();
+    assert!(matches!(err, RgbProxyError::Transport(_)));
+    proxy.set_faults(vec![RgbProxyFault::Unavailable(1)]);
+    let err = proxy
+        .client()
+        .get_consignment(&pending.recipient_id)
+        .err()
+        .unwrap();
+    assert_eq!(err, RgbProxyError::Http(503));
+
+    // a slow proxy times out, the upload retried afterwards is accepted as a duplicate
+    proxy.set_faults(vec![RgbProxyFault::Delay(Duration::from_millis(500))]);
+    let slow_client = proxy
+        .client()
+        .with_timeout(Duration::from_millis(100))
+        .with_retries(1);
+    let err = slow_client
+        .post_consignment(
+            &pending.recipient_id,
+            &pending.consignment,
+            pending.txid(),
+            None,
+        )
+        .unwrap_err();
+    assert!(matches!(err, RgbProxyError::Transport(_)));
+    proxy.clear_faults();
+    assert!(
+        !client
+            .post_consignment(
+                &pending.recipient_id,
+                &pending.consignment,
+                pending.txid(),
+                None
+            )
+            .unwrap()
+    );
+
+    // the receiver hits a flaky proxy too
+    proxy.set_faults(vec![RgbProxyFault::Unavailable(1)]);
+    let (consignment, ack) = wlt_2.review_transfer(&client, &invoice);
+    assert!(ack);
+
+    let outcome = wlt_1.complete_transfer(&client, &pending, Duration::from_secs(5));
+    assert_eq!(outcome, TransferOutcome::Accepted);
+    wlt_1.mine_tx(&pending.txid(), false);
+    wlt_2.accept_transfer(consignment, None);
+    wlt_1.sync();
+
+    wlt_1.check_allocations(contract_id, schema_id, vec![issue_supply - amount], false);
+    wlt_2.check_allocations(contract_id, schema_id, vec![amount], false);
+}
+
+#[cfg(not(feature = "altered"))]
+#[test]
+fn proxy_transfer_timeout() {
+    initialize();
+
+    let proxy = RgbProxy::start();
+    let client = proxy.client();
+
+    let mut wlt_1 = BpTestWallet::with_descriptor(&DescriptorType::Wpkh);
+    let mut wlt_2 = BpTestWallet::with_descriptor(&DescriptorType::Wpkh);
+
+    let issue_supply = 600;
+    let contract_id = wlt_1.issue_nia(issue_supply, None);
+    let schema_id = wlt_1.schema_id(contract_id);
+
+    let amount = 100;
+    let invoice = wlt_2.invoice(contract_id, schema_id, amount, TransferType::Witness);
+    let pending = wlt_1.post_transfer(&client, invoice.clone(), Some(2000), None);
+
+    // the receiver never answers
+    let outcome = wlt_1.complete_transfer(&client, &pending, Duration::from_secs(1));
+    assert_eq!(outcome, TransferOutcome::TimedOut);
+    assert!(wlt_1.get_tx_height(&pending.txid()).is_none());
+    wlt_1.check_allocations(contract_id, schema_id, vec![issue_supply], false);
+
+    // the same invoice is then paid with a new transfer, relayed with a new recipient ID
+    let invoice = wlt_2.invoice(contract_id, schema_id, amount, TransferType::Witness);
+    let pending = wlt_1.post_transfer(&client, invoice.clone(), Some(2000), None);
+    let (consignment, ack) = wlt_2.review_transfer(&client, &invoice);
+    assert!(ack);
+    let outcome = wlt_1.complete_transfer(&client, &pending, Duration::from_secs(5));
+    assert_eq!(outcome, TransferOutcome::Accepted);
+    wlt_1.mine_tx(&pending.txid(), false);
+    wlt_2.accept_transfer(consignment, None);
+    wlt_1.sync();
+
+    wlt_1.check_allocations(contract_id, schema_id, vec![issue_supply - amount], false);
+    wlt_2.check_allocations(contract_id, schema_id, vec![amount], false);
+}
+
+#[cfg(not(feature = "altered"))]
+#[test]
+fn proxy_media() {
+    let proxy = RgbProxy::start();
+    let client = proxy.client();
+
+    let file = std::fs::read(MEDIA_FPATH).unwrap();
+    let attachment_id = sha256::Hash::hash(&file).to_string();
+    let err = client.get_media(&attachment_id).unwrap_err();
+    assert_eq!(err.rpc_code(), Some(ERR_NOT_FOUND_MEDIA));
+
+    assert!(client.post_media(&attachment_id, &file).unwrap());
+    assert!(!client.post_media(&attachment_id, &file).unwrap());
+    let err = client.post_media(&attachment_id, &file[1..]).unwrap_err();
+    assert_eq!(err.rpc_code(), Some(ERR_CANNOT_CHANGE_UPLOADED_FILE));
+    assert_eq!(client.get_media(&attachment_id).unwrap(), file);
+
+    let err = client.post_media("", &file).unwrap_err();
+    assert_eq!(err.rpc_code(), Some(ERR_MISSING_ATTACHMENT_ID));
+
+    // files are uploaded as multipart forms and downloaded base64-encoded
+    let url = format!("{}{RGB_PROXY_JSON_RPC_PATH}", proxy.url());
+    let rpc = |method: &str| {
+        let params = json!({"attachment_id": attachment_id});
+        let request = json!({"jsonrpc": "2.0", "id": 1, "method": method, "params": params});
+        let response = http_call("POST", &url, &serde_json::to_vec(&request).unwrap()).unwrap();
+        serde_json::from_slice::<Value>(&response.body).unwrap()
+    };
+    assert_eq!(rpc("media.post")["error"]["code"], ERR_MISSING_FILE);
+    assert_eq!(rpc("media.get")["result"], BASE64.encode(&file));
+}
+
+fn uda_media_asset_info(
+    media_store: &MediaStore,
+    attachments: BTreeMap<u8, Attachment>,
+) -> AssetInfo {
+    media_store.upload_fpath(MEDIA_FPATH);
+    let token_media = media_store.upload_fpath("README.md");
+    let token_data = uda_token_data(
+        "TDTCKR",
+        "token data name",
+        "token data details",
+        EmbeddedMedia {
+            ty: MediaType::with("image/jpeg"),
+            data: Confined::try_from(vec![1u8, 3u8, 9u8]).unwrap(),
+        },
+        token_media,
+        attachments,
+        ProofOfReserves {
+            utxo: Outpoint::from_str(FAKE_TXID).unwrap(),
+            proof: Confined::try_from(vec![2u8, 4u8, 6u8, 10u8]).unwrap(),
+        },
+    );
+    AssetInfo::uda(
+        "TCKR",
+        "asset name",
+        None,
+        "Ricardian contract",
+        Some(MEDIA_FPATH),
+        token_data,
+    )
+}
+
+#[cfg(not(feature = "altered"))]
+#[rstest]
+#[case(false)]
+#[case(true)]
+fn uda_media_verification(#[case] use_proxy: bool) {
+    println!("use_proxy {use_proxy}");
+
+    initialize();
+
+    let proxy = RgbProxy::start();
+    let media_store = if use_proxy {
+        MediaStore::proxy(&proxy)
+    } else {
+        MediaStore::local()
+    };
+    let mut wlt_1 = BpTestWallet::with_descriptor(&DescriptorType::Wpkh);
+    let mut wlt_2 = BpTestWallet::with_descriptor(&DescriptorType::Tr);
+
+    // the receiver can't fetch anything from its own store
+    let attachments = bmap! {0 => media_store.upload_fpath("Cargo.toml")};
+    let asset_info = uda_media_asset_info(&media_store, attachments);
+    let contract_id = wlt_1.issue_with_info(asset_info, vec![], None, None);
+    wlt_1.send(&mut wlt_2, InvoiceType::Witness, contract_id, 1, 1000, None);
+    let mismatches = wlt_2.verify_uda_media(contract_id);
+    assert_eq!(
+        mismatches
+            .iter()
+            .map(|m| (m.location, m.issue.clone()))
+            .collect::<Vec<_>>(),
+        vec![
+            (MediaLocation::Terms, MediaIssue::Missing),
+            (MediaLocation::TokenMedia, MediaIssue::Missing),
+            (MediaLocation::TokenAttachment(0), MediaIssue::Missing),
+        ]
+    );
+
+    // all files are fetched from the shared store and match their attachments
+    wlt_1.set_media_store(media_store.clone());
+    wlt_2.set_media_store(media_store);
+    assert_eq!(wlt_1.verify_uda_media(contract_id), vec![]);
+    assert_eq!(wlt_2.verify_uda_media(contract_id), vec![]);
+
+    if use_proxy {
+        proxy.set_faults(vec![RgbProxyFault::Unavailable(3)]);
+        let mismatches = wlt_2.verify_uda_media(contract_id);
+        assert_eq!(mismatches.len(), 3);
+        assert!(
+            mismatches
+                .iter()
+                .all(|m| matches!(m.issue, MediaIssue::Unreachable(_)))
+        );
+        assert_eq!(wlt_2.verify_uda_media(contract_id), vec![]);
+    }
+}
+
+#[cfg(not(feature = "altered"))]
+#[test]
+fn uda_media_tampered() {
+    initialize();
+
+    let media_store = MediaStore::local();
+    let mut wlt_1 = BpTestWallet::with_descriptor(&DescriptorType::Wpkh);
+    let mut wlt_2 = BpTestWallet::with_descriptor(&DescriptorType::Wpkh);
+    wlt_1.set_media_store(media_store.clone());
+    wlt_2.set_media_store(media_store.clone());
+
+    // an attachment declaring the wrong media type for its file
+    let mislabeled = Attachment {
+        ty: MediaType::with("text/plain"),
+        ..attachment_from_fpath(MEDIA_FPATH)
+    };
+    let attachments = bmap! {
+        0 => media_store.upload_fpath("Cargo.toml"),
+        1 => mislabeled.clone(),
+    };
+    let asset_info = uda_media_asset_info(&media_store, attachments.clone());
+    let contract_id = wlt_1.issue_with_info(asset_info, vec![], None, None);
+    wlt_1.send(
+        &mut wlt_2,
+        InvoiceType::Blinded(None),
+        contract_id,
+        1,
+        1000,
+        None,
+    );
+    let mislabeled_mismatch = MediaMismatch {
+        location: MediaLocation::TokenAttachment(1),
+        attachment: mislabeled,
+        issue: MediaIssue::MediaTypeMismatch {
+            found: s!("image/jpeg"),
+        },
+    };
+    assert_eq!(
+        wlt_2.verify_uda_media(contract_id),
+        vec![mislabeled_mismatch.clone()]
+    );
+
+    // the token media is replaced and an attachment is deleted from the store
+    let token_media = attachment_from_fpath("README.md");
+    let mut tampered = std::fs::read("README.md").unwrap();
+    tampered.extend(b"\ntampered\n");
+    media_store.upload(&token_media, &tampered);
+    media_store.remove(&attachments[&0]);
+    assert_eq!(
+        wlt_2.verify_uda_media(contract_id),
+        vec![
+            MediaMismatch {
+                location: MediaLocation::TokenMedia,
+                attachment: token_media.clone(),
+                issue: MediaIssue::DigestMismatch {
+                    found: sha256::Hash::hash(&tampered).to_string(),
+                },
+            },
+            MediaMismatch {
+                location: MediaLocation::TokenAttachment(0),
+                attachment: attachments[&0].clone(),
+                issue: MediaIssue::Missing,
+            },
+            mislabeled_mismatch.clone(),
+        ]
+    );
+
+    // restoring the original files fixes them
+    media_store.upload_fpath("README.md");
+    media_store.upload_fpath("Cargo.toml");
+    assert_eq!(
+        wlt_2.verify_uda_media(contract_id),
+        vec![mislabeled_mismatch]
+    );
+}
+
 #[cfg(not(feature = "altered"))]
 #[test]
 fn check_fungible_history() {
@@ -1546,7 +3125,7 @@ fn pfa() {
     let mut wlt_1 = BpTestWallet::with_descriptor(&DescriptorType::Wpkh);
     let mut wlt_2 = BpTestWallet::with_descriptor(&DescriptorType::Wpkh);
 
-    let (secret_key, public_key) = Secp256k1::new().generate_keypair(&mut rand::thread_rng());
+    let (secret_key, public_key) = with_test_rng(|rng| Secp256k1::new().generate_keypair(rng));
     let pubkey = CompressedPublicKey::from_slice(&public_key.serialize()).unwrap();
 
     let utxo = wlt_1.get_utxo(None);
@@ -1572,25 +3151,107 @@ fn pfa() {
     wlt_1.send_pfa(
         &mut wlt_2,
         TransferType::Witness,
-        contract_id_2,
+        contract_id_2,
+        amt_2,
+        secret_key,
+    );
+
+    wlt_1.check_allocations(
+        contract_id_1,
+        schema_id_1,
+        vec![issued_amt_1 - amt_1],
+        false,
+    );
+    wlt_2.check_allocations(contract_id_1, schema_id_1, vec![amt_1], false);
+    wlt_1.check_allocations(
+        contract_id_2,
+        schema_id_2,
+        vec![issued_amt_2 - amt_2],
+        false,
+    );
+    wlt_2.check_allocations(contract_id_2, schema_id_2, vec![amt_2], false);
+}
+
+#[cfg(not(feature = "altered"))]
+#[test]
+fn pfa_issuer_key() {
+    initialize();
+
+    let mut wlt_1 = BpTestWallet::with_descriptor(&DescriptorType::Wpkh);
+    let mut wlt_2 = BpTestWallet::with_descriptor(&DescriptorType::Wpkh);
+    let mut wlt_3 = BpTestWallet::with_descriptor(&DescriptorType::Wpkh);
+
+    let issued_amt = 600;
+    let contract_id = wlt_1.issue_pfa_with_signer(issued_amt, None);
+    let schema_id = wlt_1.schema_id(contract_id);
+    assert!(wlt_2.pfa_key(contract_id).is_none());
+
+    let amt_1 = 200;
+    wlt_1.send(
+        &mut wlt_2,
+        TransferType::Witness,
+        contract_id,
+        amt_1,
+        5000,
+        None,
+    );
+
+    // the issuer authorizes the transfers of wlt_2
+    wlt_2.add_pfa_key(contract_id, wlt_1.pfa_key(contract_id).unwrap());
+    let amt_2 = 50;
+    wlt_2.send(
+        &mut wlt_1,
+        TransferType::Witness,
+        contract_id,
         amt_2,
-        secret_key,
+        1000,
+        None,
     );
-
-    wlt_1.check_allocations(
-        contract_id_1,
-        schema_id_1,
-        vec![issued_amt_1 - amt_1],
-        false,
+    let amt_3 = 100;
+    wlt_2.send(
+        &mut wlt_3,
+        TransferType::Witness,
+        contract_id,
+        amt_3,
+        2000,
+        None,
     );
-    wlt_2.check_allocations(contract_id_1, schema_id_1, vec![amt_1], false);
     wlt_1.check_allocations(
-        contract_id_2,
-        schema_id_2,
-        vec![issued_amt_2 - amt_2],
+        contract_id,
+        schema_id,
+        vec![issued_amt - amt_1, amt_2],
         false,
     );
-    wlt_2.check_allocations(contract_id_2, schema_id_2, vec![amt_2], false);
+    wlt_2.check_allocations(contract_id, schema_id, vec![amt_1 - amt_2 - amt_3], false);
+    wlt_3.check_allocations(contract_id, schema_id, vec![amt_3], false);
+
+    // wlt_3 doesn't hold the issuer key, so its transfer is left unsigned
+    let invoice = wlt_1.invoice(contract_id, schema_id, amt_3, TransferType::Witness);
+    let (mut consignment, tx, _, _) = wlt_3.pay_full(invoice, Some(1000), None, true, None);
+    let txid = txid_bp_to_bitcoin(tx.txid());
+    wlt_3.sign_pfa_transfer(&mut consignment, txid);
+    wlt_3.mine_tx(&txid, false);
+    let opid = consignment
+        .bundles
+        .iter()
+        .find(|wb| wb.witness_id() == txid)
+        .unwrap()
+        .bundle
+        .known_transitions
+        .iter()
+        .last()
+        .unwrap()
+        .opid;
+    let resolver = wlt_1.get_resolver();
+    let res = wlt_1.try_accept_transfer_custom(consignment, None, &resolver);
+    assert_eq!(
+        res.unwrap_err(),
+        ValidationError::InvalidConsignment(Failure::ScriptFailure(
+            opid,
+            Some(ERRNO_INVALID_SIGNATURE),
+            None
+        ))
+    );
 }
 
 #[cfg(not(feature = "altered"))]
@@ -1938,6 +3599,167 @@ fn ifa_burn() {
     wlt_2.check_allocations(contract_id, AssetSchema::Ifa, vec![], false);
 }
 
+#[cfg(not(feature = "altered"))]
+#[rstest]
+#[case(RejectListPolicy::Flag)]
+#[case(RejectListPolicy::Refuse)]
+fn ifa_reject_list(#[case] policy: RejectListPolicy) {
+    println!("policy {policy:?}");
+
+    initialize();
+
+    let reject_list = RejectListServer::start();
+    let mut wlt_1 = BpTestWallet::with_descriptor(&DescriptorType::Wpkh);
+    let mut wlt_2 = BpTestWallet::with_descriptor(&DescriptorType::Wpkh);
+    wlt_2.set_reject_list_policy(policy);
+
+    let reject_list_url = reject_list.url();
+    let asset_info = AssetInfo::ifa(
+        "IFATCKR",
+        "IFA asset name",
+        0,
+        None,
+        "IFA terms",
+        None,
+        Some(&reject_list_url),
+        vec![600],
+        vec![],
+        (None, None),
+    );
+    let contract_id = wlt_1.issue_with_info(asset_info, vec![None], None, None);
+    let schema_id = wlt_1.schema_id(contract_id);
+
+    let amt = 100;
+    let invoice = wlt_2.invoice(contract_id, schema_id, amt, InvoiceType::Witness);
+    let (consignment, tx, _, _) = wlt_1.pay_full(invoice, Some(2000), None, true, None);
+    wlt_1.mine_tx(&txid_bp_to_bitcoin(tx.txid()), false);
+    assert_eq!(
+        consignment_reject_list_url(&consignment),
+        Some(reject_list_url)
+    );
+
+    // opouts not involved in the transfer history don't matter
+    let issue_opout = Opout::new(consignment.genesis.id(), OS_ASSET, 0);
+    let unrelated_opout = Opout::new(consignment.genesis.id(), OS_ASSET, 1);
+    assert!(consignment_opouts(&consignment).contains(&issue_opout));
+    assert!(!consignment_opouts(&consignment).contains(&unrelated_opout));
+    reject_list.list(unrelated_opout);
+    assert_eq!(check_reject_list(&consignment), None);
+
+    // listed issuance
+    reject_list.list(issue_opout);
+    let requests = reject_list.requests();
+    let res = wlt_2.try_accept_transfer_custom(consignment.clone(), None, &wlt_2.get_resolver());
+    assert_eq!(reject_list.requests(), requests + 1);
+    let issue = RejectListIssue::Rejected(bset![issue_opout]).to_string();
+    match policy {
+        RejectListPolicy::Flag => {
+            assert!(
+                res.unwrap()
+                    .warnings
+                    .iter()
+                    .any(|w| matches!(w, Warning::Custom(msg) if *msg == issue))
+            );
+            wlt_2.check_allocations(contract_id, schema_id, vec![amt], false);
+        }
+        RejectListPolicy::Refuse => {
+            assert!(matches!(
+                res,
+                Err(ValidationError::InvalidConsignment(Failure::Custom(msg))) if msg == issue
+            ));
+            assert!(!wlt_2.list_contracts().iter().any(|c| c.id == contract_id));
+        }
+        RejectListPolicy::Ignore => unreachable!(),
+    }
+
+    // once delisted the transfer is accepted without warnings
+    reject_list.delist(issue_opout);
+    let status = wlt_2.accept_transfer(consignment, None);
+    assert!(
+        !status
+            .warnings
+            .iter()
+            .any(|w| matches!(w, Warning::Custom(_)))
+    );
+    wlt_2.check_allocations(contract_id, schema_id, vec![amt], false);
+
+    // the recipient can spend the asset
+    wlt_2.send(
+        &mut wlt_1,
+        InvoiceType::Witness,
+        contract_id,
+        amt,
+        1000,
+        None,
+    );
+    wlt_2.check_allocations(contract_id, schema_id, vec![], false);
+}
+
+#[cfg(not(feature = "altered"))]
+#[rstest]
+#[case(RejectListPolicy::Ignore)]
+#[case(RejectListPolicy::Flag)]
+#[case(RejectListPolicy::Refuse)]
+fn ifa_reject_list_unreachable(#[case] policy: RejectListPolicy) {
+    println!("policy {policy:?}");
+
+    initialize();
+
+    let reject_list = RejectListServer::start();
+    reject_list.set_available(false);
+    let mut wlt_1 = BpTestWallet::with_descriptor(&DescriptorType::Wpkh);
+    let mut wlt_2 = BpTestWallet::with_descriptor(&DescriptorType::Wpkh);
+    wlt_2.set_reject_list_policy(policy);
+
+    let asset_info = AssetInfo::ifa(
+        "IFATCKR",
+        "IFA asset name",
+        0,
+        None,
+        "IFA terms",
+        None,
+        Some(&reject_list.url()),
+        vec![600],
+        vec![],
+        (None, None),
+    );
+    let contract_id = wlt_1.issue_with_info(asset_info, vec![None], None, None);
+    let schema_id = wlt_1.schema_id(contract_id);
+
+    let amt = 100;
+    let invoice = wlt_2.invoice(contract_id, schema_id, amt, InvoiceType::Witness);
+    let (consignment, tx, _, _) = wlt_1.pay_full(invoice, Some(2000), None, true, None);
+    wlt_1.mine_tx(&txid_bp_to_bitcoin(tx.txid()), false);
+
+    let res = wlt_2.try_accept_transfer_custom(consignment, None, &wlt_2.get_resolver());
+    let unreachable = |msg: &String| msg.starts_with("unreachable reject list");
+    match policy {
+        RejectListPolicy::Ignore => {
+            assert_eq!(reject_list.requests(), 0);
+            assert_eq!(res.unwrap().validity(), Validity::Valid);
+            wlt_2.check_allocations(contract_id, schema_id, vec![amt], false);
+        }
+        RejectListPolicy::Flag => {
+            assert_eq!(reject_list.requests(), 1);
+            assert!(
+                res.unwrap()
+                    .warnings
+                    .iter()
+                    .any(|w| matches!(w, Warning::Custom(msg) if unreachable(msg)))
+            );
+            wlt_2.check_allocations(contract_id, schema_id, vec![amt], false);
+        }
+        RejectListPolicy::Refuse => {
+            assert_eq!(reject_list.requests(), 1);
+            assert!(matches!(
+                res,
+                Err(ValidationError::InvalidConsignment(Failure::Custom(msg))) if unreachable(&msg)
+            ));
+            assert!(!wlt_2.list_contracts().iter().any(|c| c.id == contract_id));
+        }
+    }
+}
+
 #[cfg(not(feature = "altered"))]
 #[should_panic(expected = "InputMapTransitionMismatch")]
 #[test]
@@ -2063,7 +3885,7 @@ fn uncommitted_input_opout() {
 
     // merge the 2 allocation to send to wlt_2
     let invoice = wlt_2.invoice(contract_id, schema_id, issued_amt, InvoiceType::Witness);
-    let (mut psbt, _, mut consignment) = wlt_1.pay_invoice(invoice, None, None);
+    let (mut psbt, _, mut consignment) = wlt_1.pay_invoice(invoice, None, None, None);
     let prev_txids = consignment
         .bundles
         .iter()
@@ -3070,11 +4892,11 @@ fn contract_linking() {
 #[case(HistoryType::Branching, ReorgType::Revert)]
 #[case(HistoryType::Merging, ReorgType::ChangeOrder)]
 #[case(HistoryType::Merging, ReorgType::Revert)]
-#[serial]
 fn reorg_history(#[case] history_type: HistoryType, #[case] reorg_type: ReorgType) {
     println!("history_type {history_type:?} reorg_type {reorg_type:?}");
 
     initialize();
+    let _reorg_nodes = lock_reorg_nodes();
     connect_reorg_nodes();
 
     let mut wlt_1 = BpTestWallet::with(&DescriptorType::Wpkh, Some(INSTANCE_2), true);
@@ -3375,11 +5197,11 @@ fn reorg_history(#[case] history_type: HistoryType, #[case] reorg_type: ReorgTyp
 #[case(HistoryType::Linear)]
 #[case(HistoryType::Branching)]
 #[case(HistoryType::Merging)]
-#[serial]
 fn reorg_revert_multiple(#[case] history_type: HistoryType) {
     println!("history_type {history_type:?}");
 
     initialize();
+    let _reorg_nodes = lock_reorg_nodes();
     connect_reorg_nodes();
 
     let mut wlt_1 = BpTestWallet::with(&DescriptorType::Wpkh, Some(INSTANCE_2), true);
@@ -3577,11 +5399,11 @@ fn reorg_revert_multiple(#[case] history_type: HistoryType) {
 #[rstest]
 #[case(false)]
 #[case(true)]
-#[serial]
 fn revert_genesis(#[case] with_transfers: bool) {
     println!("with_transfers {with_transfers}");
 
     initialize();
+    let _reorg_nodes = lock_reorg_nodes();
     // connecting before disconnecting since disconnect is not idempotent
     connect_reorg_nodes();
     disconnect_reorg_nodes();
@@ -3622,3 +5444,271 @@ fn revert_genesis(#[case] with_transfers: bool) {
 
     wlt.check_allocations(contract_id, schema_id, vec![], false);
 }
+
+#[cfg(not(feature = "altered"))]
+#[test]
+fn reorg_flip_flop() {
+    initialize();
+    // the competing branches are built on a simulated chain even when using docker
+    let chain = use_simulated_chain();
+    connect_reorg_nodes();
+
+    let mut wlt_1 = BpTestWallet::with(&DescriptorType::Wpkh, Some(INSTANCE_2), true);
+    let mut wlt_2 = BpTestWallet::with(&DescriptorType::Wpkh, Some(INSTANCE_2), true);
+
+    let issued_supply = 600;
+    let contract_id = wlt_1.issue_nia(issued_supply, None);
+    let schema_id = wlt_1.schema_id(contract_id);
+    let utxo = wlt_2.get_utxo(None);
+
+    let mut reorg = ReorgSim::with_chain(chain, INSTANCE_2, 0);
+
+    let amt = 100;
+    let invoice = wlt_2.invoice(
+        contract_id,
+        schema_id,
+        amt,
+        InvoiceType::Blinded(Some(utxo)),
+    );
+    let (_, tx) = wlt_1.send_to_invoice(&mut wlt_2, invoice, None, None, None);
+    let txid = txid_bp_to_bitcoin(tx.txid());
+
+    let confirmed = reorg.branch(vec![vec![txid]]);
+    let dropped = reorg.empty_branch(2);
+    let delayed = reorg.empty_branch(1);
+
+    for _ in 0..3 {
+        reorg.activate(dropped);
+        wlt_1.sync_and_update_witnesses(None);
+        wlt_2.sync_and_update_witnesses(None);
+        assert_eq!(
+            wlt_1.stock_witness_ord(contract_id, txid),
+            Some(WitnessOrd::Archived)
+        );
+        wlt_1.check_allocations(contract_id, schema_id, vec![issued_supply], false);
+        wlt_2.check_allocations(contract_id, schema_id, vec![], false);
+
+        reorg.activate(confirmed);
+        wlt_1.sync_and_update_witnesses(None);
+        wlt_2.sync_and_update_witnesses(None);
+        assert!(matches!(
+            wlt_1.stock_witness_ord(contract_id, txid),
+            Some(WitnessOrd::Mined(_))
+        ));
+        wlt_1.check_allocations(contract_id, schema_id, vec![issued_supply - amt], false);
+        wlt_2.check_allocations(contract_id, schema_id, vec![amt], false);
+    }
+
+    // the transfer gets confirmed later on a longer branch
+    reorg.move_txs(&[txid], delayed, 3);
+    reorg.activate(delayed);
+    wlt_1.sync_and_update_witnesses(None);
+    wlt_2.sync_and_update_witnesses(None);
+    assert_eq!(wlt_1.get_tx_height(&txid), reorg.tx_height(delayed, txid));
+    assert_eq!(reorg.tx_height(confirmed, txid), None);
+    wlt_1.check_allocations(contract_id, schema_id, vec![issued_supply - amt], false);
+    wlt_2.check_allocations(contract_id, schema_id, vec![amt], false);
+
+    reorg.activate(confirmed);
+    wlt_2.sync_and_update_witnesses(None);
+    wlt_2.check_allocations(contract_id, schema_id, vec![], false);
+}
+
+#[cfg(not(feature = "altered"))]
+#[rstest]
+#[case(false)]
+#[case(true)]
+fn reorg_deep_across_genesis(#[case] with_transfers: bool) {
+    println!("with_transfers {with_transfers}");
+
+    initialize();
+    // the competing branches are built on a simulated chain even when using docker
+    let chain = use_simulated_chain();
+    connect_reorg_nodes();
+
+    let mut wlt_1 = BpTestWallet::with(&DescriptorType::Wpkh, Some(INSTANCE_2), true);
+    let mut wlt_2 = BpTestWallet::with(&DescriptorType::Wpkh, Some(INSTANCE_2), true);
+    // the receiving UTXO must be below the fork to survive the reorgs
+    let utxo_2 = wlt_2.get_utxo(None);
+
+    let fork_height = get_height_custom(INSTANCE_2);
+    let issued_supply = 600;
+    let utxo = wlt_1.get_utxo(None);
+    let contract_id = wlt_1.issue_nia(issued_supply, Some(&utxo));
+    let schema_id = wlt_1.schema_id(contract_id);
+    let amt = 200;
+    let mut txids = vec![utxo.txid];
+    if with_transfers {
+        let (_, tx) = wlt_1.send(
+            &mut wlt_2,
+            InvoiceType::Blinded(Some(utxo_2)),
+            contract_id,
+            amt,
+            1000,
+            None,
+        );
+        txids.push(txid_bp_to_bitcoin(tx.txid()));
+    }
+    mine_custom(false, INSTANCE_2, 5);
+
+    let depth = get_height_custom(INSTANCE_2) - fork_height;
+    let mut reorg = ReorgSim::with_chain(chain, INSTANCE_2, depth);
+    let original = reorg.branch(txids.iter().map(|txid| vec![*txid]).collect());
+    reorg.extend(original, 5);
+    let deep = reorg.empty_branch(depth as usize + 5);
+
+    let alloc_1 = if with_transfers {
+        vec![issued_supply - amt]
+    } else {
+        vec![issued_supply]
+    };
+    for _ in 0..2 {
+        reorg.activate(deep);
+        wlt_1.sync_and_update_witnesses(None);
+        wlt_2.sync_and_update_witnesses(None);
+        assert_eq!(wlt_1.get_witness_ord(&utxo.txid), WitnessOrd::Archived);
+        wlt_1.check_allocations(contract_id, schema_id, vec![], false);
+        // without transfers the receiver never gets to know the contract
+        if with_transfers {
+            wlt_2.check_allocations(contract_id, schema_id, vec![], false);
+        }
+
+        reorg.activate(original);
+        wlt_1.sync_and_update_witnesses(None);
+        wlt_2.sync_and_update_witnesses(None);
+        wlt_1.check_allocations(contract_id, schema_id, alloc_1.clone(), false);
+        if with_transfers {
+            wlt_2.check_allocations(contract_id, schema_id, vec![amt], false);
+        }
+    }
+}
+
+#[cfg(not(feature = "altered"))]
+#[rstest]
+#[case(DescriptorType::Wpkh)]
+#[case(DescriptorType::Tr)]
+fn watch_only_external_signer(#[case] wlt_desc: DescriptorType) {
+    println!("wlt_desc {wlt_desc:?}");
+
+    initialize();
+
+    let (mut wlt_1, signer) = BpTestWallet::watch_only(&wlt_desc);
+    let mut wlt_2 = BpTestWallet::with_descriptor(&wlt_desc);
+    assert!(wlt_1.is_watch_only());
+
+    let issued_supply = 600;
+    let utxo = wlt_1.get_utxo(None);
+    let contract_id = wlt_1.issue_nia(issued_supply, Some(&utxo));
+    let schema_id = wlt_1.schema_id(contract_id);
+
+    // invoice payment, PSBT produced by pay
+    let amt_1 = 200;
+    let invoice = wlt_2.invoice(contract_id, schema_id, amt_1, InvoiceType::Witness);
+    let (consignment, tx) = wlt_1.pay_full_external(invoice, None, None, &signer);
+    wlt_1.mine_tx(&txid_bp_to_bitcoin(tx.txid()), false);
+    wlt_2.accept_transfer(consignment, None);
+    wlt_1.sync();
+    wlt_1.check_allocations(
+        contract_id,
+        AssetSchema::Nia,
+        vec![issued_supply - amt_1],
+        false,
+    );
+    wlt_2.check_allocations(contract_id, AssetSchema::Nia, vec![amt_1], false);
+
+    // custom payment, PSBT produced by color_psbt
+    let amt_2 = 100;
+    let input_outpoints = wlt_1
+        .contract_fungible_allocations(contract_id, false)
+        .iter()
+        .map(|fa| fa.seal.outpoint().unwrap())
+        .collect::<Vec<_>>();
+    let coloring_info = ColoringInfo {
+        asset_info_map: HashMap::from([(
+            contract_id,
+            AssetColoringInfo {
+                input_outpoints,
+                assignments: vec![AssetAssignment {
+                    destination: wlt_2.get_secret_seal(None, None).into(),
+                    amount: amt_2,
+                }],
+            },
+        )]),
+        static_blinding: None,
+        nonce: None,
+        close_method: wlt_1.close_method(),
+    };
+    let (consignments, tx, _, tweak_info) =
+        wlt_1.pay_full_flexible_custom(coloring_info, None, None, Some(&signer));
+    wlt_1.mine_tx(&txid_bp_to_bitcoin(tx.txid()), false);
+    for consignment in consignments.into_values() {
+        wlt_2.accept_transfer(consignment, None);
+    }
+    assert!(tweak_info.is_none());
+    wlt_1.sync();
+    wlt_2.sync();
+    wlt_1.check_allocations(
+        contract_id,
+        AssetSchema::Nia,
+        vec![issued_supply - amt_1 - amt_2],
+        false,
+    );
+    wlt_2.check_allocations(contract_id, AssetSchema::Nia, vec![amt_1, amt_2], false);
+
+    // the watch-only wallet keeps receiving and spending its assets
+    wlt_2.send(
+        &mut wlt_1,
+        InvoiceType::Witness,
+        contract_id,
+        amt_1 + amt_2,
+        1000,
+        None,
+    );
+    wlt_1.check_allocations(
+        contract_id,
+        AssetSchema::Nia,
+        vec![issued_supply - amt_1 - amt_2, amt_1 + amt_2],
+        false,
+    );
+}
+
+#[cfg(not(feature = "altered"))]
+#[test]
+fn multisig_cosigning() {
+    initialize();
+
+    let mut wlt_1 = BdkTestWallet::with_descriptor(&DescriptorType::WshMulti);
+    let mut wlt_2 = BdkTestWallet::with_descriptor(&DescriptorType::Wpkh);
+    assert_eq!(wlt_1.cosigners(), 2);
+
+    let issued_supply = 600;
+    let contract_id = wlt_1.issue_nia(issued_supply, None);
+    let schema_id = wlt_1.schema_id(contract_id);
+
+    let amt = 200;
+    let invoice = wlt_2.invoice(contract_id, schema_id, amt, InvoiceType::Witness);
+    wlt_1.sync();
+    let (mut psbt, _, consignment) = wlt_1.pay_invoice(invoice, None, None, None);
+
+    // a single signature is not enough for a 2-of-3
+    wlt_1.sign(&mut psbt);
+    assert!(!wlt_1.can_finalize(&psbt));
+
+    // any of the cosigners can provide the missing one
+    wlt_1.cosign_with(&mut psbt, 1);
+    assert!(wlt_1.can_finalize(&psbt));
+    wlt_1.finalize(&mut psbt);
+    let tx = wlt_1.extract(&psbt);
+    wlt_1.broadcast_tx(&tx);
+    wlt_1.mine_tx(&txid_bp_to_bitcoin(tx.txid()), false);
+    wlt_2.accept_transfer(consignment, None);
+    wlt_1.sync();
+
+    wlt_1.check_allocations(
+        contract_id,
+        AssetSchema::Nia,
+        vec![issued_supply - amt],
+        false,
+    );
+    wlt_2.check_allocations(contract_id, AssetSchema::Nia, vec![amt], false);
+}
@@ -4,6 +4,18 @@ static INIT: Once = Once::new();
 
 pub static INDEXER: OnceLock<Indexer> = OnceLock::new();
 
+pub static CHAIN: OnceLock<Chain> = OnceLock::new();
+
+static DOCKER_CONTEXT: Lazy<Arc<ChainContext>> =
+    Lazy::new(|| Arc::new(ChainContext::new(Arc::new(DockerChain), None)));
+
+// docker services are shared by all tests, reorg tests need the reorg nodes for themselves
+static REORG_NODES: Mutex<()> = Mutex::new(());
+
+thread_local! {
+    static CHAIN_CONTEXT: RefCell<Option<Arc<ChainContext>>> = const { RefCell::new(None) };
+}
+
 #[derive(Clone, Default, PartialEq, Eq, Debug)]
 pub enum Indexer {
     Electrum,
@@ -17,16 +29,41 @@ impl fmt::Display for Indexer {
     }
 }
 
-pub fn initialize() {
-    INIT.call_once(|| {
-        INDEXER.get_or_init(|| match std::env::var("INDEXER") {
-            Ok(val) if val.to_lowercase() == Indexer::Esplora.to_string() => Indexer::Esplora,
-            Ok(val) if val.to_lowercase() == Indexer::Electrum.to_string() => Indexer::Electrum,
-            Err(VarError::NotPresent) => Indexer::Esplora,
-            _ => {
-                panic!("invalid indexer. possible values: `esplora` (default), `electrum`")
-            }
-        });
+#[derive(Clone, Default, PartialEq, Eq, Debug)]
+pub enum Chain {
+    Docker,
+    #[default]
+    Simulated,
+}
+
+impl fmt::Display for Chain {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", format!("{self:?}").to_lowercase())
+    }
+}
+
+/// Source of blocks and coins used by the test helpers
+pub trait ChainBackend: Send + Sync {
+    fn start(&self);
+
+    fn mine(&self, instance: u8, blocks: u32);
+
+    fn height(&self, instance: u8) -> u32;
+
+    fn send_to_address(&self, instance: u8, address: &str, sats: u64) -> String;
+
+    fn connect_nodes(&self, instance_a: u8, instance_b: u8);
+
+    fn disconnect_nodes(&self, instance_a: u8, instance_b: u8);
+
+    fn indexer_url(&self, instance: u8, indexer: &Indexer) -> String;
+}
+
+/// Bitcoin nodes and indexers running in the docker compose services
+pub struct DockerChain;
+
+impl ChainBackend for DockerChain {
+    fn start(&self) {
         if std::env::var("SKIP_INIT").is_ok() {
             println!("skipping services initialization");
             return;
@@ -42,14 +79,303 @@ pub fn initialize() {
             panic!("failed to start test services");
         }
         (INSTANCE_1..=INSTANCE_3).for_each(_wait_indexer_sync);
-    });
+    }
+
+    fn mine(&self, instance: u8, blocks: u32) {
+        _bitcoin_cli_cmd(
+            instance,
+            vec!["-rpcwallet=miner", "-generate", &blocks.to_string()],
+        );
+        _wait_indexer_sync(instance);
+    }
+
+    fn height(&self, instance: u8) -> u32 {
+        _bitcoin_cli_cmd(instance, vec!["getblockcount"])
+            .parse::<u32>()
+            .expect("could not parse blockcount")
+    }
+
+    fn send_to_address(&self, instance: u8, address: &str, sats: u64) -> String {
+        let sats = Sats::from_sats(sats);
+        let btc = format!("{}.{:0>8}", sats.btc_floor(), sats.sats_rem());
+        _bitcoin_cli_cmd(
+            instance,
+            vec!["-rpcwallet=miner", "sendtoaddress", address, &btc],
+        )
+    }
+
+    fn connect_nodes(&self, instance_a: u8, instance_b: u8) {
+        for (instance, other) in [(instance_a, instance_b), (instance_b, instance_a)] {
+            _bitcoin_cli_cmd(instance, vec!["addnode", &_node_addr(other), "onetry"]);
+        }
+    }
+
+    fn disconnect_nodes(&self, instance_a: u8, instance_b: u8) {
+        for (instance, other) in [(instance_a, instance_b), (instance_b, instance_a)] {
+            _bitcoin_cli_cmd(instance, vec!["disconnectnode", &_node_addr(other)]);
+        }
+    }
+
+    fn indexer_url(&self, instance: u8, indexer: &Indexer) -> String {
+        match (indexer, instance) {
+            (Indexer::Electrum, INSTANCE_1) => *ELECTRUM_1_REGTEST_URL,
+            (Indexer::Electrum, INSTANCE_2) => *ELECTRUM_2_REGTEST_URL,
+            (Indexer::Electrum, INSTANCE_3) => *ELECTRUM_3_REGTEST_URL,
+            (Indexer::Esplora, INSTANCE_1) => *ESPLORA_1_REGTEST_URL,
+            (Indexer::Esplora, INSTANCE_2) => *ESPLORA_2_REGTEST_URL,
+            (Indexer::Esplora, INSTANCE_3) => *ESPLORA_3_REGTEST_URL,
+            _ => unreachable!(),
+        }
+        .to_string()
+    }
 }
 
-static MINER: Lazy<RwLock<Miner>> = Lazy::new(|| RwLock::new(Miner { no_mine_count: 0 }));
+/// Chain a test runs against, with its own miner.
+///
+/// With the simulated chain each test thread gets a new context, with its own nodes and
+/// indexers, so tests can run in parallel without interfering with each other. The docker
+/// services are instead shared by all tests, and so is their context: a test stopping mining
+/// blocks the others, which force mining again after a timeout.
+pub struct ChainContext {
+    backend: Arc<dyn ChainBackend>,
+    sim_chain: Option<Arc<SimChain>>,
+    miner: RwLock<Miner>,
+}
+
+impl ChainContext {
+    fn new(backend: Arc<dyn ChainBackend>, sim_chain: Option<Arc<SimChain>>) -> Self {
+        Self {
+            backend,
+            sim_chain,
+            miner: RwLock::new(Miner { stopped_at: vec![] }),
+        }
+    }
+
+    pub fn simulated() -> Self {
+        let backend = SimBackend::new();
+        let sim_chain = backend.chain.clone();
+        Self::new(Arc::new(backend), Some(sim_chain))
+    }
+
+    pub fn backend(&self) -> &dyn ChainBackend {
+        &*self.backend
+    }
+
+    /// Whether the chain is used only by this context
+    pub fn is_isolated(&self) -> bool {
+        self.sim_chain.is_some()
+    }
+
+    pub fn sim_chain(&self) -> Arc<SimChain> {
+        self.sim_chain
+            .clone()
+            .expect("this requires CHAIN=simulated")
+    }
+
+    /// Error out if mining is stopped more than `allowed` times on an isolated chain, since
+    /// nobody else can resume it
+    fn check_stops(&self, miner: &Miner, allowed: usize) -> Result<(), MiningError> {
+        if self.is_isolated() && miner.stopped_at.len() > allowed {
+            return Err(MiningError::NeverResumed {
+                stop_mining_at: miner.stopped_at[allowed..]
+                    .iter()
+                    .map(|location| location.to_string())
+                    .collect(),
+            });
+        }
+        Ok(())
+    }
+
+    pub fn mine(&self, resume: bool, instance: u8, blocks: u32) -> Result<(), MiningError> {
+        let t_0 = OffsetDateTime::now_utc();
+        if resume {
+            self.resume_mining();
+        }
+        loop {
+            if (OffsetDateTime::now_utc() - t_0).as_seconds_f32() > 120.0 {
+                println!("forcibly breaking mining wait");
+                self.resume_mining();
+            }
+            let miner = self.miner.read().unwrap();
+            if miner.stopped_at.is_empty() {
+                self.backend.mine(instance, blocks);
+                return Ok(());
+            }
+            self.check_stops(&miner, 0)?;
+            drop(miner);
+            std::thread::sleep(Duration::from_millis(500));
+        }
+    }
+
+    pub fn mine_but_no_resume(&self, instance: u8, blocks: u32) -> Result<(), MiningError> {
+        let t_0 = OffsetDateTime::now_utc();
+        loop {
+            if (OffsetDateTime::now_utc() - t_0).as_seconds_f32() > 120.0 {
+                println!("forcibly breaking mining wait");
+                self.resume_mining();
+            }
+            let miner = self.miner.write().unwrap();
+            if miner.stopped_at.len() <= 1 {
+                self.backend.mine(instance, blocks);
+                return Ok(());
+            }
+            self.check_stops(&miner, 1)?;
+            drop(miner);
+            std::thread::sleep(Duration::from_millis(500));
+        }
+    }
+
+    #[track_caller]
+    pub fn stop_mining(&self) {
+        self.miner.write().unwrap().stop_mining()
+    }
+
+    #[track_caller]
+    pub fn stop_mining_when_alone(&self) -> Result<(), MiningError> {
+        let t_0 = OffsetDateTime::now_utc();
+        loop {
+            if (OffsetDateTime::now_utc() - t_0).as_seconds_f32() > 120.0 {
+                println!("forcibly breaking stop wait");
+                self.stop_mining();
+            }
+            let mut miner = self.miner.write().unwrap();
+            if miner.stopped_at.is_empty() {
+                miner.stop_mining();
+                return Ok(());
+            }
+            self.check_stops(&miner, 0)?;
+            drop(miner);
+            std::thread::sleep(Duration::from_millis(500));
+        }
+    }
+
+    pub fn resume_mining(&self) {
+        self.miner.write().unwrap().resume_mining()
+    }
+
+    pub fn height(&self, instance: u8) -> u32 {
+        self.backend.height(instance)
+    }
+
+    pub fn indexer_url(&self, instance: u8, network: Network) -> String {
+        match (INDEXER.get().unwrap(), network) {
+            (Indexer::Electrum, Network::Bitcoin) => ELECTRUM_MAINNET_URL.to_string(),
+            (Indexer::Esplora, Network::Bitcoin) => ESPLORA_MAINNET_URL.to_string(),
+            (indexer, Network::Regtest) => self.backend.indexer_url(instance, indexer),
+            _ => unreachable!(),
+        }
+    }
+
+    pub fn fund_wallet(&self, address: String, sats: Option<u64>, instance: u8) -> String {
+        let txid = self
+            .backend
+            .send_to_address(instance, &address, sats.unwrap_or(100_000_000));
+        self.mine(false, instance, 1).unwrap();
+        txid
+    }
+
+    /// Connect the nodes of two instances, waiting for them to sync
+    pub fn connect_instances(&self, instance_a: u8, instance_b: u8) {
+        self.backend.connect_nodes(instance_a, instance_b);
+        let t_0 = OffsetDateTime::now_utc();
+        loop {
+            if (OffsetDateTime::now_utc() - t_0).as_seconds_f32() > 20.0 {
+                panic!("nodes are not syncing with each other")
+            }
+            if self.height(instance_a) == self.height(instance_b) {
+                break;
+            }
+            std::thread::sleep(Duration::from_millis(500));
+        }
+    }
+
+    pub fn disconnect_instances(&self, instance_a: u8, instance_b: u8) {
+        self.backend.disconnect_nodes(instance_a, instance_b);
+    }
+}
+
+/// Chain context of the current test thread
+pub fn chain_context() -> Arc<ChainContext> {
+    CHAIN_CONTEXT.with_borrow_mut(|ctx| {
+        ctx.get_or_insert_with(|| match CHAIN.get().unwrap() {
+            Chain::Docker => DOCKER_CONTEXT.clone(),
+            Chain::Simulated => Arc::new(ChainContext::simulated()),
+        })
+        .clone()
+    })
+}
+
+pub fn chain_backend() -> Arc<dyn ChainBackend> {
+    chain_context().backend.clone()
+}
+
+/// Run the rest of the test on a new simulated chain of its own, whatever the configured
+/// [`CHAIN`], returning it. This must be called before creating any wallet.
+pub fn use_simulated_chain() -> Arc<SimChain> {
+    let ctx = Arc::new(ChainContext::simulated());
+    let chain = ctx.sim_chain();
+    CHAIN_CONTEXT.with_borrow_mut(|c| *c = Some(ctx));
+    chain
+}
+
+/// Simulated chain of the current test, available only when using the simulated backend
+pub fn sim_chain() -> Arc<SimChain> {
+    chain_context().sim_chain()
+}
+
+/// Get exclusive use of the reorg nodes (instances 2 and 3) for the rest of the test, which is
+/// needed only when they are shared with other tests
+pub fn lock_reorg_nodes() -> Option<MutexGuard<'static, ()>> {
+    if chain_context().is_isolated() {
+        return None;
+    }
+    Some(REORG_NODES.lock().unwrap_or_else(|e| e.into_inner()))
+}
+
+pub fn initialize() {
+    INIT.call_once(|| {
+        INDEXER.get_or_init(|| match std::env::var("INDEXER") {
+            Ok(val) if val.to_lowercase() == Indexer::Esplora.to_string() => Indexer::Esplora,
+            Ok(val) if val.to_lowercase() == Indexer::Electrum.to_string() => Indexer::Electrum,
+            Err(VarError::NotPresent) => Indexer::Esplora,
+            _ => {
+                panic!("invalid indexer. possible values: `esplora` (default), `electrum`")
+            }
+        });
+        CHAIN.get_or_init(|| match std::env::var("CHAIN") {
+            Ok(val) if val.to_lowercase() == Chain::Docker.to_string() => Chain::Docker,
+            Ok(val) if val.to_lowercase() == Chain::Simulated.to_string() => Chain::Simulated,
+            Err(VarError::NotPresent) => Chain::Simulated,
+            _ => {
+                panic!("invalid chain. possible values: `simulated` (default), `docker`")
+            }
+        });
+        chain_backend().start();
+    });
+}
 
 #[derive(Clone, Debug)]
 pub struct Miner {
-    no_mine_count: u32,
+    /// Where each pending [`stop_mining`] was called
+    stopped_at: Vec<&'static std::panic::Location<'static>>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum MiningError {
+    /// Mining on an isolated chain waits for these `stop_mining()` calls to be resumed
+    NeverResumed { stop_mining_at: Vec<String> },
+}
+
+impl fmt::Display for MiningError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::NeverResumed { stop_mining_at } => write!(
+                f,
+                "mining was stopped by stop_mining() at {} and never resumed",
+                stop_mining_at.join(", ")
+            ),
+        }
+    }
 }
 
 fn _service_base_name() -> String {
@@ -114,30 +440,13 @@ fn _bitcoin_cli_cmd(instance: u8, args: Vec<&str>) -> String {
 }
 
 impl Miner {
-    fn mine(&self, instance: u8, blocks: u32) -> bool {
-        if self.no_mine_count > 0 {
-            return false;
-        }
-        self.force_mine(instance, blocks)
-    }
-
-    fn force_mine(&self, instance: u8, blocks: u32) -> bool {
-        _bitcoin_cli_cmd(
-            instance,
-            vec!["-rpcwallet=miner", "-generate", &blocks.to_string()],
-        );
-        _wait_indexer_sync(instance);
-        true
-    }
-
+    #[track_caller]
     fn stop_mining(&mut self) {
-        self.no_mine_count += 1;
+        self.stopped_at.push(std::panic::Location::caller());
     }
 
     fn resume_mining(&mut self) {
-        if self.no_mine_count > 0 {
-            self.no_mine_count -= 1;
-        }
+        self.stopped_at.pop();
     }
 }
 
@@ -146,21 +455,7 @@ pub fn mine(resume: bool) {
 }
 
 pub fn mine_custom(resume: bool, instance: u8, blocks: u32) {
-    let t_0 = OffsetDateTime::now_utc();
-    if resume {
-        resume_mining();
-    }
-    loop {
-        if (OffsetDateTime::now_utc() - t_0).as_seconds_f32() > 120.0 {
-            println!("forcibly breaking mining wait");
-            resume_mining();
-        }
-        let mined = MINER.read().as_ref().unwrap().mine(instance, blocks);
-        if mined {
-            break;
-        }
-        std::thread::sleep(Duration::from_millis(500));
-    }
+    chain_context().mine(resume, instance, blocks).unwrap();
 }
 
 pub fn mine_but_no_resume() {
@@ -168,77 +463,44 @@ pub fn mine_but_no_resume() {
 }
 
 pub fn mine_but_no_resume_custom(instance: u8, blocks: u32) {
-    let t_0 = OffsetDateTime::now_utc();
-    loop {
-        if (OffsetDateTime::now_utc() - t_0).as_seconds_f32() > 120.0 {
-            println!("forcibly breaking mining wait");
-            resume_mining();
-        }
-        let miner = MINER.write().unwrap();
-        if miner.no_mine_count <= 1 {
-            miner.force_mine(instance, blocks);
-            break;
-        }
-        drop(miner);
-        std::thread::sleep(Duration::from_millis(500));
-    }
+    chain_context()
+        .mine_but_no_resume(instance, blocks)
+        .unwrap();
 }
 
+#[track_caller]
 pub fn stop_mining() {
-    MINER.write().unwrap().stop_mining()
+    chain_context().stop_mining()
 }
 
+#[track_caller]
 pub fn stop_mining_when_alone() {
-    let t_0 = OffsetDateTime::now_utc();
-    loop {
-        if (OffsetDateTime::now_utc() - t_0).as_seconds_f32() > 120.0 {
-            println!("forcibly breaking stop wait");
-            stop_mining();
-        }
-        let mut miner = MINER.write().unwrap();
-        if miner.no_mine_count == 0 {
-            miner.stop_mining();
-            break;
-        }
-        drop(miner);
-        std::thread::sleep(Duration::from_millis(500));
-    }
+    chain_context().stop_mining_when_alone().unwrap()
 }
 
 pub fn resume_mining() {
-    MINER.write().unwrap().resume_mining()
+    chain_context().resume_mining()
 }
 
-fn _get_connection_tuple() -> Vec<(u8, String)> {
-    let serive_base_name = _service_base_name();
-    vec![
-        (INSTANCE_3, format!("{serive_base_name}_{INSTANCE_2}:18444")),
-        (INSTANCE_2, format!("{serive_base_name}_{INSTANCE_3}:18444")),
-    ]
+fn _node_addr(instance: u8) -> String {
+    format!("{}_{instance}:18444", _service_base_name())
 }
 
 pub fn connect_reorg_nodes() {
-    for (instance, node_addr) in _get_connection_tuple() {
-        _bitcoin_cli_cmd(instance, vec!["addnode", &node_addr, "onetry"]);
-    }
-    let t_0 = OffsetDateTime::now_utc();
-    loop {
-        if (OffsetDateTime::now_utc() - t_0).as_seconds_f32() > 20.0 {
-            panic!("nodes are not syncing with each other")
-        }
-        let height_2 = get_height_custom(INSTANCE_2);
-        let height_3 = get_height_custom(INSTANCE_3);
-        if height_2 == height_3 {
-            break;
-        }
-        std::thread::sleep(Duration::from_millis(500));
-    }
+    connect_instances(INSTANCE_2, INSTANCE_3);
 }
 
 pub fn disconnect_reorg_nodes() {
-    for (instance, node_addr) in _get_connection_tuple() {
-        _bitcoin_cli_cmd(instance, vec!["disconnectnode", &node_addr]);
-    }
+    disconnect_instances(INSTANCE_2, INSTANCE_3);
+}
+
+/// Connect the nodes of two instances, waiting for them to sync
+pub fn connect_instances(instance_a: u8, instance_b: u8) {
+    chain_context().connect_instances(instance_a, instance_b);
+}
+
+pub fn disconnect_instances(instance_a: u8, instance_b: u8) {
+    chain_context().disconnect_instances(instance_a, instance_b);
 }
 
 pub fn get_height() -> u32 {
@@ -246,24 +508,11 @@ pub fn get_height() -> u32 {
 }
 
 pub fn get_height_custom(instance: u8) -> u32 {
-    _bitcoin_cli_cmd(instance, vec!["getblockcount"])
-        .parse::<u32>()
-        .expect("could not parse blockcount")
+    chain_context().height(instance)
 }
 
 pub fn indexer_url(instance: u8, network: Network) -> String {
-    match (INDEXER.get().unwrap(), network, instance) {
-        (Indexer::Electrum, Network::Bitcoin, _) => ELECTRUM_MAINNET_URL,
-        (Indexer::Electrum, Network::Regtest, INSTANCE_1) => &ELECTRUM_1_REGTEST_URL,
-        (Indexer::Electrum, Network::Regtest, INSTANCE_2) => &ELECTRUM_2_REGTEST_URL,
-        (Indexer::Electrum, Network::Regtest, INSTANCE_3) => &ELECTRUM_3_REGTEST_URL,
-        (Indexer::Esplora, Network::Bitcoin, _) => ESPLORA_MAINNET_URL,
-        (Indexer::Esplora, Network::Regtest, INSTANCE_1) => &ESPLORA_1_REGTEST_URL,
-        (Indexer::Esplora, Network::Regtest, INSTANCE_2) => &ESPLORA_2_REGTEST_URL,
-        (Indexer::Esplora, Network::Regtest, INSTANCE_3) => &ESPLORA_3_REGTEST_URL,
-        _ => unreachable!(),
-    }
-    .to_string()
+    chain_context().indexer_url(instance, network)
 }
 
 pub enum IndexerClient {
@@ -315,17 +564,6 @@ fn _wait_indexer_sync(instance: u8) {
     }
 }
 
-fn _send_to_address(address: &str, sats: Option<u64>, instance: u8) -> String {
-    let sats = Sats::from_sats(sats.unwrap_or(100_000_000));
-    let btc = format!("{}.{:0>8}", sats.btc_floor(), sats.sats_rem());
-    _bitcoin_cli_cmd(
-        instance,
-        vec!["-rpcwallet=miner", "sendtoaddress", address, &btc],
-    )
-}
-
 pub fn fund_wallet(address: String, sats: Option<u64>, instance: u8) -> String {
-    let txid = _send_to_address(&address, sats, instance);
-    mine_custom(false, instance, 1);
-    txid
+    chain_context().fund_wallet(address, sats, instance)
 }
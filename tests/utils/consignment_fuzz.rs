@@ -0,0 +1,305 @@
+use super::*;
+
+/// Maximum number of mutations applied to a fixture consignment in a single fuzz run
+pub const MAX_FUZZ_MUTATIONS: usize = 4;
+
+/// Reader turning the raw fuzzer input into mutation choices, yielding zeros once exhausted
+pub struct FuzzInput<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> FuzzInput<'a> {
+    pub fn new(data: &'a [u8]) -> Self {
+        Self { data, pos: 0 }
+    }
+
+    pub fn u8(&mut self) -> u8 {
+        let byte = self.data.get(self.pos).copied().unwrap_or_default();
+        self.pos += 1;
+        byte
+    }
+
+    pub fn u32(&mut self) -> u32 {
+        u32::from_le_bytes([self.u8(), self.u8(), self.u8(), self.u8()])
+    }
+
+    pub fn u64(&mut self) -> u64 {
+        ((self.u32() as u64) << 32) | self.u32() as u64
+    }
+
+    /// Index in `0..len`, `len` must not be zero
+    pub fn choose(&mut self, len: usize) -> usize {
+        self.u32() as usize % len
+    }
+}
+
+/// Commitments a consignment is bound to, validation must never succeed if any of them changed
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ConsignmentCommitments {
+    pub contract_id: ContractId,
+    pub bundles: BTreeSet<(Txid, BundleId)>,
+    pub opids: BTreeSet<OpId>,
+}
+
+impl ConsignmentCommitments {
+    pub fn of(consignment: &Transfer) -> Self {
+        let mut opids = bset![consignment.genesis.id()];
+        for wbundle in consignment.bundles.iter() {
+            for KnownTransition { opid, transition } in wbundle.bundle.known_transitions.iter() {
+                opids.insert(*opid);
+                opids.insert(transition.id());
+            }
+        }
+        Self {
+            contract_id: consignment.contract_id(),
+            bundles: consignment
+                .bundles
+                .iter()
+                .map(|wb| (wb.witness_id(), wb.bundle.bundle_id()))
+                .collect(),
+            opids,
+        }
+    }
+
+    /// Whether these commitments are a subset of the given ones (i.e. data was only dropped)
+    pub fn is_subset(&self, base: &Self) -> bool {
+        self.contract_id == base.contract_id
+            && self.bundles.is_subset(&base.bundles)
+            && self.opids.is_subset(&base.opids)
+    }
+}
+
+struct FuzzFixture {
+    scenario: Scenario,
+    json: Value,
+    strict: Vec<u8>,
+    resolver: MockResolver,
+    validation_config: ValidationConfig,
+    commitments: ConsignmentCommitments,
+}
+
+impl FuzzFixture {
+    fn load(scenario: Scenario) -> Self {
+        let json = scenario.consignment_json();
+        let consignment = serde_json::from_value::<Transfer>(json.clone()).unwrap();
+        let trusted_typesystem = AssetSchema::from(consignment.schema_id()).types();
+        Self {
+            scenario,
+            json,
+            strict: scenario.consignment_strict(),
+            resolver: scenario.resolver(),
+            validation_config: ValidationConfig {
+                chain_net: ChainNet::BitcoinRegtest,
+                trusted_typesystem,
+                ..Default::default()
+            },
+            commitments: ConsignmentCommitments::of(&consignment),
+        }
+    }
+}
+
+thread_local! {
+    static FUZZ_FIXTURES: Vec<FuzzFixture> = Scenario::iter().map(FuzzFixture::load).collect();
+}
+
+/// Paths of all the entries in the given JSON value, root excluded
+pub fn json_paths(root: &Value) -> Vec<Path> {
+    fn collect(value: &Value, path: &mut Path, paths: &mut Vec<Path>) {
+        let children: Vec<(Step, &Value)> = match value {
+            Value::Object(map) => map.iter().map(|(k, v)| (Step::Key(k.clone()), v)).collect(),
+            Value::Array(arr) => arr
+                .iter()
+                .enumerate()
+                .map(|(i, v)| (Step::Idx(i), v))
+                .collect(),
+            _ => return,
+        };
+        for (step, child) in children {
+            path.push(step);
+            paths.push(path.clone());
+            collect(child, path, paths);
+            path.pop();
+        }
+    }
+    let mut paths = vec![];
+    collect(root, &mut vec![], &mut paths);
+    paths
+}
+
+fn is_hex(s: &str) -> bool {
+    !s.is_empty() && s.len().is_multiple_of(2) && s.chars().all(|c| c.is_ascii_hexdigit())
+}
+
+/// Apply a single mutation to a random entry of the given JSON value
+pub fn mutate_json(root: &mut Value, input: &mut FuzzInput) {
+    let paths = json_paths(root);
+    if paths.is_empty() {
+        return;
+    }
+    let path = &paths[input.choose(paths.len())];
+    let entry = get_entry_at_path_mut(root, path);
+    match entry {
+        Value::Null => *entry = json!(0),
+        Value::Bool(b) => *b = !*b,
+        Value::Number(n) => {
+            let n = n.as_u64().unwrap_or_default();
+            let mutated = match input.choose(5) {
+                0 => 0,
+                1 => n.wrapping_add(1),
+                2 => n.wrapping_sub(1),
+                3 => u64::MAX,
+                _ => input.u64(),
+            };
+            *entry = json!(mutated);
+        }
+        Value::String(s) if is_hex(s) => {
+            // flip a nibble, keeping the string valid hex
+            let idx = input.choose(s.len());
+            let nibble = u8::from_str_radix(&s[idx..idx + 1], 16).unwrap();
+            let flipped = nibble ^ (1 + input.choose(15) as u8);
+            s.replace_range(idx..idx + 1, &format!("{flipped:x}"));
+        }
+        Value::String(s) => match input.choose(3) {
+            0 => s.clear(),
+            1 => s.push(char::from(b'a' + input.choose(26) as u8)),
+            _ => *s = s.chars().rev().collect(),
+        },
+        Value::Array(arr) if arr.is_empty() => {}
+        Value::Array(arr) => {
+            let idx = input.choose(arr.len());
+            match input.choose(3) {
+                0 => {
+                    arr.remove(idx);
+                }
+                1 => arr.insert(idx, arr[idx].clone()),
+                _ => {
+                    let other = input.choose(arr.len());
+                    arr.swap(idx, other);
+                }
+            }
+        }
+        Value::Object(map) if map.is_empty() => {}
+        Value::Object(map) => {
+            let key = map.keys().nth(input.choose(map.len())).unwrap().clone();
+            map.remove(&key);
+        }
+    }
+}
+
+/// Apply a single mutation to the given strict-encoded data
+pub fn mutate_strict(data: &mut Vec<u8>, input: &mut FuzzInput) {
+    if data.is_empty() {
+        return;
+    }
+    let idx = input.choose(data.len());
+    match input.choose(4) {
+        0 => data[idx] ^= 1 << input.choose(8),
+        1 => data[idx] = input.u8(),
+        2 => {
+            let end = (idx + 1 + input.choose(4)).min(data.len());
+            data.drain(idx..end);
+        }
+        _ => {
+            let end = (idx + 1 + input.choose(4)).min(data.len());
+            let chunk = data[idx..end].to_vec();
+            data.splice(idx..idx, chunk);
+        }
+    }
+}
+
+/// In-memory raw reader for the strict decoder
+struct RawCursor(io::Cursor<Vec<u8>>);
+
+impl RawCursor {
+    fn remaining(&self) -> usize {
+        self.0.get_ref().len() - self.0.position() as usize
+    }
+}
+
+impl ReadRaw for RawCursor {
+    fn read_raw<const MAX_LEN: usize>(&mut self, len: usize) -> io::Result<Vec<u8>> {
+        if len > self.remaining() {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        let mut buf = vec![0u8; len];
+        self.0.read_exact(&mut buf)?;
+        Ok(buf)
+    }
+
+    fn read_raw_array<const LEN: usize>(&mut self) -> io::Result<[u8; LEN]> {
+        let mut buf = [0u8; LEN];
+        self.0.read_exact(&mut buf)?;
+        Ok(buf)
+    }
+}
+
+/// Raw reader rejecting collection lengths larger than the data left, since the decoder
+/// allocates the capacity of a collection before reading its items
+struct FuzzReader(RawCursor);
+
+impl ReadRaw for FuzzReader {
+    fn read_raw<const MAX_LEN: usize>(&mut self, len: usize) -> io::Result<Vec<u8>> {
+        self.0.read_raw::<MAX_LEN>(len)
+    }
+
+    fn read_raw_array<const LEN: usize>(&mut self) -> io::Result<[u8; LEN]> {
+        self.0.read_raw_array::<LEN>()
+    }
+
+    fn read_raw_len<const MAX_LEN: usize>(&mut self) -> Result<usize, DecodeError> {
+        let len = self.0.read_raw_len::<MAX_LEN>()?;
+        if len > self.0.remaining() {
+            return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into());
+        }
+        Ok(len)
+    }
+}
+
+/// Strict-decode a consignment from data which may carry bogus collection lengths
+fn decode_fuzzed_consignment(data: Vec<u8>) -> Option<Transfer> {
+    let mut reader = StrictReader::with(FuzzReader(RawCursor(io::Cursor::new(data))));
+    let consignment = Transfer::strict_decode(&mut reader).ok()?;
+    // like `from_strict_serialized`, trailing data makes the consignment invalid
+    (reader.unbox().0.remaining() == 0).then_some(consignment)
+}
+
+/// Mutate one of the fixture consignments as driven by the given input, then check that
+/// validation doesn't panic and doesn't succeed for a consignment with changed commitments
+pub fn fuzz_consignment(data: &[u8]) {
+    let mut input = FuzzInput::new(data);
+    FUZZ_FIXTURES.with(|fixtures| {
+        let fixture = &fixtures[input.choose(fixtures.len())];
+        let mutations = 1 + input.choose(MAX_FUZZ_MUTATIONS);
+        let strict = !input.u8().is_multiple_of(2);
+        let consignment = if strict {
+            let mut bytes = fixture.strict.clone();
+            for _ in 0..mutations {
+                mutate_strict(&mut bytes, &mut input);
+            }
+            decode_fuzzed_consignment(bytes)
+        } else {
+            let mut json = fixture.json.clone();
+            for _ in 0..mutations {
+                mutate_json(&mut json, &mut input);
+            }
+            serde_json::from_value::<Transfer>(json).ok()
+        };
+        // mutations breaking the encoding are rejected before validation
+        let Some(consignment) = consignment else {
+            return;
+        };
+        let commitments = ConsignmentCommitments::of(&consignment);
+        let Ok(valid) = consignment.validate(&fixture.resolver, &fixture.validation_config) else {
+            return;
+        };
+        if valid.validation_status().validity() == Validity::Valid {
+            assert!(
+                commitments.is_subset(&fixture.commitments),
+                "scenario {} ({}): consignment with changed commitments is valid",
+                fixture.scenario,
+                if strict { "strict" } else { "json" },
+            );
+        }
+    });
+}
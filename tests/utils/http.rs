@@ -0,0 +1,374 @@
+use super::*;
+
+#[derive(Clone, Debug)]
+pub struct HttpRequest {
+    pub method: String,
+    pub path: String,
+    pub query: String,
+    pub content_type: String,
+    pub body: Vec<u8>,
+}
+
+impl HttpRequest {
+    /// Path segments, without leading and trailing slashes
+    pub fn segments(&self) -> Vec<&str> {
+        self.path
+            .trim_matches('/')
+            .split('/')
+            .filter(|s| !s.is_empty())
+            .collect()
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct HttpResponse {
+    pub status: u16,
+    pub content_type: &'static str,
+    pub body: Vec<u8>,
+}
+
+impl HttpResponse {
+    pub fn text(body: impl ToString) -> Self {
+        Self {
+            status: 200,
+            content_type: "text/plain",
+            body: body.to_string().into_bytes(),
+        }
+    }
+
+    pub fn json(value: &Value) -> Self {
+        Self {
+            status: 200,
+            content_type: "application/json",
+            body: serde_json::to_vec(value).unwrap(),
+        }
+    }
+
+    pub fn bytes(body: Vec<u8>) -> Self {
+        Self {
+            status: 200,
+            content_type: "application/octet-stream",
+            body,
+        }
+    }
+
+    pub fn error(status: u16, message: impl ToString) -> Self {
+        Self {
+            status,
+            ..Self::text(message)
+        }
+    }
+
+    pub fn not_found() -> Self {
+        Self::error(404, "not found")
+    }
+}
+
+fn reason_phrase(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        400 => "Bad Request",
+        404 => "Not Found",
+        500 => "Internal Server Error",
+        503 => "Service Unavailable",
+        _ => "Unknown",
+    }
+}
+
+pub fn read_http_request(stream: &TcpStream) -> Option<HttpRequest> {
+    let mut reader = BufReader::new(stream);
+    let mut line = String::new();
+    reader.read_line(&mut line).ok()?;
+    let mut parts = line.split_whitespace();
+    let method = parts.next()?.to_string();
+    let target = parts.next()?.to_string();
+    let mut content_length = 0;
+    let mut content_type = String::new();
+    loop {
+        let mut header = String::new();
+        reader.read_line(&mut header).ok()?;
+        let header = header.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        let Some((name, value)) = header.split_once(':') else {
+            continue;
+        };
+        if name.eq_ignore_ascii_case("content-length") {
+            content_length = value.trim().parse().ok()?;
+        } else if name.eq_ignore_ascii_case("content-type") {
+            content_type = value.trim().to_string();
+        }
+    }
+    let mut body = vec![0; content_length];
+    reader.read_exact(&mut body).ok()?;
+    let (path, query) = target.split_once('?').unwrap_or((&target, ""));
+    Some(HttpRequest {
+        method,
+        path: path.to_string(),
+        query: query.to_string(),
+        content_type,
+        body,
+    })
+}
+
+pub fn write_http_response(stream: &mut TcpStream, response: &HttpResponse) {
+    let head = format!(
+        "HTTP/1.1 {} {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        response.status,
+        reason_phrase(response.status),
+        response.content_type,
+        response.body.len()
+    );
+    let _ = stream.write_all(head.as_bytes());
+    let _ = stream.write_all(&response.body);
+    let _ = stream.flush();
+}
+
+fn read_chunked(reader: &mut impl BufRead) -> std::io::Result<Vec<u8>> {
+    let mut body = vec![];
+    loop {
+        let mut size = String::new();
+        reader.read_line(&mut size)?;
+        let size = usize::from_str_radix(size.trim(), 16)
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
+        let mut chunk = vec![0; size + 2];
+        reader.read_exact(&mut chunk)?;
+        if size == 0 {
+            return Ok(body);
+        }
+        body.extend(&chunk[..size]);
+    }
+}
+
+/// Body of a `multipart/form-data` request, as used for file uploads
+#[derive(Clone, Debug)]
+pub struct MultipartForm {
+    boundary: String,
+    body: Vec<u8>,
+}
+
+impl MultipartForm {
+    pub fn new() -> Self {
+        Self {
+            // not drawn from the test RNG, so that uploads don't shift the seeded values
+            boundary: format!("rgb-tests-{:016x}", rand::random::<u64>()),
+            body: vec![],
+        }
+    }
+
+    fn part(mut self, disposition: String, value: &[u8]) -> Self {
+        let head = format!(
+            "--{}\r\nContent-Disposition: {disposition}\r\n\r\n",
+            self.boundary
+        );
+        self.body.extend(head.as_bytes());
+        self.body.extend(value);
+        self.body.extend(b"\r\n");
+        self
+    }
+
+    pub fn text(self, name: &str, value: &str) -> Self {
+        self.part(format!("form-data; name=\"{name}\""), value.as_bytes())
+    }
+
+    pub fn file(self, name: &str, filename: &str, file: &[u8]) -> Self {
+        self.part(
+            format!("form-data; name=\"{name}\"; filename=\"{filename}\""),
+            file,
+        )
+    }
+
+    pub fn content_type(&self) -> String {
+        format!("multipart/form-data; boundary={}", self.boundary)
+    }
+
+    pub fn into_body(mut self) -> Vec<u8> {
+        self.body
+            .extend(format!("--{}--\r\n", self.boundary).as_bytes());
+        self.body
+    }
+}
+
+impl Default for MultipartForm {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Split a `multipart/form-data` body into its parts, by name
+pub fn parse_multipart(content_type: &str, body: &[u8]) -> Option<HashMap<String, Vec<u8>>> {
+    let boundary = content_type
+        .strip_prefix("multipart/form-data")?
+        .split(';')
+        .find_map(|p| p.trim().strip_prefix("boundary="))?
+        .trim_matches('"');
+    let delimiter = format!("--{boundary}").into_bytes();
+    let find = |haystack: &[u8], needle: &[u8]| {
+        haystack
+            .windows(needle.len())
+            .position(|window| window == needle)
+    };
+    let mut parts = HashMap::new();
+    let mut rest = &body[find(body, &delimiter)? + delimiter.len()..];
+    // the last delimiter is followed by "--"
+    while !rest.starts_with(b"--") {
+        let end = find(rest, &delimiter)?;
+        let part = rest[..end].strip_prefix(b"\r\n")?.strip_suffix(b"\r\n")?;
+        let head_end = find(part, b"\r\n\r\n")?;
+        let head = std::str::from_utf8(&part[..head_end]).ok()?;
+        let name = head
+            .lines()
+            .find(|l| {
+                l.to_lowercase()
+                    .starts_with("content-disposition: form-data")
+            })?
+            .split(';')
+            .find_map(|p| p.trim().strip_prefix("name="))?
+            .trim_matches('"');
+        parts.insert(name.to_string(), part[head_end + 4..].to_vec());
+        rest = &rest[end + delimiter.len()..];
+    }
+    Some(parts)
+}
+
+/// Minimal blocking HTTP client, only supporting plain http URLs
+pub fn http_call(method: &str, url: &str, body: &[u8]) -> std::io::Result<HttpResponse> {
+    http_call_timeout(method, url, None, body, None)
+}
+
+/// Same as [`http_call`], with the given content type, failing if the server doesn't answer
+/// within the given time
+pub fn http_call_timeout(
+    method: &str,
+    url: &str,
+    content_type: Option<&str>,
+    body: &[u8],
+    timeout: Option<Duration>,
+) -> std::io::Result<HttpResponse> {
+    let invalid = |msg: &str| std::io::Error::new(std::io::ErrorKind::InvalidData, msg.to_string());
+    let url = url
+        .strip_prefix("http://")
+        .ok_or_else(|| invalid("unsupported URL scheme"))?;
+    let (host, path) = url.split_once('/').unwrap_or((url, ""));
+    let mut stream = TcpStream::connect(host)?;
+    stream.set_read_timeout(timeout)?;
+    stream.set_write_timeout(timeout)?;
+    let content_type = content_type
+        .map(|ct| format!("Content-Type: {ct}\r\n"))
+        .unwrap_or_default();
+    let head = format!(
+        "{method} /{path} HTTP/1.1\r\nHost: {host}\r\n{content_type}Content-Length: {}\r\nConnection: close\r\n\r\n",
+        body.len()
+    );
+    stream.write_all(head.as_bytes())?;
+    stream.write_all(body)?;
+    let mut reader = BufReader::new(stream);
+    let mut status_line = String::new();
+    reader.read_line(&mut status_line)?;
+    let status = status_line
+        .split_whitespace()
+        .nth(1)
+        .and_then(|s| s.parse().ok())
+        .ok_or_else(|| invalid("invalid status line"))?;
+    let mut content_type = "text/plain";
+    let mut content_length = None;
+    let mut chunked = false;
+    loop {
+        let mut header = String::new();
+        reader.read_line(&mut header)?;
+        let header = header.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        let Some((name, value)) = header.split_once(':') else {
+            continue;
+        };
+        let value = value.trim().to_lowercase();
+        match name.to_lowercase().as_str() {
+            "content-length" => content_length = value.parse().ok(),
+            "transfer-encoding" => chunked = value == "chunked",
+            "content-type" if value.starts_with("application/json") => {
+                content_type = "application/json"
+            }
+            "content-type" if value.starts_with("application/octet-stream") => {
+                content_type = "application/octet-stream"
+            }
+            _ => {}
+        }
+    }
+    let body = if chunked {
+        read_chunked(&mut reader)?
+    } else if let Some(len) = content_length {
+        let mut body = vec![0; len];
+        reader.read_exact(&mut body)?;
+        body
+    } else {
+        let mut body = vec![];
+        reader.read_to_end(&mut body)?;
+        body
+    };
+    Ok(HttpResponse {
+        status,
+        content_type,
+        body,
+    })
+}
+
+/// Server listening on a random local port, handling each connection in its own thread
+pub struct LocalServer {
+    addr: SocketAddr,
+    stopped: Arc<AtomicBool>,
+}
+
+impl LocalServer {
+    pub fn tcp(handler: impl Fn(TcpStream) + Send + Sync + 'static) -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let stopped = Arc::new(AtomicBool::new(false));
+        let handler = Arc::new(handler);
+        let stopped_clone = stopped.clone();
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                if stopped_clone.load(Ordering::SeqCst) {
+                    break;
+                }
+                let Ok(stream) = stream else {
+                    continue;
+                };
+                let handler = handler.clone();
+                std::thread::spawn(move || handler(stream));
+            }
+        });
+        Self { addr, stopped }
+    }
+
+    pub fn http(handler: impl Fn(&HttpRequest) -> HttpResponse + Send + Sync + 'static) -> Self {
+        Self::tcp(move |mut stream| {
+            if let Some(request) = read_http_request(&stream) {
+                let response = handler(&request);
+                write_http_response(&mut stream, &response);
+            }
+        })
+    }
+
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    pub fn http_url(&self) -> String {
+        format!("http://{}", self.addr)
+    }
+
+    pub fn shutdown(&self) {
+        self.stopped.store(true, Ordering::SeqCst);
+        // wake up the listener so it notices the stop flag
+        let _ = TcpStream::connect(self.addr);
+    }
+}
+
+impl Drop for LocalServer {
+    fn drop(&mut self) {
+        self.shutdown();
+    }
+}
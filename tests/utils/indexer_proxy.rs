@@ -0,0 +1,268 @@
+use super::*;
+
+/// Misbehaviour an [`IndexerProxy`] can inject in the indexer responses
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum IndexerFault {
+    /// Wait before forwarding each request
+    Delay(Duration),
+    /// Close connections without answering
+    Drop,
+    /// Serve the genesis of another network
+    WrongChain,
+    /// Report a chain tip lower than the real one by the given amount of blocks
+    WrongTip(u32),
+    /// Serve the hex of a different transaction than the requested one
+    MismatchedTx,
+    /// Report confirmed transactions as unconfirmed
+    Unconfirm,
+}
+
+fn other_genesis() -> Block {
+    genesis_block(Network::Testnet)
+}
+
+fn tamper_tx_bytes(bytes: &[u8]) -> Vec<u8> {
+    let mut tx: Transaction = consensus_deserialize(bytes).unwrap();
+    tx.lock_time = BtcLockTime::from_consensus(tx.lock_time.to_consensus_u32() + 1);
+    consensus_serialize(&tx)
+}
+
+fn tamper_tx_hex(hex: &str) -> String {
+    tamper_tx_bytes(&Vec::<u8>::from_hex(hex.trim()).unwrap()).to_hex()
+}
+
+struct ProxyState {
+    upstream: String,
+    faults: RwLock<Vec<IndexerFault>>,
+    requests: Mutex<usize>,
+}
+
+impl ProxyState {
+    fn has(&self, fault: &IndexerFault) -> bool {
+        self.faults.read().unwrap().contains(fault)
+    }
+
+    /// Count the request and apply faults acting before forwarding, returning false if the
+    /// connection should be dropped
+    fn before_forward(&self) -> bool {
+        *self.requests.lock().unwrap() += 1;
+        let faults = self.faults.read().unwrap().clone();
+        for fault in faults {
+            match fault {
+                IndexerFault::Delay(delay) => std::thread::sleep(delay),
+                IndexerFault::Drop => return false,
+                _ => {}
+            }
+        }
+        true
+    }
+
+    fn esplora(&self, mut stream: TcpStream) {
+        let Some(request) = read_http_request(&stream) else {
+            return;
+        };
+        if !self.before_forward() {
+            return;
+        }
+        let url = if request.query.is_empty() {
+            format!("{}{}", self.upstream, request.path)
+        } else {
+            format!("{}{}?{}", self.upstream, request.path, request.query)
+        };
+        let Ok(mut response) = http_call(&request.method, &url, &request.body) else {
+            return;
+        };
+        if response.status == 200 {
+            self.tamper_esplora(&request, &mut response);
+        }
+        write_http_response(&mut stream, &response);
+    }
+
+    fn tamper_esplora(&self, request: &HttpRequest, response: &mut HttpResponse) {
+        match request.segments().as_slice() {
+            ["block-height", "0"] if self.has(&IndexerFault::WrongChain) => {
+                *response = HttpResponse::text(other_genesis().block_hash());
+            }
+            ["blocks", "tip", "height"] => {
+                if let Some(IndexerFault::WrongTip(blocks)) = self
+                    .faults
+                    .read()
+                    .unwrap()
+                    .iter()
+                    .find(|f| matches!(f, IndexerFault::WrongTip(_)))
+                {
+                    let height: u32 = String::from_utf8_lossy(&response.body).parse().unwrap();
+                    *response = HttpResponse::text(height.saturating_sub(*blocks));
+                }
+            }
+            ["tx", _, "raw"] if self.has(&IndexerFault::MismatchedTx) => {
+                response.body = tamper_tx_bytes(&response.body);
+            }
+            ["tx", _, "hex"] if self.has(&IndexerFault::MismatchedTx) => {
+                response.body =
+                    tamper_tx_hex(&String::from_utf8_lossy(&response.body)).into_bytes();
+            }
+            ["tx", _, "status"] if self.has(&IndexerFault::Unconfirm) => {
+                *response = HttpResponse::json(&json!({"confirmed": false}));
+            }
+            ["tx", _] if self.has(&IndexerFault::Unconfirm) => {
+                let mut tx: Value = serde_json::from_slice(&response.body).unwrap();
+                tx["status"] = json!({"confirmed": false});
+                *response = HttpResponse::json(&tx);
+            }
+            _ => {}
+        }
+    }
+
+    fn electrum(&self, stream: TcpStream) {
+        let Ok(upstream) = TcpStream::connect(&self.upstream) else {
+            return;
+        };
+        let mut upstream_writer = upstream.try_clone().unwrap();
+        let mut upstream_reader = BufReader::new(upstream);
+        let mut writer = stream.try_clone().unwrap();
+        for line in BufReader::new(stream).lines() {
+            let Ok(line) = line else {
+                break;
+            };
+            if !self.before_forward() {
+                break;
+            }
+            if writeln!(upstream_writer, "{line}").is_err() {
+                break;
+            }
+            let mut response = String::new();
+            if upstream_reader.read_line(&mut response).is_err() {
+                break;
+            }
+            let request: Value = serde_json::from_str(&line).unwrap_or_default();
+            let mut response: Value = serde_json::from_str(&response).unwrap_or_default();
+            match (&request, &mut response) {
+                (Value::Array(requests), Value::Array(responses)) => {
+                    for response in responses.iter_mut() {
+                        if let Some(request) = requests.iter().find(|r| r["id"] == response["id"]) {
+                            self.tamper_electrum(request, response);
+                        }
+                    }
+                }
+                (request, response) => self.tamper_electrum(request, response),
+            }
+            if writeln!(writer, "{response}").is_err() {
+                break;
+            }
+        }
+    }
+
+    fn tamper_electrum(&self, request: &Value, response: &mut Value) {
+        let params = request["params"].as_array().cloned().unwrap_or_default();
+        let result = &mut response["result"];
+        if result.is_null() {
+            return;
+        }
+        match request["method"].as_str().unwrap_or_default() {
+            "server.features" if self.has(&IndexerFault::WrongChain) => {
+                result["genesis_hash"] = json!(other_genesis().block_hash());
+            }
+            "blockchain.block.header"
+                if self.has(&IndexerFault::WrongChain) && params.first() == Some(&json!(0)) =>
+            {
+                *result = json!(consensus_serialize(&other_genesis().header).to_hex());
+            }
+            "blockchain.headers.subscribe" => {
+                if let Some(IndexerFault::WrongTip(blocks)) = self
+                    .faults
+                    .read()
+                    .unwrap()
+                    .iter()
+                    .find(|f| matches!(f, IndexerFault::WrongTip(_)))
+                {
+                    let height = result["height"].as_u64().unwrap();
+                    result["height"] = json!(height.saturating_sub(*blocks as u64));
+                }
+            }
+            "blockchain.transaction.get" => {
+                if self.has(&IndexerFault::MismatchedTx) {
+                    match result {
+                        Value::String(hex) => *hex = tamper_tx_hex(hex),
+                        Value::Object(tx) => {
+                            let hex = tx["hex"].as_str().unwrap().to_string();
+                            tx.insert(s!("hex"), json!(tamper_tx_hex(&hex)));
+                        }
+                        _ => {}
+                    }
+                }
+                if self.has(&IndexerFault::Unconfirm)
+                    && let Value::Object(tx) = result
+                {
+                    for key in ["blockhash", "confirmations", "time", "blocktime"] {
+                        tx.remove(key);
+                    }
+                }
+            }
+            "blockchain.scripthash.get_history" if self.has(&IndexerFault::Unconfirm) => {
+                for entry in result.as_array_mut().unwrap() {
+                    entry["height"] = json!(0);
+                }
+            }
+            "blockchain.transaction.get_merkle" if self.has(&IndexerFault::Unconfirm) => {
+                response["error"] = json!({"code": 1, "message": "transaction not confirmed"});
+                response.as_object_mut().unwrap().remove("result");
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Proxy sitting in front of an esplora or electrum indexer, able to inject faults at runtime
+pub struct IndexerProxy {
+    indexer: Indexer,
+    state: Arc<ProxyState>,
+    server: LocalServer,
+}
+
+impl IndexerProxy {
+    pub fn start(indexer: Indexer, upstream_url: &str) -> Self {
+        let state = Arc::new(ProxyState {
+            upstream: upstream_url.trim_end_matches('/').to_string(),
+            faults: RwLock::new(vec![]),
+            requests: Mutex::new(0),
+        });
+        let server_state = state.clone();
+        let server = match indexer {
+            Indexer::Esplora => LocalServer::tcp(move |stream| server_state.esplora(stream)),
+            Indexer::Electrum => LocalServer::tcp(move |stream| server_state.electrum(stream)),
+        };
+        Self {
+            indexer,
+            state,
+            server,
+        }
+    }
+
+    /// Proxy for the indexer of the given instance, using the configured indexer type
+    pub fn for_instance(instance: u8) -> Self {
+        Self::start(
+            INDEXER.get().unwrap().clone(),
+            &indexer_url(instance, Network::Regtest),
+        )
+    }
+
+    pub fn url(&self) -> String {
+        match self.indexer {
+            Indexer::Esplora => self.server.http_url(),
+            Indexer::Electrum => self.server.addr().to_string(),
+        }
+    }
+
+    pub fn set_faults(&self, faults: Vec<IndexerFault>) {
+        *self.state.faults.write().unwrap() = faults;
+    }
+
+    pub fn clear_faults(&self) {
+        self.set_faults(vec![]);
+    }
+
+    pub fn requests(&self) -> usize {
+        *self.state.requests.lock().unwrap()
+    }
+}
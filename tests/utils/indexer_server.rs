@@ -0,0 +1,619 @@
+use super::*;
+
+// esplora pages confirmed script history 25 transactions at a time
+const ESPLORA_PAGE_SIZE: usize = 25;
+const ELECTRUM_MAX_HEADERS: u32 = 2016;
+
+/// Script type names as (esplora, bitcoind)
+fn script_type(script: &ScriptBuf) -> (&'static str, &'static str) {
+    if script.is_p2wpkh() {
+        ("v0_p2wpkh", "witness_v0_keyhash")
+    } else if script.is_p2tr() {
+        ("v1_p2tr", "witness_v1_taproot")
+    } else if script.is_p2wsh() {
+        ("v0_p2wsh", "witness_v0_scripthash")
+    } else if script.is_p2pkh() {
+        ("p2pkh", "pubkeyhash")
+    } else if script.is_p2sh() {
+        ("p2sh", "scripthash")
+    } else if script.is_op_return() {
+        ("op_return", "nulldata")
+    } else {
+        ("unknown", "nonstandard")
+    }
+}
+
+fn script_address(script: &ScriptBuf) -> Option<String> {
+    Address::from_script(script, Network::Regtest)
+        .ok()
+        .map(|a| a.to_string())
+}
+
+fn esplora_txout(txout: &TxOut) -> Value {
+    json!({
+        "scriptpubkey": txout.script_pubkey.to_hex_string(),
+        "scriptpubkey_asm": txout.script_pubkey.to_asm_string(),
+        "scriptpubkey_type": script_type(&txout.script_pubkey).0,
+        "scriptpubkey_address": script_address(&txout.script_pubkey),
+        "value": txout.value.to_sat(),
+    })
+}
+
+fn esplora_status(status: &Option<SimTxStatus>) -> Value {
+    match status {
+        Some(status) => json!({
+            "confirmed": true,
+            "block_height": status.height,
+            "block_hash": status.block_hash,
+            "block_time": status.block_time,
+        }),
+        None => json!({"confirmed": false}),
+    }
+}
+
+fn esplora_tx(
+    chain: &SimChain,
+    instance: u8,
+    tx: &Transaction,
+    status: &Option<SimTxStatus>,
+) -> Value {
+    let vin = tx
+        .input
+        .iter()
+        .map(|i| {
+            let is_coinbase = i.previous_output.is_null();
+            let prevout = chain
+                .prevout(instance, i.previous_output)
+                .filter(|_| !is_coinbase)
+                .map(|o| esplora_txout(&o));
+            json!({
+                "txid": i.previous_output.txid,
+                "vout": i.previous_output.vout,
+                "prevout": prevout,
+                "scriptsig": i.script_sig.to_hex_string(),
+                "scriptsig_asm": i.script_sig.to_asm_string(),
+                "witness": i.witness.iter().map(|w| w.to_hex()).collect::<Vec<_>>(),
+                "is_coinbase": is_coinbase,
+                "sequence": i.sequence.0,
+            })
+        })
+        .collect::<Vec<_>>();
+    json!({
+        "txid": tx.compute_txid(),
+        "version": tx.version.0,
+        "locktime": tx.lock_time.to_consensus_u32(),
+        "vin": vin,
+        "vout": tx.output.iter().map(esplora_txout).collect::<Vec<_>>(),
+        "size": tx.total_size(),
+        "weight": tx.weight().to_wu(),
+        "fee": chain.fee(instance, tx).unwrap_or_default(),
+        "status": esplora_status(status),
+    })
+}
+
+fn esplora_block(height: u32, block: &Block) -> Value {
+    let previousblockhash = (height > 0).then_some(block.header.prev_blockhash);
+    json!({
+        "id": block.block_hash(),
+        "height": height,
+        "version": block.header.version.to_consensus(),
+        "timestamp": block.header.time,
+        "mediantime": block.header.time,
+        "tx_count": block.txdata.len(),
+        "size": block.total_size(),
+        "weight": block.weight().to_wu(),
+        "merkle_root": block.header.merkle_root,
+        "previousblockhash": previousblockhash,
+        "nonce": block.header.nonce,
+        "bits": block.header.bits.to_consensus(),
+        "difficulty": 0,
+    })
+}
+
+fn merkle_proof_json(chain: &SimChain, instance: u8, txid: Txid) -> Option<Value> {
+    let (height, pos, branch) = chain.merkle_proof(instance, txid)?;
+    Some(json!({
+        "block_height": height,
+        "merkle": branch.iter().map(|n| n.to_string()).collect::<Vec<_>>(),
+        "pos": pos,
+    }))
+}
+
+fn decode_tx(hex: &str) -> Result<Transaction, String> {
+    let bytes = Vec::<u8>::from_hex(hex.trim()).map_err(|e| e.to_string())?;
+    consensus_deserialize(&bytes).map_err(|e| e.to_string())
+}
+
+fn esplora_script_txs(
+    chain: &SimChain,
+    instance: u8,
+    script: Option<ScriptBuf>,
+    mempool: bool,
+    confirmed: bool,
+    last_seen: Option<Txid>,
+) -> Value {
+    let Some(script) = script else {
+        return json!([]);
+    };
+    let history = chain.history(instance, &script);
+    let mut txs = vec![];
+    if mempool {
+        txs.extend(history.iter().filter(|(_, s)| s.is_none()).rev());
+    }
+    if confirmed {
+        let confirmed = history
+            .iter()
+            .filter(|(_, s)| s.is_some())
+            .rev()
+            .collect::<Vec<_>>();
+        let start = last_seen
+            .and_then(|l| confirmed.iter().position(|(tx, _)| tx.compute_txid() == l))
+            .map(|pos| pos + 1)
+            .unwrap_or_default();
+        txs.extend(confirmed.into_iter().skip(start).take(ESPLORA_PAGE_SIZE));
+    }
+    Value::Array(
+        txs.into_iter()
+            .map(|(tx, status)| esplora_tx(chain, instance, tx, status))
+            .collect(),
+    )
+}
+
+fn esplora_response(chain: &SimChain, instance: u8, req: &HttpRequest) -> HttpResponse {
+    let (tip_height, tip) = chain.tip(instance).unwrap();
+    let segments = req.segments();
+    // address and scripthash endpoints share the same logic
+    let script = match segments.as_slice() {
+        ["scripthash", hash, ..] => sha256::Hash::from_str(hash)
+            .ok()
+            .and_then(|h| chain.script_by_hash(instance, h)),
+        ["address", address, ..] => Address::from_str(address)
+            .ok()
+            .map(|a| a.assume_checked().script_pubkey()),
+        _ => None,
+    };
+    match (req.method.as_str(), segments.as_slice()) {
+        ("GET", ["blocks", "tip", "height"]) => HttpResponse::text(tip_height),
+        ("GET", ["blocks", "tip", "hash"]) => HttpResponse::text(tip.block_hash()),
+        ("GET", ["blocks", rest @ ..]) => {
+            let start = match rest {
+                [start] => start.parse().unwrap_or(tip_height).min(tip_height),
+                _ => tip_height,
+            };
+            let blocks = (0..=start)
+                .rev()
+                .take(10)
+                .filter_map(|h| chain.block(instance, h).map(|b| esplora_block(h, &b)))
+                .collect::<Vec<_>>();
+            HttpResponse::json(&json!(blocks))
+        }
+        ("GET", ["block-height", height]) => match height
+            .parse()
+            .ok()
+            .and_then(|h| chain.block_hash(instance, h))
+        {
+            Some(block_hash) => HttpResponse::text(block_hash),
+            None => HttpResponse::error(404, "Block not found"),
+        },
+        ("GET", ["block", hash, rest @ ..]) => {
+            let Some((height, block)) = BlockHash::from_str(hash)
+                .ok()
+                .and_then(|h| chain.block_by_hash(instance, h))
+            else {
+                return HttpResponse::error(404, "Block not found");
+            };
+            match rest {
+                [] => HttpResponse::json(&esplora_block(height, &block)),
+                ["header"] => HttpResponse::text(consensus_serialize(&block.header).to_hex()),
+                ["raw"] => HttpResponse::bytes(consensus_serialize(&block)),
+                ["txids"] => HttpResponse::json(&json!(
+                    block
+                        .txdata
+                        .iter()
+                        .map(|tx| tx.compute_txid())
+                        .collect::<Vec<_>>()
+                )),
+                ["status"] => HttpResponse::json(&json!({
+                    "in_best_chain": true,
+                    "height": height,
+                    "next_best": chain.block_hash(instance, height + 1),
+                })),
+                _ => HttpResponse::not_found(),
+            }
+        }
+        ("GET", ["tx", txid, rest @ ..]) => {
+            let Some(txid) = Txid::from_str(txid).ok() else {
+                return HttpResponse::error(400, "Invalid hex string");
+            };
+            let Some((tx, status)) = chain.get_tx(instance, txid) else {
+                return HttpResponse::error(404, "Transaction not found");
+            };
+            match rest {
+                [] => HttpResponse::json(&esplora_tx(chain, instance, &tx, &status)),
+                ["hex"] => HttpResponse::text(consensus_serialize(&tx).to_hex()),
+                ["raw"] => HttpResponse::bytes(consensus_serialize(&tx)),
+                ["status"] => HttpResponse::json(&esplora_status(&status)),
+                ["merkle-proof"] => match merkle_proof_json(chain, instance, txid) {
+                    Some(proof) => HttpResponse::json(&proof),
+                    None => HttpResponse::error(404, "Transaction not found"),
+                },
+                ["outspends"] => HttpResponse::json(&json!(
+                    (0..tx.output.len() as u32)
+                        .map(|vout| esplora_outspend(chain, instance, Outpoint::new(txid, vout)))
+                        .collect::<Vec<_>>()
+                )),
+                ["outspend", vout] => match vout.parse() {
+                    Ok(vout) => HttpResponse::json(&esplora_outspend(
+                        chain,
+                        instance,
+                        Outpoint::new(txid, vout),
+                    )),
+                    Err(_) => HttpResponse::error(400, "Invalid vout"),
+                },
+                _ => HttpResponse::not_found(),
+            }
+        }
+        ("GET", ["scripthash" | "address", _, "txs"]) => HttpResponse::json(&esplora_script_txs(
+            chain, instance, script, true, true, None,
+        )),
+        ("GET", ["scripthash" | "address", _, "txs", "mempool"]) => HttpResponse::json(
+            &esplora_script_txs(chain, instance, script, true, false, None),
+        ),
+        ("GET", ["scripthash" | "address", _, "txs", "chain", rest @ ..]) => {
+            let last_seen = rest.first().and_then(|t| Txid::from_str(t).ok());
+            HttpResponse::json(&esplora_script_txs(
+                chain, instance, script, false, true, last_seen,
+            ))
+        }
+        ("GET", ["scripthash" | "address", _, "utxo"]) => {
+            let utxos = script
+                .map(|s| chain.unspent(instance, &s))
+                .unwrap_or_default()
+                .into_iter()
+                .map(|(outpoint, txout)| {
+                    let status = chain.get_tx(instance, outpoint.txid).and_then(|(_, s)| s);
+                    json!({
+                        "txid": outpoint.txid,
+                        "vout": outpoint.vout,
+                        "status": esplora_status(&status),
+                        "value": txout.value.to_sat(),
+                    })
+                })
+                .collect::<Vec<_>>();
+            HttpResponse::json(&json!(utxos))
+        }
+        ("GET", ["fee-estimates"]) => HttpResponse::json(&json!({"1": 1.0, "6": 1.0, "144": 1.0})),
+        ("POST", ["tx"]) => {
+            let tx = match decode_tx(&String::from_utf8_lossy(&req.body)) {
+                Ok(tx) => tx,
+                Err(e) => return HttpResponse::error(400, format!("TX decode failed: {e}")),
+            };
+            match chain.broadcast(instance, tx) {
+                Ok(txid) => HttpResponse::text(txid),
+                Err(e) => HttpResponse::error(400, format!("sendrawtransaction RPC error: {e}")),
+            }
+        }
+        _ => HttpResponse::not_found(),
+    }
+}
+
+fn esplora_outspend(chain: &SimChain, instance: u8, outpoint: Outpoint) -> Value {
+    match chain.spender(instance, outpoint) {
+        Some((txid, vin, status)) => json!({
+            "spent": true,
+            "txid": txid,
+            "vin": vin,
+            "status": esplora_status(&status),
+        }),
+        None => json!({"spent": false}),
+    }
+}
+
+fn electrum_script(chain: &SimChain, instance: u8, params: &[Value]) -> Option<ScriptBuf> {
+    // electrum script hashes are displayed in reverse byte order
+    let mut hash = Vec::<u8>::from_hex(params.first()?.as_str()?).ok()?;
+    hash.reverse();
+    let hash = sha256::Hash::from_slice(&hash).ok()?;
+    chain.script_by_hash(instance, hash)
+}
+
+fn electrum_history(chain: &SimChain, instance: u8, params: &[Value]) -> Vec<(Txid, i64, u64)> {
+    let Some(script) = electrum_script(chain, instance, params) else {
+        return vec![];
+    };
+    chain
+        .history(instance, &script)
+        .into_iter()
+        .map(|(tx, status)| {
+            let height = status.map(|s| s.height as i64).unwrap_or_default();
+            let fee = chain.fee(instance, &tx).unwrap_or_default();
+            (tx.compute_txid(), height, fee)
+        })
+        .collect()
+}
+
+fn electrum_verbose_tx(
+    chain: &SimChain,
+    instance: u8,
+    tx: &Transaction,
+    status: &Option<SimTxStatus>,
+) -> Value {
+    let vin = tx
+        .input
+        .iter()
+        .map(|i| {
+            if i.previous_output.is_null() {
+                json!({"coinbase": i.script_sig.to_hex_string(), "sequence": i.sequence.0})
+            } else {
+                json!({
+                    "txid": i.previous_output.txid,
+                    "vout": i.previous_output.vout,
+                    "scriptSig": {
+                        "asm": i.script_sig.to_asm_string(),
+                        "hex": i.script_sig.to_hex_string(),
+                    },
+                    "txinwitness": i.witness.iter().map(|w| w.to_hex()).collect::<Vec<_>>(),
+                    "sequence": i.sequence.0,
+                })
+            }
+        })
+        .collect::<Vec<_>>();
+    let vout = tx
+        .output
+        .iter()
+        .enumerate()
+        .map(|(n, o)| {
+            json!({
+                "value": o.value.to_btc(),
+                "n": n,
+                "scriptPubKey": {
+                    "asm": o.script_pubkey.to_asm_string(),
+                    "hex": o.script_pubkey.to_hex_string(),
+                    "type": script_type(&o.script_pubkey).1,
+                    "address": script_address(&o.script_pubkey),
+                },
+            })
+        })
+        .collect::<Vec<_>>();
+    let mut value = json!({
+        "txid": tx.compute_txid(),
+        "hash": tx.compute_wtxid(),
+        "version": tx.version.0,
+        "size": tx.total_size(),
+        "vsize": tx.vsize(),
+        "weight": tx.weight().to_wu(),
+        "locktime": tx.lock_time.to_consensus_u32(),
+        "vin": vin,
+        "vout": vout,
+        "hex": consensus_serialize(tx).to_hex(),
+    });
+    if let Some(status) = status {
+        let tip_height = chain.height_of(instance).unwrap();
+        value["blockhash"] = json!(status.block_hash);
+        value["confirmations"] = json!(tip_height - status.height + 1);
+        value["time"] = json!(status.block_time);
+        value["blocktime"] = json!(status.block_time);
+    }
+    value
+}
+
+fn electrum_header(chain: &SimChain, instance: u8, height: u32) -> Result<String, String> {
+    chain
+        .block(instance, height)
+        .map(|b| consensus_serialize(&b.header).to_hex())
+        .ok_or_else(|| format!("height {height} out of range"))
+}
+
+fn electrum_call(
+    chain: &SimChain,
+    instance: u8,
+    method: &str,
+    params: &[Value],
+) -> Result<Value, String> {
+    let param_u32 = |idx: usize| -> Result<u32, String> {
+        params
+            .get(idx)
+            .and_then(|p| p.as_u64())
+            .map(|p| p as u32)
+            .ok_or_else(|| format!("invalid param {idx}"))
+    };
+    let param_txid = |idx: usize| -> Result<Txid, String> {
+        params
+            .get(idx)
+            .and_then(|p| p.as_str())
+            .and_then(|p| Txid::from_str(p).ok())
+            .ok_or_else(|| format!("invalid param {idx}"))
+    };
+    match method {
+        "server.version" => Ok(json!(["rgb-tests sim indexer", "1.4"])),
+        "server.ping" => Ok(Value::Null),
+        "server.banner" => Ok(json!("rgb-tests simulated electrum")),
+        "server.features" => Ok(json!({
+            "genesis_hash": chain.block_hash(instance, 0),
+            "hosts": {},
+            "protocol_max": "1.4",
+            "protocol_min": "1.4",
+            "pruning": null,
+            "server_version": "rgb-tests sim indexer",
+            "hash_function": "sha256",
+        })),
+        "blockchain.headers.subscribe" => {
+            let (height, block) = chain.tip(instance).unwrap();
+            Ok(json!({"height": height, "hex": consensus_serialize(&block.header).to_hex()}))
+        }
+        "blockchain.block.header" => Ok(json!(electrum_header(chain, instance, param_u32(0)?)?)),
+        "blockchain.block.headers" => {
+            let start = param_u32(0)?;
+            let tip_height = chain.height_of(instance).unwrap();
+            let count = param_u32(1)?
+                .min(ELECTRUM_MAX_HEADERS)
+                .min((tip_height + 1).saturating_sub(start));
+            let hex = (start..start + count)
+                .map(|h| electrum_header(chain, instance, h))
+                .collect::<Result<String, _>>()?;
+            Ok(json!({"count": count, "hex": hex, "max": ELECTRUM_MAX_HEADERS}))
+        }
+        "blockchain.estimatefee" | "blockchain.relayfee" => Ok(json!(0.00001)),
+        "mempool.get_fee_histogram" => Ok(json!([])),
+        "blockchain.scripthash.get_history" => {
+            let history = electrum_history(chain, instance, params)
+                .into_iter()
+                .map(|(txid, height, fee)| {
+                    if height > 0 {
+                        json!({"tx_hash": txid, "height": height})
+                    } else {
+                        json!({"tx_hash": txid, "height": height, "fee": fee})
+                    }
+                })
+                .collect::<Vec<_>>();
+            Ok(json!(history))
+        }
+        "blockchain.scripthash.get_mempool" => {
+            let mempool = electrum_history(chain, instance, params)
+                .into_iter()
+                .filter(|(_, height, _)| *height == 0)
+                .map(|(txid, height, fee)| json!({"tx_hash": txid, "height": height, "fee": fee}))
+                .collect::<Vec<_>>();
+            Ok(json!(mempool))
+        }
+        "blockchain.scripthash.subscribe" => {
+            let history = electrum_history(chain, instance, params);
+            if history.is_empty() {
+                return Ok(Value::Null);
+            }
+            let status = history
+                .iter()
+                .map(|(txid, height, _)| format!("{txid}:{height}:"))
+                .collect::<String>();
+            Ok(json!(sha256::Hash::hash(status.as_bytes()).to_string()))
+        }
+        "blockchain.scripthash.listunspent" | "blockchain.scripthash.get_balance" => {
+            let utxos = electrum_script(chain, instance, params)
+                .map(|s| chain.unspent(instance, &s))
+                .unwrap_or_default()
+                .into_iter()
+                .map(|(outpoint, txout)| {
+                    let height = chain
+                        .get_tx(instance, outpoint.txid)
+                        .and_then(|(_, s)| s)
+                        .map(|s| s.height)
+                        .unwrap_or_default();
+                    (outpoint, txout.value.to_sat(), height)
+                })
+                .collect::<Vec<_>>();
+            if method.ends_with("get_balance") {
+                let (confirmed, unconfirmed): (Vec<_>, Vec<_>) =
+                    utxos.iter().partition(|(_, _, height)| *height > 0);
+                let sum = |utxos: Vec<&(Outpoint, u64, u32)>| -> u64 {
+                    utxos.iter().map(|(_, sats, _)| sats).sum()
+                };
+                return Ok(json!({"confirmed": sum(confirmed), "unconfirmed": sum(unconfirmed)}));
+            }
+            let utxos = utxos
+                .into_iter()
+                .map(|(outpoint, value, height)| {
+                    json!({
+                        "tx_hash": outpoint.txid,
+                        "tx_pos": outpoint.vout,
+                        "height": height,
+                        "value": value,
+                    })
+                })
+                .collect::<Vec<_>>();
+            Ok(json!(utxos))
+        }
+        "blockchain.transaction.get" => {
+            let txid = param_txid(0)?;
+            let verbose = params.get(1).and_then(|v| v.as_bool()).unwrap_or_default();
+            let (tx, status) = chain
+                .get_tx(instance, txid)
+                .ok_or_else(|| format!("transaction {txid} not found"))?;
+            if verbose {
+                Ok(electrum_verbose_tx(chain, instance, &tx, &status))
+            } else {
+                Ok(json!(consensus_serialize(&tx).to_hex()))
+            }
+        }
+        "blockchain.transaction.get_merkle" => {
+            let txid = param_txid(0)?;
+            merkle_proof_json(chain, instance, txid)
+                .ok_or_else(|| format!("transaction {txid} not confirmed"))
+        }
+        "blockchain.transaction.broadcast" => {
+            let hex = params
+                .first()
+                .and_then(|p| p.as_str())
+                .ok_or("invalid param 0")?;
+            let tx = decode_tx(hex)?;
+            chain
+                .broadcast(instance, tx)
+                .map(|txid| json!(txid))
+                .map_err(|e| e.to_string())
+        }
+        _ => Err(format!("unknown method {method}")),
+    }
+}
+
+fn electrum_response(chain: &SimChain, instance: u8, request: &Value) -> Value {
+    let id = request["id"].clone();
+    let method = request["method"].as_str().unwrap_or_default();
+    let params = request["params"].as_array().cloned().unwrap_or_default();
+    match electrum_call(chain, instance, method, &params) {
+        Ok(result) => json!({"jsonrpc": "2.0", "id": id, "result": result}),
+        Err(message) => {
+            json!({"jsonrpc": "2.0", "id": id, "error": {"code": 1, "message": message}})
+        }
+    }
+}
+
+fn serve_electrum(chain: &SimChain, instance: u8, stream: TcpStream) {
+    let mut writer = stream.try_clone().unwrap();
+    for line in BufReader::new(stream).lines() {
+        let Ok(line) = line else {
+            break;
+        };
+        if line.trim().is_empty() {
+            continue;
+        }
+        let response = match serde_json::from_str::<Value>(&line) {
+            Ok(Value::Array(requests)) => Value::Array(
+                requests
+                    .iter()
+                    .map(|r| electrum_response(chain, instance, r))
+                    .collect(),
+            ),
+            Ok(request) => electrum_response(chain, instance, &request),
+            Err(e) => json!({
+                "jsonrpc": "2.0",
+                "id": null,
+                "error": {"code": -32700, "message": e.to_string()},
+            }),
+        };
+        if writeln!(writer, "{response}").is_err() {
+            break;
+        }
+    }
+}
+
+/// Esplora REST and Electrum JSON-RPC servers exposing one node of a simulated chain
+pub struct IndexerServer {
+    esplora: LocalServer,
+    electrum: LocalServer,
+}
+
+impl IndexerServer {
+    pub fn start(chain: Arc<SimChain>, instance: u8) -> Self {
+        let esplora_chain = chain.clone();
+        let esplora = LocalServer::http(move |req| esplora_response(&esplora_chain, instance, req));
+        let electrum = LocalServer::tcp(move |stream| serve_electrum(&chain, instance, stream));
+        Self { esplora, electrum }
+    }
+
+    pub fn esplora_url(&self) -> String {
+        self.esplora.http_url()
+    }
+
+    pub fn electrum_url(&self) -> String {
+        self.electrum.addr().to_string()
+    }
+}
@@ -0,0 +1,461 @@
+use super::*;
+
+/// Sats of the remote output of commitment transactions
+pub const REMOTE_OUTPUT_SATS: u64 = 2000;
+/// Sats of each HTLC output of commitment transactions
+pub const HTLC_OUTPUT_SATS: u64 = 4000;
+/// Maximum number of HTLCs pending at the same time
+pub const MAX_PENDING_HTLCS: usize = 3;
+
+const CHANNEL_BLINDING: u64 = 666;
+const COMMITMENT_NONCE: u64 = u64::MAX - 1;
+const HTLC_NONCE: u64 = u64::MAX;
+
+/// Channel transactions are never broadcast unless the channel gets force-closed
+struct LnFasciaResolver;
+
+impl WitnessOrdProvider for LnFasciaResolver {
+    fn witness_ord(&self, _: Txid) -> Result<WitnessOrd, WitnessResolverError> {
+        Ok(WitnessOrd::Ignored)
+    }
+}
+
+pub type HtlcId = usize;
+
+#[derive(Clone, Debug)]
+pub struct Htlc {
+    pub id: HtlcId,
+    pub amount: u64,
+    witness_info: WitnessInfo,
+}
+
+/// Pre-signed transaction claiming back an HTLC output of a commitment to the local wallet
+pub struct HtlcTx {
+    pub htlc_id: HtlcId,
+    pub psbt: BpPsbt,
+}
+
+/// Commitment transaction spending the funding outpoint, with the channel balances
+pub struct Commitment {
+    pub psbt: BpPsbt,
+    pub fascia: Fascia,
+    pub local_balance: u64,
+    pub remote_balance: u64,
+    pub htlcs: Vec<Htlc>,
+    pub htlc_txs: Vec<HtlcTx>,
+}
+
+impl Commitment {
+    pub fn txid(&self) -> Txid {
+        self.fascia.witness_id()
+    }
+
+    fn vout(&self, witness_info: &WitnessInfo) -> u32 {
+        let script = witness_info.script_pubkey();
+        self.psbt
+            .outputs()
+            .find(|o| o.script == script)
+            .unwrap()
+            .vout()
+            .to_u32()
+    }
+}
+
+/// Update of the channel state
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ChannelOp {
+    /// Move the given amount from the local to the remote balance
+    Pay(u64),
+    /// Lock the given amount of the local balance in a new HTLC
+    AddHtlc(u64),
+    /// Fulfill an HTLC, moving its amount to the remote balance
+    SettleHtlc(HtlcId),
+    /// Fail an HTLC, moving its amount back to the local balance
+    FailHtlc(HtlcId),
+}
+
+/// RGB channel between the local wallet, which funded it, and a remote one.
+///
+/// Every state update builds a new commitment transaction (and the HTLC ones) spending the
+/// funding outpoint, consumed by the local stock as never broadcast. Closing the channel
+/// broadcasts either the latest commitment or a cooperative transaction.
+pub struct LnChannel {
+    pub contract_id: ContractId,
+    pub funding: Outpoint,
+    pre_funding_height: u32,
+    local_output: WitnessInfo,
+    remote_output: WitnessInfo,
+    local_balance: u64,
+    remote_balance: u64,
+    htlcs: Vec<Htlc>,
+    next_htlc_id: HtlcId,
+    commitments: Vec<Commitment>,
+}
+
+impl LnChannel {
+    /// Open a channel with all the state of the contract on the given (mined) local outpoint
+    pub fn open(
+        local: &mut BpTestWallet,
+        remote: &mut BpTestWallet,
+        contract_id: ContractId,
+        funding: Outpoint,
+    ) -> Self {
+        let funding_height = local
+            .get_tx_height(&funding.txid)
+            .expect("funding TX not mined");
+        let capacity = local
+            .contract_fungible_allocations(contract_id, false)
+            .into_iter()
+            .filter(|a| a.seal.outpoint() == Some(funding))
+            .map(|a| a.state.value())
+            .sum::<u64>();
+        assert!(capacity > 0, "funding outpoint without state");
+        let mut channel = Self {
+            contract_id,
+            funding,
+            pre_funding_height: funding_height - 1,
+            local_output: local.get_witness_info(None, Some(CloseMethod::OpretFirst)),
+            remote_output: remote
+                .get_witness_info(Some(REMOTE_OUTPUT_SATS), Some(CloseMethod::OpretFirst)),
+            local_balance: capacity,
+            remote_balance: 0,
+            htlcs: vec![],
+            next_htlc_id: 0,
+            commitments: vec![],
+        };
+        channel.commit(local);
+        channel
+    }
+
+    pub fn capacity(&self) -> u64 {
+        self.local_balance + self.remote_balance + self.htlcs.iter().map(|h| h.amount).sum::<u64>()
+    }
+
+    pub fn local_balance(&self) -> u64 {
+        self.local_balance
+    }
+
+    pub fn remote_balance(&self) -> u64 {
+        self.remote_balance
+    }
+
+    pub fn htlcs(&self) -> &[Htlc] {
+        &self.htlcs
+    }
+
+    pub fn commitments(&self) -> &[Commitment] {
+        &self.commitments
+    }
+
+    pub fn latest_commitment(&self) -> &Commitment {
+        self.commitments.last().unwrap()
+    }
+
+    pub fn pay(&mut self, local: &mut BpTestWallet, amount: u64) {
+        assert!(amount <= self.local_balance, "insufficient local balance");
+        self.local_balance -= amount;
+        self.remote_balance += amount;
+        self.commit(local);
+    }
+
+    pub fn add_htlc(&mut self, local: &mut BpTestWallet, amount: u64) -> HtlcId {
+        assert!(amount <= self.local_balance, "insufficient local balance");
+        assert!(
+            self.htlcs.len() < MAX_PENDING_HTLCS,
+            "too many pending HTLCs"
+        );
+        let id = self.next_htlc_id;
+        self.next_htlc_id += 1;
+        self.local_balance -= amount;
+        self.htlcs.push(Htlc {
+            id,
+            amount,
+            witness_info: local
+                .get_witness_info(Some(HTLC_OUTPUT_SATS), Some(CloseMethod::OpretFirst)),
+        });
+        self.commit(local);
+        id
+    }
+
+    pub fn settle_htlc(&mut self, local: &mut BpTestWallet, id: HtlcId) {
+        self.remote_balance += self.remove_htlc(id).amount;
+        self.commit(local);
+    }
+
+    pub fn fail_htlc(&mut self, local: &mut BpTestWallet, id: HtlcId) {
+        self.local_balance += self.remove_htlc(id).amount;
+        self.commit(local);
+    }
+
+    fn remove_htlc(&mut self, id: HtlcId) -> Htlc {
+        let pos = self
+            .htlcs
+            .iter()
+            .position(|h| h.id == id)
+            .expect("unknown HTLC");
+        self.htlcs.remove(pos)
+    }
+
+    pub fn apply(&mut self, local: &mut BpTestWallet, op: ChannelOp) {
+        match op {
+            ChannelOp::Pay(amount) => self.pay(local, amount),
+            ChannelOp::AddHtlc(amount) => {
+                self.add_htlc(local, amount);
+            }
+            ChannelOp::SettleHtlc(id) => self.settle_htlc(local, id),
+            ChannelOp::FailHtlc(id) => self.fail_htlc(local, id),
+        }
+    }
+
+    /// Build and consume the commitment for the current state, along with its HTLC TXs
+    fn commit(&mut self, local: &mut BpTestWallet) {
+        let mut beneficiaries = vec![
+            self.remote_output.btc_beneficiary(),
+            self.local_output.btc_beneficiary(),
+        ];
+        let mut assignments = vec![
+            AssetAssignment {
+                destination: AssetDestination::Witness(self.remote_output.clone()),
+                amount: self.remote_balance,
+            },
+            AssetAssignment {
+                destination: AssetDestination::Witness(self.local_output.clone()),
+                amount: self.local_balance,
+            },
+        ];
+        for htlc in &self.htlcs {
+            beneficiaries.push(htlc.witness_info.btc_beneficiary());
+            assignments.push(AssetAssignment {
+                destination: AssetDestination::Witness(htlc.witness_info.clone()),
+                amount: htlc.amount,
+            });
+        }
+        let (mut psbt, mut meta) = local.construct_psbt(vec![self.funding], beneficiaries, None);
+        let coloring_info = ColoringInfo {
+            asset_info_map: HashMap::from([(
+                self.contract_id,
+                AssetColoringInfo {
+                    input_outpoints: vec![self.funding],
+                    assignments,
+                },
+            )]),
+            static_blinding: Some(CHANNEL_BLINDING),
+            nonce: Some(COMMITMENT_NONCE),
+            close_method: CloseMethod::OpretFirst,
+        };
+        let (fascia, _, _, _) = local.color_psbt(&mut psbt, &mut meta, coloring_info, None);
+        local.consume_fascia_custom_resolver(fascia.clone(), LnFasciaResolver);
+        let mut commitment = Commitment {
+            psbt,
+            fascia,
+            local_balance: self.local_balance,
+            remote_balance: self.remote_balance,
+            htlcs: self.htlcs.clone(),
+            htlc_txs: vec![],
+        };
+
+        for htlc in &self.htlcs {
+            let input_outpoint =
+                Outpoint::new(commitment.txid(), commitment.vout(&htlc.witness_info));
+            let witness_info = local.get_witness_info(None, Some(CloseMethod::OpretFirst));
+            let (mut psbt, mut meta) = local.construct_psbt_offchain(
+                vec![(
+                    outpoint_bitcoin_to_bp(input_outpoint),
+                    HTLC_OUTPUT_SATS,
+                    htlc.witness_info.terminal(),
+                    htlc.witness_info.script_pubkey(),
+                )],
+                vec![witness_info.btc_beneficiary()],
+                None,
+            );
+            let coloring_info = ColoringInfo {
+                asset_info_map: HashMap::from([(
+                    self.contract_id,
+                    AssetColoringInfo {
+                        input_outpoints: vec![input_outpoint],
+                        assignments: vec![AssetAssignment {
+                            destination: AssetDestination::Witness(witness_info),
+                            amount: htlc.amount,
+                        }],
+                    },
+                )]),
+                static_blinding: Some(CHANNEL_BLINDING),
+                nonce: Some(HTLC_NONCE),
+                close_method: CloseMethod::OpretFirst,
+            };
+            let (fascia, _, _, _) = local.color_psbt(&mut psbt, &mut meta, coloring_info, None);
+            local.consume_fascia_custom_resolver(fascia, LnFasciaResolver);
+            commitment.htlc_txs.push(HtlcTx {
+                htlc_id: htlc.id,
+                psbt,
+            });
+        }
+
+        self.commitments.push(commitment);
+        self.check_latest_commitment();
+    }
+
+    /// Check the latest commitment assigns the channel balances to the right outputs, without
+    /// creating or destroying any state
+    pub fn check_latest_commitment(&self) {
+        let commitment = self.latest_commitment();
+        let (_, bundle) = commitment
+            .fascia
+            .clone()
+            .into_bundles()
+            .into_iter()
+            .find(|(cid, _)| *cid == self.contract_id)
+            .unwrap();
+        let amounts = bundle_vout_amounts(&bundle);
+        let mut expected = BTreeMap::new();
+        expected.insert(
+            commitment.vout(&self.remote_output),
+            commitment.remote_balance,
+        );
+        expected.insert(
+            commitment.vout(&self.local_output),
+            commitment.local_balance,
+        );
+        for htlc in &commitment.htlcs {
+            expected.insert(commitment.vout(&htlc.witness_info), htlc.amount);
+        }
+        expected.retain(|_, amount| *amount > 0);
+        assert_eq!(amounts, expected);
+        assert_eq!(amounts.values().sum::<u64>(), self.capacity());
+    }
+
+    /// Give the remote wallet its balance on the given closing TX
+    fn deliver_remote_balance(
+        &self,
+        local: &BpTestWallet,
+        remote: &mut BpTestWallet,
+        tx: &Tx,
+        remote_vout: u32,
+    ) {
+        if self.remote_balance == 0 {
+            return;
+        }
+        let seal = ExplicitSeal::new(Outpoint::new(txid_bp_to_bitcoin(tx.txid()), remote_vout));
+        let consignment = local.consign_transfer(self.contract_id, [seal], [], [], Some(tx.txid()));
+        remote.accept_transfer(consignment, None);
+        remote.sync();
+    }
+
+    /// Broadcast the latest commitment and the TXs claiming back its pending HTLCs
+    pub fn force_close(mut self, local: &mut BpTestWallet, remote: &mut BpTestWallet) -> Tx {
+        let mut commitment = self.commitments.pop().unwrap();
+        let tx = local.sign_finalize_extract(&mut commitment.psbt);
+        local.broadcast_tx(&tx);
+        let txid = txid_bp_to_bitcoin(tx.txid());
+        local.mine_tx(&txid, false);
+        local.sync();
+        let mut closing_txids = vec![txid];
+        for htlc_tx in &mut commitment.htlc_txs {
+            let tx = local.sign_finalize_extract(&mut htlc_tx.psbt);
+            local.broadcast_tx(&tx);
+            let txid = txid_bp_to_bitcoin(tx.txid());
+            local.mine_tx(&txid, false);
+            closing_txids.push(txid);
+        }
+        local.sync();
+        local.update_witnesses(self.pre_funding_height, closing_txids);
+
+        // pending HTLCs timed out, so they're back to the local balance
+        self.local_balance += commitment.htlcs.iter().map(|h| h.amount).sum::<u64>();
+        self.htlcs.clear();
+        let remote_vout = commitment.vout(&self.remote_output);
+        self.deliver_remote_balance(local, remote, &tx, remote_vout);
+        tx
+    }
+
+    /// Spend the funding outpoint with a TX paying the balances, no HTLC can be pending
+    pub fn cooperative_close(self, local: &mut BpTestWallet, remote: &mut BpTestWallet) -> Tx {
+        assert!(self.htlcs.is_empty(), "cannot close with pending HTLCs");
+        let coloring_info = ColoringInfo {
+            asset_info_map: HashMap::from([(
+                self.contract_id,
+                AssetColoringInfo {
+                    input_outpoints: vec![self.funding],
+                    // an empty balance gets no output, the TX pays at least one of them
+                    assignments: [
+                        (&self.remote_output, self.remote_balance),
+                        (&self.local_output, self.local_balance),
+                    ]
+                    .into_iter()
+                    .filter(|(_, amount)| *amount > 0)
+                    .map(|(output, amount)| AssetAssignment {
+                        destination: AssetDestination::Witness(output.clone()),
+                        amount,
+                    })
+                    .collect(),
+                },
+            )]),
+            static_blinding: None,
+            nonce: None,
+            close_method: CloseMethod::OpretFirst,
+        };
+        let (_, tx, _, _) = local.pay_full_flexible(coloring_info, None, None);
+        let txid = txid_bp_to_bitcoin(tx.txid());
+        local.mine_tx(&txid, false);
+        local.sync_and_update_witnesses(Some(self.pre_funding_height));
+        if self.remote_balance > 0 {
+            let remote_script = self.remote_output.script_pubkey();
+            let remote_vout = tx
+                .outputs()
+                .position(|o| o.script_pubkey == remote_script)
+                .unwrap() as u32;
+            self.deliver_remote_balance(local, remote, &tx, remote_vout);
+        }
+        tx
+    }
+}
+
+/// Run on a fresh channel the sequence of updates driven by the given input, checking every
+/// commitment, then close the channel (cooperatively if no HTLC is pending) and check the final
+/// balances of both wallets. The wallets live in a temporary directory, removed at the end.
+pub fn fuzz_channel(data: &[u8]) {
+    let mut input = FuzzInput::new(data);
+    let data_dir = tempfile::tempdir().unwrap();
+    let mut local = BpTestWallet::in_dir(&DescriptorType::Wpkh, data_dir.path());
+    let mut remote = BpTestWallet::in_dir(&DescriptorType::Wpkh, data_dir.path());
+    let capacity = 1000;
+    let funding = local.get_utxo(Some(30_000));
+    let contract_id = local.issue_nia(capacity, Some(&funding));
+    let schema_id = local.schema_id(contract_id);
+    let mut channel = LnChannel::open(&mut local, &mut remote, contract_id, funding);
+
+    for _ in 0..1 + input.choose(8) {
+        let htlcs = channel.htlcs();
+        let op = match input.choose(4) {
+            0 | 1 if htlcs.len() < MAX_PENDING_HTLCS && channel.local_balance() > 0 => {
+                let amount = 1 + input.choose(channel.local_balance() as usize) as u64;
+                if input.u8().is_multiple_of(2) {
+                    ChannelOp::Pay(amount)
+                } else {
+                    ChannelOp::AddHtlc(amount)
+                }
+            }
+            2 if !htlcs.is_empty() => ChannelOp::SettleHtlc(htlcs[input.choose(htlcs.len())].id),
+            3 if !htlcs.is_empty() => ChannelOp::FailHtlc(htlcs[input.choose(htlcs.len())].id),
+            _ => continue,
+        };
+        channel.apply(&mut local, op);
+    }
+
+    let remote_balance = channel.remote_balance();
+    let local_balance = capacity - remote_balance;
+    if channel.htlcs().is_empty() && input.u8().is_multiple_of(2) {
+        channel.cooperative_close(&mut local, &mut remote);
+    } else {
+        channel.force_close(&mut local, &mut remote);
+    }
+    let local_amounts = local
+        .contract_fungible_allocations(contract_id, false)
+        .into_iter()
+        .map(|a| a.state.value())
+        .sum::<u64>();
+    assert_eq!(local_amounts, local_balance);
+    if remote_balance > 0 {
+        remote.check_allocations(contract_id, schema_id, vec![remote_balance], false);
+    }
+}
@@ -0,0 +1,162 @@
+use super::*;
+
+pub const MEDIA_DATA_DIR: &str = "media";
+
+/// ID of an attachment in a media store, the hex of its sha256 digest
+pub fn attachment_id(attachment: &Attachment) -> String {
+    sha256::Hash::from_byte_array(attachment.digest.to_byte_array()).to_string()
+}
+
+/// Where the files referenced by contract attachments can be fetched from
+#[derive(Clone, Debug)]
+pub enum MediaStore {
+    /// Directory holding one file per attachment, named after its ID
+    Local(PathBuf),
+    /// RGB proxy server, storing files through its `media.*` methods
+    Proxy(RgbProxyClient),
+}
+
+impl MediaStore {
+    /// Local store in a new directory, to be shared by the wallets of a test
+    pub fn local() -> Self {
+        let dir = PathBuf::from(TEST_DATA_DIR)
+            .join(MEDIA_DATA_DIR)
+            .join(format!(
+                "{}-{}",
+                std::process::id(),
+                Utc::now().timestamp_nanos_opt().unwrap()
+            ));
+        std::fs::create_dir_all(&dir).unwrap();
+        Self::Local(dir)
+    }
+
+    pub fn proxy(proxy: &RgbProxy) -> Self {
+        Self::Proxy(proxy.client())
+    }
+
+    /// Store the given bytes for the attachment, without checking they match it
+    pub fn upload(&self, attachment: &Attachment, file: &[u8]) {
+        let attachment_id = attachment_id(attachment);
+        match self {
+            Self::Local(dir) => {
+                std::fs::create_dir_all(dir).unwrap();
+                std::fs::write(dir.join(attachment_id), file).unwrap();
+            }
+            Self::Proxy(client) => {
+                client.post_media(&attachment_id, file).unwrap();
+            }
+        }
+    }
+
+    /// Store the file at the given path, returning the attachment referencing it
+    pub fn upload_fpath(&self, fpath: &str) -> Attachment {
+        let attachment = attachment_from_fpath(fpath);
+        self.upload(&attachment, &std::fs::read(fpath).unwrap());
+        attachment
+    }
+
+    /// Remove the file of the attachment, only supported by local stores
+    pub fn remove(&self, attachment: &Attachment) {
+        match self {
+            Self::Local(dir) => std::fs::remove_file(dir.join(attachment_id(attachment))).unwrap(),
+            Self::Proxy(_) => panic!("cannot remove files from a proxy"),
+        }
+    }
+
+    /// Fetch the file of the attachment, `None` if the store doesn't have it
+    pub fn download(&self, attachment: &Attachment) -> Result<Option<Vec<u8>>, String> {
+        let attachment_id = attachment_id(attachment);
+        match self {
+            Self::Local(dir) => match std::fs::read(dir.join(attachment_id)) {
+                Ok(file) => Ok(Some(file)),
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+                Err(e) => Err(e.to_string()),
+            },
+            Self::Proxy(client) => match client.get_media(&attachment_id) {
+                Ok(file) => Ok(Some(file)),
+                Err(e) if e.rpc_code() == Some(ERR_NOT_FOUND_MEDIA) => Ok(None),
+                Err(e) => Err(format!("{e:?}")),
+            },
+        }
+    }
+}
+
+/// Attachment of a contract, as referenced by its terms or token data
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MediaLocation {
+    Terms,
+    TokenMedia,
+    TokenAttachment(u8),
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum MediaIssue {
+    /// The store doesn't have the file
+    Missing,
+    /// The store couldn't be queried
+    Unreachable(String),
+    /// The file doesn't hash to the attachment digest
+    DigestMismatch { found: String },
+    /// The file format doesn't match the attachment media type
+    MediaTypeMismatch { found: String },
+}
+
+/// Attachment whose file failed verification
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MediaMismatch {
+    pub location: MediaLocation,
+    pub attachment: Attachment,
+    pub issue: MediaIssue,
+}
+
+/// Fetch the file of the attachment and check it matches its digest and media type
+pub fn verify_attachment(store: &MediaStore, attachment: &Attachment) -> Option<MediaIssue> {
+    let file = match store.download(attachment) {
+        Ok(Some(file)) => file,
+        Ok(None) => return Some(MediaIssue::Missing),
+        Err(e) => return Some(MediaIssue::Unreachable(e)),
+    };
+    let digest = sha256::Hash::hash(&file).to_string();
+    if digest != attachment_id(attachment) {
+        return Some(MediaIssue::DigestMismatch { found: digest });
+    }
+    let media_type = FileFormat::from_bytes(&file).media_type().to_string();
+    if media_type != attachment.ty.to_string() {
+        return Some(MediaIssue::MediaTypeMismatch { found: media_type });
+    }
+    None
+}
+
+impl<W: WalletProvider, D> TestWallet<W, D>
+where
+    Self: TestWalletExt,
+    <Self as TestWalletExt>::Psbt: Serialize,
+{
+    /// Verify the files of all the attachments of a UDA contract against the wallet media store.
+    /// The token preview is embedded in the contract, so it isn't checked.
+    pub fn verify_uda_media(&self, contract_id: ContractId) -> Vec<MediaMismatch> {
+        let contract = self.contract_wrapper::<UniqueDigitalAsset>(contract_id);
+        let token_data = contract.token_data();
+        let attachments = contract
+            .contract_terms()
+            .media
+            .map(|a| (MediaLocation::Terms, a))
+            .into_iter()
+            .chain(token_data.media.map(|a| (MediaLocation::TokenMedia, a)))
+            .chain(
+                token_data
+                    .attachments
+                    .iter()
+                    .map(|(idx, a)| (MediaLocation::TokenAttachment(*idx), a.clone())),
+            );
+        attachments
+            .filter_map(|(location, attachment)| {
+                verify_attachment(self.media_store(), &attachment).map(|issue| MediaMismatch {
+                    location,
+                    attachment,
+                    issue,
+                })
+            })
+            .collect()
+    }
+}
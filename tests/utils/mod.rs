@@ -1,4 +1,18 @@
 pub mod chain;
+pub mod consignment_fuzz;
+pub mod http;
+pub mod indexer_proxy;
+pub mod indexer_server;
+pub mod ln_channel;
+pub mod media_store;
+pub mod reject_list;
+pub mod reorg_sim;
+pub mod rgb_proxy;
+pub mod sim_chain;
+pub mod swap;
+pub mod test_context;
+pub mod transfer_matrix;
+pub mod validation_fixtures;
 pub mod wallet;
 
 pub const TEST_DATA_DIR: &str = "test-data";
@@ -36,20 +50,21 @@ pub use std::os::unix::process::CommandExt;
 pub use std::{
     borrow::Borrow,
     cell::{OnceCell, RefCell},
-    cmp::max,
+    cmp::{Reverse, max},
     collections::{BTreeMap, BTreeSet, HashMap, HashSet},
     env::VarError,
     ffi::OsString,
     fmt::{self, Display},
     fs::OpenOptions,
-    io::Write,
+    io::{self, BufRead, BufReader, Read, Write},
+    net::{SocketAddr, TcpListener, TcpStream},
     num::NonZeroU32,
     path::{MAIN_SEPARATOR, PathBuf},
     process::{Command, Stdio},
     str::FromStr,
     sync::Arc,
     sync::atomic::{AtomicBool, Ordering},
-    sync::{Mutex, Once, OnceLock, RwLock},
+    sync::{Mutex, MutexGuard, Once, OnceLock, RwLock},
     time::{Duration, Instant},
 };
 
@@ -59,11 +74,12 @@ pub use amplify::{
         Collection, Confined, LargeVec, NonEmptyOrdMap, NonEmptyOrdSet, NonEmptyVec, SmallBlob,
         SmallOrdMap, TinyOrdMap, TinyOrdSet, U16,
     },
-    hex::FromHex,
+    hex::{FromHex, ToHex},
     map, none,
     num::u24,
     s, set,
 };
+pub use base64::{Engine as _, engine::general_purpose::STANDARD as BASE64};
 pub use bdk_electrum::{
     BdkElectrumClient,
     electrum_client::{Client as ElectrumClient, ElectrumApi as _},
@@ -75,7 +91,7 @@ pub use bdk_esplora::{
 pub use bdk_wallet::{
     ChangeSet, KeychainKind, PersistedWallet, SignOptions, Update, Wallet as BdkWallet,
     bitcoin::{
-        bip32::Xpriv,
+        bip32::{DerivationPath as BdkDerivationPath, Xpriv, Xpub as BdkXpub},
         psbt::{Output as BdkOutput, raw::ProprietaryKey},
     },
     chain::{
@@ -83,6 +99,7 @@ pub use bdk_wallet::{
         spk_client::{FullScanRequest, FullScanResponse},
     },
     file_store::Store,
+    miniscript::Descriptor as BdkDescriptor,
 };
 pub use bitcoin_hashes::{Hash, sha256};
 pub use bpwallet::{
@@ -108,6 +125,7 @@ pub use lazy_static::lazy_static;
 #[cfg(not(target_os = "windows"))]
 pub use nix::unistd::{self, Pid};
 pub use once_cell::sync::Lazy;
+pub use proptest::prelude::*;
 #[cfg(not(feature = "altered"))]
 pub use psrgbt::{
     OpoutAndOpids, RgbOutExt, RgbPropKeyExt, RgbPsbtExt, Terminal,
@@ -141,9 +159,18 @@ pub use rgb::{
     TypedAssigns, Vin, VoidState, WalletProvider, WpkhDescr,
     assignments::AssignVec,
     bitcoin::{
-        Address, CompressedPublicKey, Network, Psbt, ScriptBuf, TapLeafHash, TapNodeHash,
-        Transaction, constants::ChainHash, hashes::sha256d, key::Secp256k1 as BitcoinSecp256k1,
-        taproot::LeafScript, taproot::LeafVersion,
+        Address, Amount as BtcAmount, Block, BlockHash, CompressedPublicKey, Network, Psbt,
+        ScriptBuf, Sequence, TapLeafHash, TapNodeHash, Transaction, TxIn, TxMerkleNode, TxOut,
+        Witness,
+        absolute::LockTime as BtcLockTime,
+        block::{Header as BlockHeader, Version as BlockVersion},
+        consensus::{deserialize as consensus_deserialize, serialize as consensus_serialize},
+        constants::{ChainHash, genesis_block},
+        hashes::sha256d,
+        key::Secp256k1 as BitcoinSecp256k1,
+        taproot::LeafScript,
+        taproot::LeafVersion,
+        transaction::Version as TxVersion,
     },
     containers::{PubWitness, ValidContract, WitnessBundle},
     contract::{
@@ -173,9 +200,18 @@ pub use rgb_altered::{
     WalletProvider, WpkhDescr,
     assignments::AssignVec,
     bitcoin::{
-        Address, CompressedPublicKey, Network, Psbt, ScriptBuf, TapLeafHash, TapNodeHash,
-        Transaction, constants::ChainHash, hashes::sha256d, key::Secp256k1 as BitcoinSecp256k1,
-        taproot::LeafScript, taproot::LeafVersion,
+        Address, Amount as BtcAmount, Block, BlockHash, CompressedPublicKey, Network, Psbt,
+        ScriptBuf, Sequence, TapLeafHash, TapNodeHash, Transaction, TxIn, TxMerkleNode, TxOut,
+        Witness,
+        absolute::LockTime as BtcLockTime,
+        block::{Header as BlockHeader, Version as BlockVersion},
+        consensus::{deserialize as consensus_deserialize, serialize as consensus_serialize},
+        constants::{ChainHash, genesis_block},
+        hashes::sha256d,
+        key::Secp256k1 as BitcoinSecp256k1,
+        taproot::LeafScript,
+        taproot::LeafVersion,
+        transaction::Version as TxVersion,
     },
     containers::{PubWitness, ValidContract, WitnessBundle},
     contract::{
@@ -215,8 +251,8 @@ pub use rgbstd::{
         IssuerWrapper, LinkableSchemaWrapper, TransitionBuilder,
     },
     indexers::AnyResolver,
-    invoice::{Beneficiary, RgbInvoice, RgbInvoiceBuilder, XChainNet},
-    persistence::{ContractStateRead, StashReadProvider, fs::FsBinStore},
+    invoice::{Beneficiary, InvoiceState, RgbInvoice, RgbInvoiceBuilder, RgbTransport, XChainNet},
+    persistence::{ContractStateRead, StashReadProvider, UpdateRes, fs::FsBinStore},
     schema::SchemaId,
     stl::{
         AssetSpec, Attachment, Details, EmbeddedMedia, MediaType, Name, ProofOfReserves,
@@ -226,23 +262,30 @@ pub use rgbstd::{
 pub use rstest::rstest;
 pub use schemata::{
     CFA_SCHEMA_ID, CollectibleFungibleAsset, ERRNO_BURN_MISMATCH, ERRNO_BURN_ZERO,
-    ERRNO_INFLATION_MISMATCH, ERRNO_ISSUED_MISMATCH, ERRNO_NON_EQUAL_IN_OUT, GS_ISSUED_SUPPLY,
-    IFA_SCHEMA_ID, IfaWrapper, InflatableFungibleAsset, MS_ALLOWED_INFLATION, NIA_SCHEMA_ID,
-    NonInflatableAsset, OS_ASSET, OS_INFLATION, OS_LINK, PFA_SCHEMA_ID, PermissionedFungibleAsset,
-    TS_BURN, TS_INFLATION, TS_TRANSFER, UDA_SCHEMA_ID, UniqueDigitalAsset, burn_meta_by_assignment,
+    ERRNO_INFLATION_MISMATCH, ERRNO_INVALID_SIGNATURE, ERRNO_ISSUED_MISMATCH,
+    ERRNO_NON_EQUAL_IN_OUT, GS_ISSUED_SUPPLY, IFA_SCHEMA_ID, IfaWrapper, InflatableFungibleAsset,
+    MS_ALLOWED_INFLATION, NIA_SCHEMA_ID, NonInflatableAsset, OS_ASSET, OS_INFLATION, OS_LINK,
+    PFA_SCHEMA_ID, PermissionedFungibleAsset, TS_BURN, TS_INFLATION, TS_TRANSFER, UDA_SCHEMA_ID,
+    UniqueDigitalAsset, burn_meta_by_assignment,
 };
 pub use serde::{Deserialize, Serialize};
 pub use serde_json::{Value, json};
-pub use serial_test::serial;
 pub use signal_hook::consts::{SIGINT, SIGTERM};
 pub use signal_hook::flag::register;
-pub use strict_encoding::{FieldName, StrictSerialize, TypeName, fname, tn};
+pub use strict_encoding::{
+    DecodeError, FieldName, ReadRaw, StrictDecode, StrictReader, StrictSerialize, TypeName, fname,
+    tn,
+};
 pub use strict_types::{SemId, StrictDeserialize, StrictDumb, StrictVal, TypeSystem};
 pub use strum::IntoEnumIterator;
 pub use strum_macros::EnumIter;
 pub use time::OffsetDateTime;
 
-pub use crate::utils::{chain::*, wallet::*};
+pub use crate::{
+    chain::*, consignment_fuzz::*, http::*, indexer_proxy::*, indexer_server::*, ln_channel::*,
+    media_store::*, reject_list::*, reorg_sim::*, rgb_proxy::*, sim_chain::*, swap::*,
+    test_context::*, transfer_matrix::*, validation_fixtures::*, wallet::*,
+};
 
 fn running_in_docker() -> bool {
     std::path::Path::new("/.dockerenv").exists()
@@ -0,0 +1,172 @@
+use super::*;
+
+pub const REJECT_LIST_PATH: &str = "/rejectList";
+
+/// How a wallet honours the reject list of the contracts it receives
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum RejectListPolicy {
+    /// Don't fetch reject lists
+    #[default]
+    Ignore,
+    /// Accept transfers involving rejected opouts or whose reject list can't be fetched, adding
+    /// a warning to the validation status
+    Flag,
+    /// Refuse transfers involving rejected opouts or whose reject list can't be fetched
+    Refuse,
+}
+
+struct RejectListState {
+    opouts: RwLock<BTreeSet<Opout>>,
+    available: AtomicBool,
+    requests: Mutex<usize>,
+}
+
+/// Local stand-in for a reject list server, serving one opout per line
+pub struct RejectListServer {
+    state: Arc<RejectListState>,
+    server: LocalServer,
+}
+
+impl RejectListServer {
+    pub fn start() -> Self {
+        let state = Arc::new(RejectListState {
+            opouts: RwLock::new(bset![]),
+            available: AtomicBool::new(true),
+            requests: Mutex::new(0),
+        });
+        let server_state = state.clone();
+        let server = LocalServer::http(move |request| {
+            *server_state.requests.lock().unwrap() += 1;
+            if !server_state.available.load(Ordering::SeqCst) {
+                return HttpResponse::error(503, "service unavailable");
+            }
+            if request.method != "GET" || request.path != REJECT_LIST_PATH {
+                return HttpResponse::not_found();
+            }
+            let list = server_state
+                .opouts
+                .read()
+                .unwrap()
+                .iter()
+                .map(|opout| format!("{opout}\n"))
+                .collect::<String>();
+            HttpResponse::text(list)
+        });
+        Self { state, server }
+    }
+
+    /// URL to set in the contract terms, which don't include the scheme
+    pub fn url(&self) -> String {
+        format!("{}{REJECT_LIST_PATH}", self.server.addr())
+    }
+
+    pub fn list(&self, opout: Opout) {
+        self.state.opouts.write().unwrap().insert(opout);
+    }
+
+    pub fn delist(&self, opout: Opout) {
+        self.state.opouts.write().unwrap().remove(&opout);
+    }
+
+    pub fn set_available(&self, available: bool) {
+        self.state.available.store(available, Ordering::SeqCst);
+    }
+
+    pub fn requests(&self) -> usize {
+        *self.state.requests.lock().unwrap()
+    }
+}
+
+/// Fetch the opouts listed at the given reject list URL, which has no scheme
+pub fn fetch_reject_list(url: &str) -> Result<BTreeSet<Opout>, String> {
+    let response = http_call("GET", &format!("http://{url}"), &[]).map_err(|e| e.to_string())?;
+    if response.status != 200 {
+        return Err(format!(
+            "reject list answered with status {}",
+            response.status
+        ));
+    }
+    String::from_utf8_lossy(&response.body)
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(|line| Opout::from_str(line).map_err(|e| format!("invalid opout {line}: {e}")))
+        .collect()
+}
+
+/// Reject list URL set in the genesis of the consignment contract, if any
+pub fn consignment_reject_list_url<const TRANSFER: bool>(
+    consignment: &Consignment<TRANSFER>,
+) -> Option<String> {
+    let (global_type, _) = consignment
+        .schema
+        .global_types
+        .iter()
+        .find(|(_, details)| details.name == fname!("rejectListUrl"))?;
+    let data = consignment.genesis.globals.get(global_type)?.first()?;
+    let url = RejectListUrl::from_strict_serialized::<U16>(Confined::from_checked(
+        data.as_slice().to_vec(),
+    ))
+    .unwrap();
+    Some(url.to_string())
+}
+
+/// Opouts created by the operations of the consignment, i.e. the whole history it proves
+pub fn consignment_opouts<const TRANSFER: bool>(
+    consignment: &Consignment<TRANSFER>,
+) -> BTreeSet<Opout> {
+    let mut opouts = bset![];
+    let genesis_id = consignment.genesis.id();
+    for (assignment_type, assigns) in consignment.genesis.assignments.iter() {
+        for no in 0..assigns.len_u16() {
+            opouts.insert(Opout::new(genesis_id, *assignment_type, no));
+        }
+    }
+    for wbundle in consignment.bundles.iter() {
+        for KnownTransition { opid, transition } in wbundle.bundle.known_transitions.iter() {
+            for (assignment_type, assigns) in transition.assignments.iter() {
+                for no in 0..assigns.len_u16() {
+                    opouts.insert(Opout::new(*opid, *assignment_type, no));
+                }
+            }
+        }
+    }
+    opouts
+}
+
+/// Problem found checking a consignment against the reject list of its contract
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum RejectListIssue {
+    /// The consignment history involves the given listed opouts
+    Rejected(BTreeSet<Opout>),
+    /// The reject list couldn't be fetched
+    Unreachable(String),
+}
+
+impl fmt::Display for RejectListIssue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Rejected(opouts) => {
+                let opouts = opouts.iter().map(Opout::to_string).collect::<Vec<_>>();
+                write!(f, "rejected opouts: {}", opouts.join(", "))
+            }
+            Self::Unreachable(e) => write!(f, "unreachable reject list: {e}"),
+        }
+    }
+}
+
+/// Check the consignment against the reject list of its contract, if it has one
+pub fn check_reject_list<const TRANSFER: bool>(
+    consignment: &Consignment<TRANSFER>,
+) -> Option<RejectListIssue> {
+    let url = consignment_reject_list_url(consignment)?;
+    let rejected = match fetch_reject_list(&url) {
+        Ok(rejected) => rejected,
+        Err(e) => return Some(RejectListIssue::Unreachable(e)),
+    };
+    let involved = consignment_opouts(consignment)
+        .intersection(&rejected)
+        .copied()
+        .collect::<BTreeSet<_>>();
+    (!involved.is_empty()).then_some(RejectListIssue::Rejected(involved))
+}
@@ -0,0 +1,93 @@
+use super::*;
+
+/// Transactions confirmed by each block of a branch, in order
+pub type BranchBlocks = Vec<Vec<Txid>>;
+
+/// Competing branches forked from the chain of an instance, each one described by the
+/// transactions confirmed in its blocks.
+///
+/// Branches are built from the fork point each time they get activated, so they can be changed
+/// (e.g. moving transactions among them) and activated again any number of times. Activating a
+/// branch also evicts from the mempool the transactions listed only in the other branches, as if
+/// the nodes had never seen them.
+pub struct ReorgSim {
+    chain: Arc<SimChain>,
+    instance: u8,
+    base: BranchId,
+    branches: Vec<BranchBlocks>,
+}
+
+impl ReorgSim {
+    /// Fork the chain of the given instance `depth` blocks below its tip
+    pub fn with_chain(chain: Arc<SimChain>, instance: u8, depth: u32) -> Self {
+        let tip = chain.height_of(instance).unwrap();
+        let fork_height = tip.checked_sub(depth).expect("fork below genesis");
+        let base = chain.fork(instance, fork_height).unwrap();
+        Self {
+            chain,
+            instance,
+            base,
+            branches: vec![],
+        }
+    }
+
+    pub fn fork_height(&self) -> u32 {
+        self.chain.branch_height(self.base).unwrap()
+    }
+
+    /// Add a branch with the given blocks, returning its index
+    pub fn branch(&mut self, blocks: BranchBlocks) -> usize {
+        self.branches.push(blocks);
+        self.branches.len() - 1
+    }
+
+    /// Add a branch of the given length, without any transaction
+    pub fn empty_branch(&mut self, length: usize) -> usize {
+        self.branch(vec![vec![]; length])
+    }
+
+    /// Append empty blocks to a branch
+    pub fn extend(&mut self, branch: usize, blocks: usize) {
+        self.branches[branch].extend(vec![vec![]; blocks]);
+    }
+
+    /// Move the given transactions to a block of a branch, removing them from anywhere else
+    pub fn move_txs(&mut self, txids: &[Txid], branch: usize, block: usize) {
+        for blocks in &mut self.branches {
+            for block_txids in blocks.iter_mut() {
+                block_txids.retain(|txid| !txids.contains(txid));
+            }
+        }
+        let blocks = &mut self.branches[branch];
+        if blocks.len() <= block {
+            blocks.resize(block + 1, vec![]);
+        }
+        blocks[block].extend(txids);
+    }
+
+    /// Height at which the given transaction gets confirmed by a branch
+    pub fn tx_height(&self, branch: usize, txid: Txid) -> Option<u32> {
+        self.branches[branch]
+            .iter()
+            .position(|block_txids| block_txids.contains(&txid))
+            .map(|pos| self.fork_height() + pos as u32 + 1)
+    }
+
+    /// Build a branch and reorg the instance (and its connected nodes) to it
+    pub fn activate(&self, branch: usize) {
+        let id = self.chain.fork_branch(self.base).unwrap();
+        for block_txids in &self.branches[branch] {
+            self.chain.mine_branch(id, 1, block_txids).unwrap();
+        }
+        self.chain.reorg_to(self.instance, id).unwrap();
+        let foreign_txids = self
+            .branches
+            .iter()
+            .flatten()
+            .flatten()
+            .filter(|txid| self.tx_height(branch, **txid).is_none())
+            .copied()
+            .collect::<Vec<_>>();
+        self.chain.evict(self.instance, &foreign_txids).unwrap();
+    }
+}
@@ -0,0 +1,622 @@
+use super::*;
+
+pub const RGB_PROXY_PROTOCOL_VERSION: &str = "0.2";
+pub const RGB_PROXY_JSON_RPC_PATH: &str = "/json-rpc";
+pub const RGB_PROXY_CLIENT_TIMEOUT: Duration = Duration::from_secs(5);
+pub const RGB_PROXY_RETRY_DELAY: Duration = Duration::from_millis(100);
+pub const ACK_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+// error codes of the RGB proxy JSON-RPC API
+pub const ERR_CANNOT_CHANGE_ACK: i64 = -100;
+pub const ERR_CANNOT_CHANGE_UPLOADED_FILE: i64 = -101;
+pub const ERR_INVALID_ACK: i64 = -200;
+pub const ERR_INVALID_FILE: i64 = -201;
+pub const ERR_MISSING_ACK: i64 = -300;
+pub const ERR_MISSING_ATTACHMENT_ID: i64 = -301;
+pub const ERR_MISSING_FILE: i64 = -302;
+pub const ERR_MISSING_RECIPIENT_ID: i64 = -303;
+pub const ERR_MISSING_TXID: i64 = -304;
+pub const ERR_NOT_FOUND_CONSIGNMENT: i64 = -400;
+pub const ERR_NOT_FOUND_MEDIA: i64 = -401;
+pub const ERR_PARSE: i64 = -32700;
+pub const ERR_METHOD_NOT_FOUND: i64 = -32601;
+
+/// Misbehaviour an [`RgbProxy`] can inject while serving requests
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum RgbProxyFault {
+    /// Wait before handling each request
+    Delay(Duration),
+    /// Answer the given amount of upcoming requests with a 503 error
+    Unavailable(usize),
+    /// Close connections without answering
+    Drop,
+}
+
+struct StoredConsignment {
+    file: Vec<u8>,
+    txid: String,
+    vout: Option<u32>,
+}
+
+type RpcResult = Result<Value, (i64, String)>;
+
+fn rpc_err(code: i64, message: &str) -> (i64, String) {
+    (code, message.to_string())
+}
+
+fn param_str(params: &Value, name: &str, missing_code: i64) -> Result<String, (i64, String)> {
+    params[name]
+        .as_str()
+        .filter(|s| !s.is_empty())
+        .map(str::to_string)
+        .ok_or_else(|| rpc_err(missing_code, &format!("missing {name}")))
+}
+
+fn upload(file: Option<Vec<u8>>) -> Result<Vec<u8>, (i64, String)> {
+    file.filter(|f| !f.is_empty())
+        .ok_or_else(|| rpc_err(ERR_MISSING_FILE, "missing file"))
+}
+
+struct RelayState {
+    started: Instant,
+    consignments: Mutex<HashMap<String, StoredConsignment>>,
+    acks: Mutex<HashMap<String, bool>>,
+    media: Mutex<HashMap<String, Vec<u8>>>,
+    faults: RwLock<Vec<RgbProxyFault>>,
+    requests: Mutex<usize>,
+}
+
+impl RelayState {
+    /// Count the request and apply faults acting before handling it, returning the response to
+    /// give instead of handling it, if any, or an error if the connection should be dropped
+    fn before_handle(&self) -> Result<Option<HttpResponse>, ()> {
+        *self.requests.lock().unwrap() += 1;
+        let faults = self.faults.read().unwrap().clone();
+        for fault in faults {
+            match fault {
+                RgbProxyFault::Delay(delay) => std::thread::sleep(delay),
+                RgbProxyFault::Drop => return Err(()),
+                RgbProxyFault::Unavailable(_) => {}
+            }
+        }
+        let mut faults = self.faults.write().unwrap();
+        if let Some(pos) = faults
+            .iter()
+            .position(|f| matches!(f, RgbProxyFault::Unavailable(_)))
+        {
+            let RgbProxyFault::Unavailable(left) = &mut faults[pos] else {
+                unreachable!()
+            };
+            if *left > 0 {
+                *left -= 1;
+                return Ok(Some(HttpResponse::error(503, "service unavailable")));
+            }
+            faults.remove(pos);
+        }
+        Ok(None)
+    }
+
+    fn handle(&self, mut stream: TcpStream) {
+        let Some(request) = read_http_request(&stream) else {
+            return;
+        };
+        let response = match self.before_handle() {
+            Err(()) => return,
+            Ok(Some(response)) => response,
+            Ok(None) if request.method != "POST" || request.path != RGB_PROXY_JSON_RPC_PATH => {
+                HttpResponse::not_found()
+            }
+            Ok(None) => HttpResponse::json(&self.json_rpc(&request)),
+        };
+        write_http_response(&mut stream, &response);
+    }
+
+    /// Parse a JSON-RPC request, either sent as JSON or, when it uploads a file, as a multipart
+    /// form with the `jsonrpc`, `id`, `method` and `params` fields next to the `file` one
+    fn parse_request(request: &HttpRequest) -> Option<(Value, Option<Vec<u8>>)> {
+        if !request.content_type.starts_with("multipart/form-data") {
+            return Some((serde_json::from_slice(&request.body).ok()?, None));
+        }
+        let mut parts = parse_multipart(&request.content_type, &request.body)?;
+        let file = parts.remove("file");
+        let mut field = |name: &str| {
+            parts
+                .remove(name)
+                .map(|v| String::from_utf8(v).ok())
+                .unwrap_or_default()
+        };
+        let params = match field("params") {
+            Some(params) => serde_json::from_str(&params).ok()?,
+            None => Value::Null,
+        };
+        let request = json!({
+            "jsonrpc": field("jsonrpc"),
+            "id": field("id"),
+            "method": field("method"),
+            "params": params,
+        });
+        Some((request, file))
+    }
+
+    fn json_rpc(&self, request: &HttpRequest) -> Value {
+        let Some((request, file)) = Self::parse_request(request) else {
+            return json!({
+                "jsonrpc": "2.0",
+                "id": null,
+                "error": {"code": ERR_PARSE, "message": "parse error"},
+            });
+        };
+        let id = request["id"].clone();
+        let method = request["method"].as_str().unwrap_or_default();
+        match self.call(method, &request["params"], file) {
+            Ok(result) => json!({"jsonrpc": "2.0", "id": id, "result": result}),
+            Err((code, message)) => json!({
+                "jsonrpc": "2.0",
+                "id": id,
+                "error": {"code": code, "message": message},
+            }),
+        }
+    }
+
+    fn call(&self, method: &str, params: &Value, file: Option<Vec<u8>>) -> RpcResult {
+        match method {
+            "server.info" => Ok(json!({
+                "protocol_version": RGB_PROXY_PROTOCOL_VERSION,
+                "version": env!("CARGO_PKG_VERSION"),
+                "uptime": self.started.elapsed().as_secs(),
+            })),
+            "consignment.post" => {
+                let recipient_id = param_str(params, "recipient_id", ERR_MISSING_RECIPIENT_ID)?;
+                let txid = param_str(params, "txid", ERR_MISSING_TXID)?;
+                let file = upload(file)?;
+                let vout = params["vout"].as_u64().map(|v| v as u32);
+                let mut consignments = self.consignments.lock().unwrap();
+                match consignments.get(&recipient_id) {
+                    // re-posting the same file is allowed, so that uploads can be retried
+                    Some(stored) if stored.file == file => Ok(json!(false)),
+                    Some(_) => Err(rpc_err(
+                        ERR_CANNOT_CHANGE_UPLOADED_FILE,
+                        "cannot change uploaded file",
+                    )),
+                    None => {
+                        consignments.insert(recipient_id, StoredConsignment { file, txid, vout });
+                        Ok(json!(true))
+                    }
+                }
+            }
+            "consignment.get" => {
+                let recipient_id = param_str(params, "recipient_id", ERR_MISSING_RECIPIENT_ID)?;
+                let consignments = self.consignments.lock().unwrap();
+                let stored = consignments
+                    .get(&recipient_id)
+                    .ok_or_else(|| rpc_err(ERR_NOT_FOUND_CONSIGNMENT, "consignment not found"))?;
+                Ok(json!({
+                    "consignment": BASE64.encode(&stored.file),
+                    "txid": stored.txid,
+                    "vout": stored.vout,
+                }))
+            }
+            "ack.post" => {
+                let recipient_id = param_str(params, "recipient_id", ERR_MISSING_RECIPIENT_ID)?;
+                let ack = match &params["ack"] {
+                    Value::Null => return Err(rpc_err(ERR_MISSING_ACK, "missing ack")),
+                    Value::Bool(ack) => *ack,
+                    _ => return Err(rpc_err(ERR_INVALID_ACK, "ack must be a boolean")),
+                };
+                if !self
+                    .consignments
+                    .lock()
+                    .unwrap()
+                    .contains_key(&recipient_id)
+                {
+                    return Err(rpc_err(ERR_NOT_FOUND_CONSIGNMENT, "consignment not found"));
+                }
+                let mut acks = self.acks.lock().unwrap();
+                if acks.contains_key(&recipient_id) {
+                    return Err(rpc_err(ERR_CANNOT_CHANGE_ACK, "cannot change ACK"));
+                }
+                acks.insert(recipient_id, ack);
+                Ok(json!(true))
+            }
+            "ack.get" => {
+                let recipient_id = param_str(params, "recipient_id", ERR_MISSING_RECIPIENT_ID)?;
+                if !self
+                    .consignments
+                    .lock()
+                    .unwrap()
+                    .contains_key(&recipient_id)
+                {
+                    return Err(rpc_err(ERR_NOT_FOUND_CONSIGNMENT, "consignment not found"));
+                }
+                Ok(json!(self.acks.lock().unwrap().get(&recipient_id)))
+            }
+            "media.post" => {
+                let attachment_id = param_str(params, "attachment_id", ERR_MISSING_ATTACHMENT_ID)?;
+                let file = upload(file)?;
+                let mut media = self.media.lock().unwrap();
+                match media.get(&attachment_id) {
+                    Some(stored) if *stored == file => Ok(json!(false)),
+                    Some(_) => Err(rpc_err(
+                        ERR_CANNOT_CHANGE_UPLOADED_FILE,
+                        "cannot change uploaded file",
+                    )),
+                    None => {
+                        media.insert(attachment_id, file);
+                        Ok(json!(true))
+                    }
+                }
+            }
+            "media.get" => {
+                let attachment_id = param_str(params, "attachment_id", ERR_MISSING_ATTACHMENT_ID)?;
+                self.media
+                    .lock()
+                    .unwrap()
+                    .get(&attachment_id)
+                    .map(|file| json!(BASE64.encode(file)))
+                    .ok_or_else(|| rpc_err(ERR_NOT_FOUND_MEDIA, "media not found"))
+            }
+            _ => Err(rpc_err(ERR_METHOD_NOT_FOUND, "method not found")),
+        }
+    }
+}
+
+/// Local stand-in for an RGB proxy server, relaying consignments and ACKs between wallets.
+///
+/// It serves the RGB proxy JSON-RPC methods: files are uploaded as multipart forms and returned
+/// base64-encoded, as the real server does.
+pub struct RgbProxy {
+    state: Arc<RelayState>,
+    server: LocalServer,
+}
+
+impl RgbProxy {
+    pub fn start() -> Self {
+        let state = Arc::new(RelayState {
+            started: Instant::now(),
+            consignments: Mutex::new(HashMap::new()),
+            acks: Mutex::new(HashMap::new()),
+            media: Mutex::new(HashMap::new()),
+            faults: RwLock::new(vec![]),
+            requests: Mutex::new(0),
+        });
+        let server_state = state.clone();
+        let server = LocalServer::tcp(move |stream| server_state.handle(stream));
+        Self { state, server }
+    }
+
+    pub fn url(&self) -> String {
+        self.server.http_url()
+    }
+
+    pub fn client(&self) -> RgbProxyClient {
+        RgbProxyClient::new(&self.url())
+    }
+
+    pub fn set_faults(&self, faults: Vec<RgbProxyFault>) {
+        *self.state.faults.write().unwrap() = faults;
+    }
+
+    pub fn clear_faults(&self) {
+        self.set_faults(vec![]);
+    }
+
+    pub fn requests(&self) -> usize {
+        *self.state.requests.lock().unwrap()
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum RgbProxyError {
+    /// The server couldn't be reached or didn't answer in time
+    Transport(String),
+    /// The server answered with an HTTP error status
+    Http(u16),
+    /// The server answered with a JSON-RPC error
+    Rpc { code: i64, message: String },
+}
+
+impl RgbProxyError {
+    /// Whether the same request may succeed if sent again
+    pub fn is_transient(&self) -> bool {
+        match self {
+            RgbProxyError::Transport(_) => true,
+            RgbProxyError::Http(status) => *status >= 500,
+            RgbProxyError::Rpc { .. } => false,
+        }
+    }
+
+    pub fn rpc_code(&self) -> Option<i64> {
+        match self {
+            RgbProxyError::Rpc { code, .. } => Some(*code),
+            _ => None,
+        }
+    }
+}
+
+/// Consignment fetched from an RGB proxy
+pub struct ProxyConsignment {
+    pub consignment: Transfer,
+    pub txid: Txid,
+    pub vout: Option<u32>,
+}
+
+/// Blocking client of an RGB proxy server, retrying transient failures
+#[derive(Clone, Debug)]
+pub struct RgbProxyClient {
+    url: String,
+    timeout: Duration,
+    retries: usize,
+}
+
+impl RgbProxyClient {
+    pub fn new(url: &str) -> Self {
+        Self {
+            url: url.trim_end_matches('/').to_string(),
+            timeout: RGB_PROXY_CLIENT_TIMEOUT,
+            retries: 0,
+        }
+    }
+
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    pub fn with_retries(mut self, retries: usize) -> Self {
+        self.retries = retries;
+        self
+    }
+
+    fn call(&self, method: &str, params: Value) -> Result<Value, RgbProxyError> {
+        let body = serde_json::to_vec(&json!({
+            "jsonrpc": "2.0",
+            "id": 1,
+            "method": method,
+            "params": params,
+        }))
+        .unwrap();
+        self.send("application/json", &body)
+    }
+
+    /// Same as [`call`](Self::call), uploading the given file in a multipart form
+    fn call_with_file(
+        &self,
+        method: &str,
+        params: Value,
+        file: &[u8],
+    ) -> Result<Value, RgbProxyError> {
+        let form = MultipartForm::new()
+            .text("jsonrpc", "2.0")
+            .text("id", "1")
+            .text("method", method)
+            .text("params", &params.to_string())
+            .file("file", "file", file);
+        self.send(&form.content_type(), &form.into_body())
+    }
+
+    fn send(&self, content_type: &str, body: &[u8]) -> Result<Value, RgbProxyError> {
+        let url = format!("{}{RGB_PROXY_JSON_RPC_PATH}", self.url);
+        let mut attempts = 0;
+        loop {
+            let timeout = Some(self.timeout);
+            let err = match http_call_timeout("POST", &url, Some(content_type), body, timeout) {
+                Ok(response) if response.status == 200 => {
+                    let response: Value = serde_json::from_slice(&response.body)
+                        .map_err(|e| RgbProxyError::Transport(e.to_string()))?;
+                    if let Some(error) = response.get("error") {
+                        return Err(RgbProxyError::Rpc {
+                            code: error["code"].as_i64().unwrap_or_default(),
+                            message: error["message"].as_str().unwrap_or_default().to_string(),
+                        });
+                    }
+                    return Ok(response["result"].clone());
+                }
+                Ok(response) => RgbProxyError::Http(response.status),
+                Err(e) => RgbProxyError::Transport(e.to_string()),
+            };
+            if !err.is_transient() || attempts == self.retries {
+                return Err(err);
+            }
+            attempts += 1;
+            std::thread::sleep(RGB_PROXY_RETRY_DELAY);
+        }
+    }
+
+    pub fn server_info(&self) -> Result<Value, RgbProxyError> {
+        self.call("server.info", json!({}))
+    }
+
+    /// Post a consignment, returning false if the same one had already been posted
+    pub fn post_consignment(
+        &self,
+        recipient_id: &str,
+        consignment: &Transfer,
+        txid: Txid,
+        vout: Option<u32>,
+    ) -> Result<bool, RgbProxyError> {
+        let file = consignment
+            .to_strict_serialized::<{ usize::MAX }>()
+            .unwrap()
+            .release();
+        let params = json!({
+            "recipient_id": recipient_id,
+            "txid": txid.to_string(),
+            "vout": vout,
+        });
+        self.call_with_file("consignment.post", params, &file)
+            .map(|res| res.as_bool().unwrap())
+    }
+
+    pub fn get_consignment(&self, recipient_id: &str) -> Result<ProxyConsignment, RgbProxyError> {
+        let res = self.call("consignment.get", json!({"recipient_id": recipient_id}))?;
+        let bytes = BASE64.decode(res["consignment"].as_str().unwrap()).unwrap();
+        Ok(ProxyConsignment {
+            consignment: Transfer::from_strict_serialized::<{ usize::MAX }>(
+                Confined::from_checked(bytes),
+            )
+            .unwrap(),
+            txid: Txid::from_str(res["txid"].as_str().unwrap()).unwrap(),
+            vout: res["vout"].as_u64().map(|v| v as u32),
+        })
+    }
+
+    pub fn post_ack(&self, recipient_id: &str, ack: bool) -> Result<bool, RgbProxyError> {
+        self.call(
+            "ack.post",
+            json!({"recipient_id": recipient_id, "ack": ack}),
+        )
+        .map(|res| res.as_bool().unwrap())
+    }
+
+    /// ACK (true) or NACK (false) of the recipient, if it has already answered
+    pub fn get_ack(&self, recipient_id: &str) -> Result<Option<bool>, RgbProxyError> {
+        self.call("ack.get", json!({"recipient_id": recipient_id}))
+            .map(|res| res.as_bool())
+    }
+
+    pub fn post_media(&self, attachment_id: &str, file: &[u8]) -> Result<bool, RgbProxyError> {
+        let params = json!({"attachment_id": attachment_id});
+        self.call_with_file("media.post", params, file)
+            .map(|res| res.as_bool().unwrap())
+    }
+
+    pub fn get_media(&self, attachment_id: &str) -> Result<Vec<u8>, RgbProxyError> {
+        let res = self.call("media.get", json!({"attachment_id": attachment_id}))?;
+        Ok(BASE64.decode(res.as_str().unwrap()).unwrap())
+    }
+}
+
+/// ID under which the consignment paying the given invoice is relayed
+pub fn recipient_id(invoice: &RgbInvoice) -> String {
+    match invoice.beneficiary.into_inner() {
+        Beneficiary::BlindedSeal(secret_seal) => secret_seal.to_string(),
+        Beneficiary::WitnessVout(pay2vout, _) => pay2vout.to_string(),
+    }
+}
+
+/// Transfer posted to an RGB proxy, whose witness waits for the recipient ACK to be broadcast
+pub struct PendingTransfer {
+    pub recipient_id: String,
+    pub consignment: Transfer,
+    pub tx: Tx,
+    pub vout: Option<u32>,
+}
+
+impl PendingTransfer {
+    pub fn txid(&self) -> Txid {
+        txid_bp_to_bitcoin(self.tx.txid())
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TransferOutcome {
+    /// The recipient ACKed the consignment and the witness has been broadcast
+    Accepted,
+    /// The recipient NACKed the consignment and the transfer has been abandoned
+    Rejected,
+    /// The recipient didn't answer in time and the transfer has been abandoned
+    TimedOut,
+}
+
+impl<W: WalletProvider, D> TestWallet<W, D>
+where
+    Self: TestWalletExt,
+    <Self as TestWalletExt>::Psbt: Serialize,
+{
+    /// Pay the invoice without broadcasting the witness and post the consignment to the proxy
+    pub fn post_transfer(
+        &mut self,
+        proxy: &RgbProxyClient,
+        invoice: RgbInvoice,
+        sats: Option<u64>,
+        fee: Option<u64>,
+    ) -> PendingTransfer {
+        let recipient_id = recipient_id(&invoice);
+        let beneficiary = invoice.beneficiary.into_inner();
+        let (consignment, tx, _, _) = self.pay_full(invoice, sats, fee, false, None);
+        let vout = match beneficiary {
+            Beneficiary::BlindedSeal(_) => None,
+            Beneficiary::WitnessVout(pay2vout, _) => {
+                let script_pubkey = script_buf_to_script_pubkey(pay2vout.to_script());
+                tx.outputs()
+                    .position(|o| o.script_pubkey == script_pubkey)
+                    .map(|vout| vout as u32)
+            }
+        };
+        let txid = txid_bp_to_bitcoin(tx.txid());
+        assert!(
+            proxy
+                .post_consignment(&recipient_id, &consignment, txid, vout)
+                .unwrap()
+        );
+        PendingTransfer {
+            recipient_id,
+            consignment,
+            tx,
+            vout,
+        }
+    }
+
+    /// Fetch the consignment relayed for the given invoice and answer with an ACK if it's valid
+    /// and moves the invoiced contract, with a NACK otherwise. The witness is resolved from the
+    /// consignment, since the sender broadcasts it only after receiving the ACK.
+    pub fn review_transfer(
+        &mut self,
+        proxy: &RgbProxyClient,
+        invoice: &RgbInvoice,
+    ) -> (Transfer, bool) {
+        let recipient_id = recipient_id(invoice);
+        let ProxyConsignment {
+            consignment, txid, ..
+        } = proxy.get_consignment(&recipient_id).unwrap();
+        let resolver = OffchainResolver {
+            witness_id: txid,
+            consignment: &consignment,
+            fallback: &self.get_resolver(),
+        };
+        let validation_config = ValidationConfig {
+            chain_net: self.chain_net(),
+            trusted_typesystem: AssetSchema::from(consignment.schema_id()).types(),
+            ..Default::default()
+        };
+        let ack = Some(consignment.contract_id()) == invoice.contract
+            && consignment
+                .clone()
+                .validate(&resolver, &validation_config)
+                .is_ok_and(|valid| valid.validation_status().validity() == Validity::Valid);
+        assert!(proxy.post_ack(&recipient_id, ack).unwrap());
+        (consignment, ack)
+    }
+
+    /// Wait for the recipient answer, broadcasting the witness if it ACKs the consignment and
+    /// abandoning the transfer if it NACKs it or doesn't answer within the given time
+    pub fn complete_transfer(
+        &mut self,
+        proxy: &RgbProxyClient,
+        pending: &PendingTransfer,
+        timeout: Duration,
+    ) -> TransferOutcome {
+        let deadline = Instant::now() + timeout;
+        let outcome = loop {
+            match proxy.get_ack(&pending.recipient_id) {
+                Ok(Some(true)) => break TransferOutcome::Accepted,
+                Ok(Some(false)) => break TransferOutcome::Rejected,
+                Ok(None) => {}
+                // an unreachable proxy is the same as a missing answer until the deadline
+                Err(e) if e.is_transient() => {}
+                Err(e) => panic!("cannot get ACK: {e:?}"),
+            }
+            if Instant::now() >= deadline {
+                break TransferOutcome::TimedOut;
+            }
+            std::thread::sleep(ACK_POLL_INTERVAL);
+        };
+        if outcome == TransferOutcome::Accepted {
+            self.broadcast_tx(&pending.tx);
+        } else {
+            // the witness is unknown to the indexer, so its allocations get released
+            self.sync_and_update_witnesses(None);
+        }
+        outcome
+    }
+}
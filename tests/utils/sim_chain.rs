@@ -0,0 +1,824 @@
+use super::*;
+
+pub const SIM_COINBASE_SATS: u64 = 5_000_000_000;
+pub const SIM_FUNDING_FEE: u64 = 1_000;
+// same amount of blocks generated by start_services.sh
+pub const SIM_INITIAL_BLOCKS: u32 = 103;
+
+/// Index of a chain branch detached from the nodes, see [`SimChain::fork`]
+pub type BranchId = usize;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SimChainError {
+    UnknownInstance(u8),
+    AlreadyKnown(Txid),
+    MissingInputs(Txid),
+    Conflict(Txid),
+    InsufficientFee(Txid),
+    InvalidScript(Txid, usize),
+    NoFunds(u64),
+    UnknownBranch(BranchId),
+    UnknownTx(Txid),
+    InvalidHeight(u32),
+}
+
+impl fmt::Display for SimChainError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::UnknownInstance(instance) => write!(f, "unknown instance {instance}"),
+            Self::AlreadyKnown(txid) => write!(f, "transaction {txid} already known"),
+            Self::MissingInputs(txid) => write!(f, "transaction {txid} has missing inputs"),
+            Self::Conflict(txid) => write!(f, "transaction {txid} spends already spent outputs"),
+            Self::InsufficientFee(txid) => write!(f, "transaction {txid} has insufficient fee"),
+            Self::InvalidScript(txid, vin) => {
+                write!(
+                    f,
+                    "transaction {txid} input {vin} fails script verification"
+                )
+            }
+            Self::NoFunds(sats) => write!(f, "miner has no UTXO with at least {sats} sats"),
+            Self::UnknownBranch(branch) => write!(f, "unknown branch {branch}"),
+            Self::UnknownTx(txid) => write!(f, "transaction {txid} is unknown to all nodes"),
+            Self::InvalidHeight(height) => write!(f, "height {height} is above the chain tip"),
+        }
+    }
+}
+
+/// Confirmation details of a transaction included in the active chain of a node
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SimTxStatus {
+    pub height: u32,
+    pub block_hash: BlockHash,
+    pub block_time: u32,
+    pub pos: usize,
+}
+
+fn miner_script() -> ScriptBuf {
+    // OP_TRUE, spendable by the miner without signatures
+    ScriptBuf::from_bytes(vec![0x51])
+}
+
+fn coinbase_tx(height: u32, nonce: u64, value: u64) -> Transaction {
+    let mut script_sig = height.to_le_bytes().to_vec();
+    // makes coinbases mined at the same height on different branches unique
+    script_sig.extend(nonce.to_le_bytes());
+    Transaction {
+        version: TxVersion::TWO,
+        lock_time: BtcLockTime::ZERO,
+        input: vec![TxIn {
+            previous_output: Outpoint::null(),
+            script_sig: ScriptBuf::from_bytes(script_sig),
+            sequence: Sequence::MAX,
+            witness: Witness::new(),
+        }],
+        output: vec![TxOut {
+            value: BtcAmount::from_sat(value),
+            script_pubkey: miner_script(),
+        }],
+    }
+}
+
+/// Run the scripts of every input against the output it spends, with all the standard soft forks
+fn verify_scripts(tx: &Transaction, prevouts: &[TxOut]) -> Result<(), SimChainError> {
+    let tx_bytes = consensus_serialize(tx);
+    let utxos = prevouts
+        .iter()
+        .map(|o| bitcoinconsensus::Utxo {
+            script_pubkey: o.script_pubkey.as_bytes().as_ptr(),
+            script_pubkey_len: o.script_pubkey.len() as u32,
+            value: o.value.to_sat() as i64,
+        })
+        .collect::<Vec<_>>();
+    for (vin, prevout) in prevouts.iter().enumerate() {
+        bitcoinconsensus::verify_with_flags(
+            prevout.script_pubkey.as_bytes(),
+            prevout.value.to_sat(),
+            &tx_bytes,
+            Some(&utxos),
+            vin,
+            bitcoinconsensus::VERIFY_ALL_PRE_TAPROOT | bitcoinconsensus::VERIFY_TAPROOT,
+        )
+        .map_err(|_| SimChainError::InvalidScript(tx.compute_txid(), vin))?;
+    }
+    Ok(())
+}
+
+#[derive(Clone, Debug)]
+struct SimNode {
+    blocks: Vec<Block>,
+    mempool: Vec<Transaction>,
+}
+
+impl SimNode {
+    fn new() -> Self {
+        Self {
+            blocks: vec![genesis_block(Network::Regtest)],
+            mempool: vec![],
+        }
+    }
+
+    fn height(&self) -> u32 {
+        (self.blocks.len() - 1) as u32
+    }
+
+    fn tx_status(&self, height: u32, pos: usize) -> SimTxStatus {
+        let block = &self.blocks[height as usize];
+        SimTxStatus {
+            height,
+            block_hash: block.block_hash(),
+            block_time: block.header.time,
+            pos,
+        }
+    }
+
+    fn confirmed_txs(&self) -> impl Iterator<Item = (&Transaction, u32, usize)> {
+        self.blocks.iter().enumerate().flat_map(|(height, block)| {
+            block
+                .txdata
+                .iter()
+                .enumerate()
+                .map(move |(pos, tx)| (tx, height as u32, pos))
+        })
+    }
+
+    fn all_txs(&self) -> impl Iterator<Item = (&Transaction, Option<SimTxStatus>)> {
+        self.confirmed_txs()
+            .map(|(tx, height, pos)| (tx, Some(self.tx_status(height, pos))))
+            .chain(self.mempool.iter().map(|tx| (tx, None)))
+    }
+
+    fn get_tx(&self, txid: Txid) -> Option<(Transaction, Option<SimTxStatus>)> {
+        self.all_txs()
+            .find(|(tx, _)| tx.compute_txid() == txid)
+            .map(|(tx, status)| (tx.clone(), status))
+    }
+
+    fn prevout(&self, outpoint: Outpoint) -> Option<TxOut> {
+        self.get_tx(outpoint.txid)
+            .and_then(|(tx, _)| tx.output.get(outpoint.vout as usize).cloned())
+    }
+
+    fn spender(&self, outpoint: Outpoint) -> Option<(Txid, u32, Option<SimTxStatus>)> {
+        self.all_txs().find_map(|(tx, status)| {
+            tx.input
+                .iter()
+                .position(|i| i.previous_output == outpoint)
+                .map(|vin| (tx.compute_txid(), vin as u32, status))
+        })
+    }
+
+    fn block_by_hash(&self, block_hash: BlockHash) -> Option<(u32, &Block)> {
+        self.blocks
+            .iter()
+            .enumerate()
+            .find(|(_, b)| b.block_hash() == block_hash)
+            .map(|(height, b)| (height as u32, b))
+    }
+
+    fn history(&self, script: &ScriptBuf) -> Vec<(Transaction, Option<SimTxStatus>)> {
+        self.all_txs()
+            .filter(|(tx, _)| {
+                tx.output.iter().any(|o| &o.script_pubkey == script)
+                    || tx.input.iter().any(|i| {
+                        self.prevout(i.previous_output)
+                            .is_some_and(|o| &o.script_pubkey == script)
+                    })
+            })
+            .map(|(tx, status)| (tx.clone(), status))
+            .collect()
+    }
+
+    fn merkle_proof(&self, txid: Txid) -> Option<(u32, usize, Vec<TxMerkleNode>)> {
+        let (_, status) = self.get_tx(txid)?;
+        let status = status?;
+        let block = &self.blocks[status.height as usize];
+        let mut level = block
+            .txdata
+            .iter()
+            .map(|tx| tx.compute_txid().to_byte_array())
+            .collect::<Vec<_>>();
+        let mut pos = status.pos;
+        let mut branch = vec![];
+        while level.len() > 1 {
+            if level.len() % 2 == 1 {
+                level.push(*level.last().unwrap());
+            }
+            branch.push(TxMerkleNode::from_byte_array(level[pos ^ 1]));
+            level = level
+                .chunks(2)
+                .map(|pair| sha256d::Hash::hash(&[pair[0], pair[1]].concat()).to_byte_array())
+                .collect();
+            pos /= 2;
+        }
+        Some((status.height, status.pos, branch))
+    }
+
+    fn fee(&self, tx: &Transaction) -> Option<u64> {
+        let mut input_sats = 0;
+        for input in &tx.input {
+            input_sats += self.prevout(input.previous_output)?.value.to_sat();
+        }
+        let output_sats: u64 = tx.output.iter().map(|o| o.value.to_sat()).sum();
+        input_sats.checked_sub(output_sats)
+    }
+
+    fn mempool_descendants(&self, mut txids: BTreeSet<Txid>) -> BTreeSet<Txid> {
+        loop {
+            let children = self
+                .mempool
+                .iter()
+                .filter(|tx| {
+                    tx.input
+                        .iter()
+                        .any(|i| txids.contains(&i.previous_output.txid))
+                })
+                .map(|tx| tx.compute_txid())
+                .filter(|txid| !txids.contains(txid))
+                .collect::<Vec<_>>();
+            if children.is_empty() {
+                return txids;
+            }
+            txids.extend(children);
+        }
+    }
+
+    fn accept(&mut self, tx: Transaction) -> Result<Txid, SimChainError> {
+        let txid = tx.compute_txid();
+        if self.get_tx(txid).is_some() {
+            return Err(SimChainError::AlreadyKnown(txid));
+        }
+        let mut conflicts = BTreeSet::new();
+        let mut prevouts = vec![];
+        for input in &tx.input {
+            let Some(prevout) = self.prevout(input.previous_output) else {
+                return Err(SimChainError::MissingInputs(txid));
+            };
+            prevouts.push(prevout);
+            match self.spender(input.previous_output) {
+                Some((_, _, Some(_))) => return Err(SimChainError::Conflict(txid)),
+                Some((spender, _, None)) => {
+                    conflicts.insert(spender);
+                }
+                None => {}
+            }
+        }
+        verify_scripts(&tx, &prevouts)?;
+        let fee = self.fee(&tx).ok_or(SimChainError::InsufficientFee(txid))?;
+        if !conflicts.is_empty() {
+            // replace-by-fee: the new transaction must pay more than everything it evicts
+            let replaced = self.mempool_descendants(conflicts);
+            let replaced_fee: u64 = self
+                .mempool
+                .iter()
+                .filter(|t| replaced.contains(&t.compute_txid()))
+                .map(|t| self.fee(t).unwrap_or_default())
+                .sum();
+            if fee <= replaced_fee {
+                return Err(SimChainError::InsufficientFee(txid));
+            }
+            self.mempool
+                .retain(|t| !replaced.contains(&t.compute_txid()));
+        }
+        self.mempool.push(tx);
+        Ok(txid)
+    }
+
+    fn mine(&mut self, blocks: u32, nonce: &mut u64) {
+        for _ in 0..blocks {
+            let fees: u64 = self
+                .mempool
+                .iter()
+                .map(|tx| self.fee(tx).unwrap_or_default())
+                .sum();
+            let height = self.height() + 1;
+            *nonce += 1;
+            let mut txdata = vec![coinbase_tx(height, *nonce, SIM_COINBASE_SATS + fees)];
+            txdata.extend(std::mem::take(&mut self.mempool));
+            let prev = self.blocks.last().unwrap();
+            let time = max(prev.header.time + 1, test_now() as u32);
+            let mut block = Block {
+                header: BlockHeader {
+                    version: BlockVersion::TWO,
+                    prev_blockhash: prev.block_hash(),
+                    merkle_root: TxMerkleNode::all_zeros(),
+                    time,
+                    bits: prev.header.bits,
+                    nonce: 0,
+                },
+                txdata,
+            };
+            block.header.merkle_root = block.compute_merkle_root().unwrap();
+            self.blocks.push(block);
+        }
+    }
+
+    /// Switch to the given chain if it is longer than the current one
+    fn adopt(&mut self, blocks: &[Block]) -> bool {
+        if blocks.len() <= self.blocks.len() {
+            return false;
+        }
+        self.reorg(blocks);
+        true
+    }
+
+    /// Switch to the given chain, returning disconnected transactions to the mempool when they
+    /// are still valid
+    fn reorg(&mut self, blocks: &[Block]) {
+        let fork_height = self
+            .blocks
+            .iter()
+            .zip(blocks)
+            .position(|(a, b)| a.block_hash() != b.block_hash())
+            .unwrap_or(self.blocks.len());
+        let disconnected = self.blocks[fork_height..]
+            .iter()
+            .flat_map(|b| b.txdata.iter().skip(1).cloned())
+            .collect::<Vec<_>>();
+        let mempool = std::mem::take(&mut self.mempool);
+        self.blocks = blocks.to_vec();
+        for tx in disconnected.into_iter().chain(mempool) {
+            let _ = self.accept(tx);
+        }
+    }
+
+    /// Drop the given transactions and their descendants from the mempool, returning them
+    fn evict(&mut self, txids: BTreeSet<Txid>) -> Vec<Transaction> {
+        let evicted = self.mempool_descendants(txids);
+        let (evicted, kept) = std::mem::take(&mut self.mempool)
+            .into_iter()
+            .partition(|tx| evicted.contains(&tx.compute_txid()));
+        self.mempool = kept;
+        evicted
+    }
+
+    fn unspent(&self, script: &ScriptBuf) -> Vec<(Outpoint, TxOut)> {
+        self.all_txs()
+            .flat_map(|(tx, _)| {
+                let txid = tx.compute_txid();
+                tx.output
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, o)| &o.script_pubkey == script)
+                    .map(move |(vout, o)| (Outpoint::new(txid, vout as u32), o.clone()))
+            })
+            .filter(|(outpoint, _)| self.spender(*outpoint).is_none())
+            .collect()
+    }
+}
+
+#[derive(Debug)]
+struct SimNetwork {
+    nodes: BTreeMap<u8, SimNode>,
+    links: BTreeSet<(u8, u8)>,
+    branches: Vec<SimNode>,
+    /// Transactions evicted from the mempools, which branches can still confirm
+    evicted: BTreeMap<Txid, Transaction>,
+    coinbase_nonce: u64,
+}
+
+impl SimNetwork {
+    fn node(&self, instance: u8) -> Result<&SimNode, SimChainError> {
+        self.nodes
+            .get(&instance)
+            .ok_or(SimChainError::UnknownInstance(instance))
+    }
+
+    fn node_mut(&mut self, instance: u8) -> Result<&mut SimNode, SimChainError> {
+        self.nodes
+            .get_mut(&instance)
+            .ok_or(SimChainError::UnknownInstance(instance))
+    }
+
+    fn branch(&self, branch: BranchId) -> Result<&SimNode, SimChainError> {
+        self.branches
+            .get(branch)
+            .ok_or(SimChainError::UnknownBranch(branch))
+    }
+
+    fn branch_mut(&mut self, branch: BranchId) -> Result<&mut SimNode, SimChainError> {
+        self.branches
+            .get_mut(branch)
+            .ok_or(SimChainError::UnknownBranch(branch))
+    }
+
+    /// Look for a transaction in all the nodes and branches
+    fn find_tx(&self, txid: Txid) -> Option<Transaction> {
+        self.nodes
+            .values()
+            .chain(&self.branches)
+            .find_map(|n| n.get_tx(txid))
+            .map(|(tx, _)| tx)
+            .or_else(|| self.evicted.get(&txid).cloned())
+    }
+
+    fn peers(&self, instance: u8) -> BTreeSet<u8> {
+        let mut peers = bset![instance];
+        loop {
+            let linked = self
+                .links
+                .iter()
+                .filter(|(a, b)| peers.contains(a) || peers.contains(b))
+                .flat_map(|(a, b)| [*a, *b])
+                .filter(|i| !peers.contains(i))
+                .collect::<Vec<_>>();
+            if linked.is_empty() {
+                return peers;
+            }
+            peers.extend(linked);
+        }
+    }
+
+    /// Relay blocks and mempool transactions of the given instance to all connected nodes
+    fn propagate(&mut self, instance: u8) {
+        let peers = self.peers(instance);
+        let best = peers
+            .iter()
+            .map(|i| &self.nodes[i])
+            .fold(&self.nodes[&instance], |best, node| {
+                if node.blocks.len() > best.blocks.len() {
+                    node
+                } else {
+                    best
+                }
+            })
+            .blocks
+            .clone();
+        // transactions disconnected by a reorg are relayed too
+        for peer in &peers {
+            self.nodes.get_mut(peer).unwrap().adopt(&best);
+        }
+        let mempool = peers
+            .iter()
+            .flat_map(|i| self.nodes[i].mempool.clone())
+            .collect::<Vec<_>>();
+        for peer in peers {
+            let node = self.nodes.get_mut(&peer).unwrap();
+            for tx in &mempool {
+                let _ = node.accept(tx.clone());
+            }
+        }
+    }
+}
+
+/// In-process regtest chain, with one simulated node for each test instance
+#[derive(Debug)]
+pub struct SimChain {
+    network: Mutex<SimNetwork>,
+}
+
+impl Default for SimChain {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SimChain {
+    /// Create the same topology set up by start_services.sh
+    pub fn new() -> Self {
+        let chain = Self::with_instances(&[INSTANCE_1, INSTANCE_2, INSTANCE_3]);
+        chain.mine_blocks(INSTANCE_1, SIM_INITIAL_BLOCKS).unwrap();
+        chain.mine_blocks(INSTANCE_2, SIM_INITIAL_BLOCKS).unwrap();
+        chain.connect(INSTANCE_2, INSTANCE_3).unwrap();
+        chain
+    }
+
+    pub fn with_instances(instances: &[u8]) -> Self {
+        Self {
+            network: Mutex::new(SimNetwork {
+                nodes: instances.iter().map(|i| (*i, SimNode::new())).collect(),
+                links: bset![],
+                branches: vec![],
+                evicted: bmap![],
+                coinbase_nonce: 0,
+            }),
+        }
+    }
+
+    pub fn height_of(&self, instance: u8) -> Result<u32, SimChainError> {
+        Ok(self.network.lock().unwrap().node(instance)?.height())
+    }
+
+    pub fn block_hash(&self, instance: u8, height: u32) -> Option<BlockHash> {
+        self.block(instance, height).map(|b| b.block_hash())
+    }
+
+    pub fn block(&self, instance: u8, height: u32) -> Option<Block> {
+        let network = self.network.lock().unwrap();
+        network
+            .node(instance)
+            .ok()?
+            .blocks
+            .get(height as usize)
+            .cloned()
+    }
+
+    pub fn mine_blocks(&self, instance: u8, blocks: u32) -> Result<(), SimChainError> {
+        let mut network = self.network.lock().unwrap();
+        let mut nonce = network.coinbase_nonce;
+        network.node_mut(instance)?.mine(blocks, &mut nonce);
+        network.coinbase_nonce = nonce;
+        network.propagate(instance);
+        Ok(())
+    }
+
+    pub fn broadcast(&self, instance: u8, tx: Transaction) -> Result<Txid, SimChainError> {
+        let mut network = self.network.lock().unwrap();
+        let txid = network.node_mut(instance)?.accept(tx)?;
+        network.propagate(instance);
+        Ok(txid)
+    }
+
+    pub fn get_tx(&self, instance: u8, txid: Txid) -> Option<(Transaction, Option<SimTxStatus>)> {
+        let network = self.network.lock().unwrap();
+        network.node(instance).ok()?.get_tx(txid)
+    }
+
+    pub fn mempool(&self, instance: u8) -> Vec<Txid> {
+        let network = self.network.lock().unwrap();
+        network
+            .node(instance)
+            .map(|n| n.mempool.iter().map(|tx| tx.compute_txid()).collect())
+            .unwrap_or_default()
+    }
+
+    pub fn unspent(&self, instance: u8, script: &ScriptBuf) -> Vec<(Outpoint, TxOut)> {
+        let network = self.network.lock().unwrap();
+        network
+            .node(instance)
+            .map(|n| n.unspent(script))
+            .unwrap_or_default()
+    }
+
+    pub fn spender(
+        &self,
+        instance: u8,
+        outpoint: Outpoint,
+    ) -> Option<(Txid, u32, Option<SimTxStatus>)> {
+        let network = self.network.lock().unwrap();
+        network.node(instance).ok()?.spender(outpoint)
+    }
+
+    pub fn tip(&self, instance: u8) -> Option<(u32, Block)> {
+        let network = self.network.lock().unwrap();
+        let node = network.node(instance).ok()?;
+        Some((node.height(), node.blocks.last().unwrap().clone()))
+    }
+
+    pub fn block_by_hash(&self, instance: u8, block_hash: BlockHash) -> Option<(u32, Block)> {
+        let network = self.network.lock().unwrap();
+        network
+            .node(instance)
+            .ok()?
+            .block_by_hash(block_hash)
+            .map(|(height, b)| (height, b.clone()))
+    }
+
+    pub fn prevout(&self, instance: u8, outpoint: Outpoint) -> Option<TxOut> {
+        let network = self.network.lock().unwrap();
+        network.node(instance).ok()?.prevout(outpoint)
+    }
+
+    pub fn fee(&self, instance: u8, tx: &Transaction) -> Option<u64> {
+        let network = self.network.lock().unwrap();
+        network.node(instance).ok()?.fee(tx)
+    }
+
+    /// Find a script appearing in the outputs of known transactions from its sha256 hash
+    pub fn script_by_hash(&self, instance: u8, script_hash: sha256::Hash) -> Option<ScriptBuf> {
+        let network = self.network.lock().unwrap();
+        network.node(instance).ok()?.all_txs().find_map(|(tx, _)| {
+            tx.output
+                .iter()
+                .map(|o| &o.script_pubkey)
+                .find(|s| sha256::Hash::hash(s.as_bytes()) == script_hash)
+                .cloned()
+        })
+    }
+
+    /// Transactions funding or spending the given script, confirmed ones first
+    pub fn history(
+        &self,
+        instance: u8,
+        script: &ScriptBuf,
+    ) -> Vec<(Transaction, Option<SimTxStatus>)> {
+        let network = self.network.lock().unwrap();
+        network
+            .node(instance)
+            .map(|n| n.history(script))
+            .unwrap_or_default()
+    }
+
+    /// Height, position in block and merkle branch of a confirmed transaction
+    pub fn merkle_proof(
+        &self,
+        instance: u8,
+        txid: Txid,
+    ) -> Option<(u32, usize, Vec<TxMerkleNode>)> {
+        let network = self.network.lock().unwrap();
+        network.node(instance).ok()?.merkle_proof(txid)
+    }
+
+    /// Send sats to the given script from the coinbase outputs of the node
+    pub fn send_to_script(
+        &self,
+        instance: u8,
+        script: ScriptBuf,
+        sats: u64,
+    ) -> Result<Txid, SimChainError> {
+        let mut network = self.network.lock().unwrap();
+        let node = network.node_mut(instance)?;
+        let (outpoint, txout) = node
+            .unspent(&miner_script())
+            .into_iter()
+            .max_by_key(|(_, o)| o.value)
+            .filter(|(_, o)| o.value.to_sat() >= sats + SIM_FUNDING_FEE)
+            .ok_or(SimChainError::NoFunds(sats + SIM_FUNDING_FEE))?;
+        let tx = Transaction {
+            version: TxVersion::TWO,
+            lock_time: BtcLockTime::ZERO,
+            input: vec![TxIn {
+                previous_output: outpoint,
+                script_sig: ScriptBuf::new(),
+                sequence: Sequence::ENABLE_RBF_NO_LOCKTIME,
+                witness: Witness::new(),
+            }],
+            output: vec![
+                TxOut {
+                    value: BtcAmount::from_sat(sats),
+                    script_pubkey: script,
+                },
+                TxOut {
+                    value: BtcAmount::from_sat(txout.value.to_sat() - sats - SIM_FUNDING_FEE),
+                    script_pubkey: miner_script(),
+                },
+            ],
+        };
+        let txid = node.accept(tx)?;
+        network.propagate(instance);
+        Ok(txid)
+    }
+
+    pub fn connect(&self, instance_a: u8, instance_b: u8) -> Result<(), SimChainError> {
+        let mut network = self.network.lock().unwrap();
+        network.node(instance_a)?;
+        network.node(instance_b)?;
+        network
+            .links
+            .insert((instance_a.min(instance_b), instance_a.max(instance_b)));
+        network.propagate(instance_a);
+        Ok(())
+    }
+
+    pub fn disconnect(&self, instance_a: u8, instance_b: u8) {
+        let mut network = self.network.lock().unwrap();
+        network
+            .links
+            .remove(&(instance_a.min(instance_b), instance_a.max(instance_b)));
+    }
+
+    /// Detach a copy of the chain of the given instance, up to the given height, as a new branch
+    pub fn fork(&self, instance: u8, height: u32) -> Result<BranchId, SimChainError> {
+        let mut network = self.network.lock().unwrap();
+        let node = network.node(instance)?;
+        if height > node.height() {
+            return Err(SimChainError::InvalidHeight(height));
+        }
+        let branch = SimNode {
+            blocks: node.blocks[..=height as usize].to_vec(),
+            mempool: vec![],
+        };
+        network.branches.push(branch);
+        Ok(network.branches.len() - 1)
+    }
+
+    /// Copy an existing branch into a new one
+    pub fn fork_branch(&self, branch: BranchId) -> Result<BranchId, SimChainError> {
+        let mut network = self.network.lock().unwrap();
+        let branch = network.branch(branch)?.clone();
+        network.branches.push(branch);
+        Ok(network.branches.len() - 1)
+    }
+
+    pub fn branch_height(&self, branch: BranchId) -> Result<u32, SimChainError> {
+        Ok(self.network.lock().unwrap().branch(branch)?.height())
+    }
+
+    /// Mine blocks on a branch, confirming the given transactions (known to any node or branch,
+    /// or evicted from a mempool) in the first one
+    pub fn mine_branch(
+        &self,
+        branch: BranchId,
+        blocks: u32,
+        txids: &[Txid],
+    ) -> Result<(), SimChainError> {
+        let mut network = self.network.lock().unwrap();
+        let txs = txids
+            .iter()
+            .map(|txid| {
+                network
+                    .find_tx(*txid)
+                    .ok_or(SimChainError::UnknownTx(*txid))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let mut nonce = network.coinbase_nonce;
+        let node = network.branch_mut(branch)?;
+        for tx in txs {
+            node.accept(tx)?;
+        }
+        node.mine(blocks, &mut nonce);
+        network.coinbase_nonce = nonce;
+        Ok(())
+    }
+
+    /// Make the given instance and its connected nodes follow a branch, as if it had more work
+    /// than their current chain, even when shorter
+    pub fn reorg_to(&self, instance: u8, branch: BranchId) -> Result<(), SimChainError> {
+        let mut network = self.network.lock().unwrap();
+        network.node(instance)?;
+        let blocks = network.branch(branch)?.blocks.clone();
+        for peer in network.peers(instance) {
+            network.nodes.get_mut(&peer).unwrap().reorg(&blocks);
+        }
+        network.propagate(instance);
+        Ok(())
+    }
+
+    /// Drop the given transactions and their descendants from the mempool of the given instance
+    /// and its connected nodes
+    pub fn evict(&self, instance: u8, txids: &[Txid]) -> Result<(), SimChainError> {
+        let mut network = self.network.lock().unwrap();
+        network.node(instance)?;
+        for peer in network.peers(instance) {
+            let node = network.nodes.get_mut(&peer).unwrap();
+            let evicted = node.evict(txids.iter().copied().collect());
+            network
+                .evicted
+                .extend(evicted.into_iter().map(|tx| (tx.compute_txid(), tx)));
+        }
+        Ok(())
+    }
+}
+
+/// Simulated chain plus the local indexers serving its data
+pub struct SimBackend {
+    pub chain: Arc<SimChain>,
+    indexers: BTreeMap<u8, IndexerServer>,
+}
+
+impl Default for SimBackend {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SimBackend {
+    pub fn new() -> Self {
+        let chain = Arc::new(SimChain::new());
+        let indexers = [INSTANCE_1, INSTANCE_2, INSTANCE_3]
+            .into_iter()
+            .map(|i| (i, IndexerServer::start(chain.clone(), i)))
+            .collect();
+        Self { chain, indexers }
+    }
+}
+
+impl ChainBackend for SimBackend {
+    fn start(&self) {
+        println!("using simulated chain");
+    }
+
+    fn mine(&self, instance: u8, blocks: u32) {
+        self.chain.mine_blocks(instance, blocks).unwrap();
+    }
+
+    fn height(&self, instance: u8) -> u32 {
+        self.chain.height_of(instance).unwrap()
+    }
+
+    fn send_to_address(&self, instance: u8, address: &str, sats: u64) -> String {
+        let script = Address::from_str(address)
+            .unwrap()
+            .assume_checked()
+            .script_pubkey();
+        self.chain
+            .send_to_script(instance, script, sats)
+            .unwrap()
+            .to_string()
+    }
+
+    fn connect_nodes(&self, instance_a: u8, instance_b: u8) {
+        self.chain.connect(instance_a, instance_b).unwrap();
+    }
+
+    fn disconnect_nodes(&self, instance_a: u8, instance_b: u8) {
+        self.chain.disconnect(instance_a, instance_b);
+    }
+
+    fn indexer_url(&self, instance: u8, indexer: &Indexer) -> String {
+        let server = &self.indexers[&instance];
+        match indexer {
+            Indexer::Electrum => server.electrum_url(),
+            Indexer::Esplora => server.esplora_url(),
+        }
+    }
+}
@@ -0,0 +1,333 @@
+use super::*;
+
+/// Minimum amount of sats the taker keeps on its output
+pub const MIN_SWAP_OUTPUT_SATS: u64 = 1000;
+
+/// What the maker of a swap asks in exchange for the offered asset
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum SwapAsk {
+    /// Amount of another RGB asset
+    Asset(ContractId, u64),
+    /// Amount of sats
+    Sats(u64),
+}
+
+impl SwapAsk {
+    fn sats(&self) -> u64 {
+        match self {
+            SwapAsk::Asset(..) => 0,
+            SwapAsk::Sats(sats) => *sats,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum SwapParty {
+    Maker,
+    Taker,
+}
+
+impl SwapParty {
+    pub fn counterparty(self) -> Self {
+        match self {
+            SwapParty::Maker => SwapParty::Taker,
+            SwapParty::Taker => SwapParty::Maker,
+        }
+    }
+}
+
+/// Inputs and output a party contributes to the swap PSBT
+struct SwapSide {
+    inputs: Vec<Outpoint>,
+    output: WitnessInfo,
+    beneficiaries: Option<AssetBeneficiariesMap>,
+    signed: bool,
+}
+
+/// Atomic swap co-constructed by a maker and a taker in a single PSBT.
+///
+/// Each party has a single output, receiving both the asset bought and the change of the asset
+/// sold. The PSBT is completed by the taker, then each party colors its own side and the last
+/// one commits, so that consignments can be exchanged and verified before anybody signs.
+/// Commitments always use opret, since the two sides can't share a tapret host.
+pub struct Swap {
+    pub psbt: BpPsbt,
+    pub offer: (ContractId, u64),
+    pub ask: SwapAsk,
+    maker: SwapSide,
+    taker: Option<SwapSide>,
+    fascia: Option<Fascia>,
+}
+
+impl Swap {
+    /// Offer the given amount of an asset, spending all the maker outpoints holding it
+    pub fn offer(
+        maker: &mut BpTestWallet,
+        contract_id: ContractId,
+        amount: u64,
+        ask: SwapAsk,
+    ) -> Self {
+        maker.sync();
+        let unspents = maker.list_unspents();
+        let inputs = maker
+            .contract_outpoints(contract_id)
+            .into_iter()
+            .collect::<Vec<_>>();
+        let input_sats = inputs.iter().map(|o| unspents[o]).sum::<u64>();
+
+        let mut psbt = BpPsbt::default();
+        for outpoint in &inputs {
+            maker.psbt_add_input(&mut psbt, outpoint_bitcoin_to_bp(*outpoint));
+        }
+        // the sats asked are added to the maker's own ones
+        let output =
+            maker.get_witness_info(Some(input_sats + ask.sats()), Some(CloseMethod::OpretFirst));
+        psbt.construct_output_expect(
+            output.script_pubkey(),
+            Sats::from_sats(output.amount_sats.unwrap()),
+        );
+
+        Self {
+            psbt,
+            offer: (contract_id, amount),
+            ask,
+            maker: SwapSide {
+                inputs,
+                output,
+                beneficiaries: None,
+                signed: false,
+            },
+            taker: None,
+            fascia: None,
+        }
+    }
+
+    /// Complete the PSBT with the taker inputs and output, the taker pays the whole fee
+    pub fn take(&mut self, taker: &mut BpTestWallet, fee: u64) {
+        assert!(self.taker.is_none(), "swap already taken");
+        taker.sync();
+        let unspents = taker.list_unspents();
+        let mut inputs = match self.ask {
+            SwapAsk::Asset(contract_id, _) => taker
+                .contract_outpoints(contract_id)
+                .into_iter()
+                .collect::<Vec<_>>(),
+            SwapAsk::Sats(_) => vec![],
+        };
+        let needed_sats = self.ask.sats() + fee + MIN_SWAP_OUTPUT_SATS;
+        let asset_sats = inputs.iter().map(|o| unspents[o]).sum::<u64>();
+        if asset_sats < needed_sats {
+            inputs.extend(taker.select_coins(needed_sats - asset_sats, &ColoredCoins::Exclude));
+        }
+        let input_sats = inputs.iter().map(|o| unspents[o]).sum::<u64>();
+        assert!(input_sats >= needed_sats, "taker doesn't have enough sats");
+
+        for outpoint in &inputs {
+            taker.psbt_add_input(&mut self.psbt, outpoint_bitcoin_to_bp(*outpoint));
+        }
+        let output = taker.get_witness_info(
+            Some(input_sats - self.ask.sats() - fee),
+            Some(CloseMethod::OpretFirst),
+        );
+        self.psbt.construct_output_expect(
+            output.script_pubkey(),
+            Sats::from_sats(output.amount_sats.unwrap()),
+        );
+        self.taker = Some(SwapSide {
+            inputs,
+            output,
+            beneficiaries: None,
+            signed: false,
+        });
+    }
+
+    fn side(&self, party: SwapParty) -> &SwapSide {
+        match party {
+            SwapParty::Maker => &self.maker,
+            SwapParty::Taker => self.taker.as_ref().expect("swap not taken"),
+        }
+    }
+
+    fn side_mut(&mut self, party: SwapParty) -> &mut SwapSide {
+        match party {
+            SwapParty::Maker => &mut self.maker,
+            SwapParty::Taker => self.taker.as_mut().expect("swap not taken"),
+        }
+    }
+
+    /// Asset the given party gives away
+    fn given(&self, party: SwapParty) -> Option<(ContractId, u64)> {
+        match (party, self.ask) {
+            (SwapParty::Maker, _) => Some(self.offer),
+            (SwapParty::Taker, SwapAsk::Asset(contract_id, amount)) => Some((contract_id, amount)),
+            (SwapParty::Taker, SwapAsk::Sats(_)) => None,
+        }
+    }
+
+    /// Parties needing to color the PSBT, the last one commits it
+    fn coloring_parties(&self) -> Vec<SwapParty> {
+        match self.ask {
+            SwapAsk::Asset(..) => vec![SwapParty::Maker, SwapParty::Taker],
+            SwapAsk::Sats(_) => vec![SwapParty::Maker],
+        }
+    }
+
+    pub fn is_committed(&self) -> bool {
+        self.fascia.is_some()
+    }
+
+    pub fn txid(&self) -> BpTxid {
+        self.psbt.txid()
+    }
+
+    fn vout(&self, party: SwapParty) -> BpVout {
+        let script = self.side(party).output.script_pubkey();
+        self.psbt
+            .outputs()
+            .find(|o| o.script == script)
+            .unwrap()
+            .vout()
+    }
+
+    /// Add the transitions of the given party, committing the PSBT if it's the last one to color
+    pub fn color(&mut self, party: SwapParty, wallet: &mut BpTestWallet) {
+        let parties = self.coloring_parties();
+        assert!(parties.contains(&party), "{party:?} has nothing to color");
+        assert!(!self.is_committed(), "swap PSBT already committed");
+        assert!(
+            self.side(party).beneficiaries.is_none(),
+            "swap already colored by {party:?}"
+        );
+        let (contract_id, amount) = self.given(party).unwrap();
+        let counterparty_output = self.side(party.counterparty()).output.clone();
+        let side = self.side(party);
+        let coloring_info = ColoringInfo {
+            asset_info_map: HashMap::from([(
+                contract_id,
+                AssetColoringInfo {
+                    input_outpoints: side.inputs.clone(),
+                    assignments: vec![AssetAssignment {
+                        destination: AssetDestination::Witness(counterparty_output),
+                        amount,
+                    }],
+                },
+            )]),
+            static_blinding: None,
+            nonce: None,
+            close_method: CloseMethod::OpretFirst,
+        };
+        // the change of the given asset goes to the party's own output
+        let mut meta = BpPsbtMeta {
+            change_vout: Some(self.vout(party)),
+            change_terminal: Some(side.output.derived_address.terminal),
+        };
+
+        let is_last = parties
+            .iter()
+            .filter(|p| **p != party)
+            .all(|p| self.side(*p).beneficiaries.is_some());
+        let beneficiaries = if is_last {
+            let (fascia, beneficiaries, _, _) =
+                wallet.color_psbt(&mut self.psbt, &mut meta, coloring_info, None);
+            self.fascia = Some(fascia);
+            beneficiaries
+        } else {
+            let (beneficiaries, _, _) =
+                wallet.color_psbt_init(&mut self.psbt, &mut meta, coloring_info, None);
+            beneficiaries
+        };
+        self.side_mut(party).beneficiaries = Some(beneficiaries);
+    }
+
+    /// Consignment for the counterparty, proving the transfer of the asset given by the party
+    pub fn consignment(&self, party: SwapParty, wallet: &BpTestWallet) -> Option<Transfer> {
+        let fascia = self.fascia.as_ref().expect("swap not committed");
+        let (contract_id, _) = self.given(party)?;
+        let beneficiaries = self.side(party).beneficiaries.clone().unwrap();
+        let mut consignments = wallet.create_consignments(beneficiaries, self.txid(), fascia);
+        consignments.remove(&contract_id)
+    }
+
+    /// Checks the given party should do before signing: its inputs are spent, its output gets
+    /// the expected sats and the counterparty consignment assigns the expected amount of the
+    /// asset bought to it
+    pub fn verify(&self, party: SwapParty, consignment: Option<&Transfer>) {
+        assert!(self.is_committed(), "swap not committed");
+        let side = self.side(party);
+        let spent = self
+            .psbt
+            .inputs()
+            .map(|i| outpoint_bp_to_bitcoin(i.previous_outpoint))
+            .collect::<HashSet<_>>();
+        assert!(side.inputs.iter().all(|o| spent.contains(o)));
+        let vout = self.vout(party);
+        let output = self.psbt.output(vout.to_u32() as usize).unwrap();
+        assert_eq!(Some(output.amount.sats()), side.output.amount_sats);
+
+        let Some((contract_id, amount)) = self.given(party.counterparty()) else {
+            assert!(consignment.is_none());
+            return;
+        };
+        let consignment = consignment.expect("missing counterparty consignment");
+        assert_eq!(consignment.contract_id(), contract_id);
+        let txid = txid_bp_to_bitcoin(self.txid());
+        let received = consignment
+            .bundles
+            .iter()
+            .filter(|wb| wb.witness_id() == txid)
+            .filter_map(|wb| bundle_vout_amounts(&wb.bundle).get(&vout.to_u32()).copied())
+            .sum::<u64>();
+        assert_eq!(received, amount, "consignment doesn't pay the swap amount");
+    }
+
+    pub fn sign(&mut self, party: SwapParty, wallet: &BpTestWallet) {
+        assert!(self.is_committed(), "swap not committed");
+        assert!(!self.side(party).signed, "swap already signed by {party:?}");
+        wallet.sign_finalize(&mut self.psbt);
+        self.side_mut(party).signed = true;
+    }
+
+    /// Extract the fully signed transaction and broadcast it
+    pub fn complete(&self, wallet: &BpTestWallet) -> Tx {
+        assert!(
+            self.maker.signed && self.side(SwapParty::Taker).signed,
+            "swap not signed by both parties"
+        );
+        let tx = wallet.extract(&self.psbt);
+        wallet.broadcast_tx(&tx);
+        tx
+    }
+
+    /// Register the swap in the stock of the given party, along with the asset bought
+    pub fn settle(
+        &self,
+        party: SwapParty,
+        wallet: &mut BpTestWallet,
+        consignment: Option<Transfer>,
+    ) {
+        assert!(self.side(party).signed, "swap not signed by the {party:?}");
+        let fascia = self.fascia.clone().expect("swap not committed");
+        // the bundles of contracts unknown to the party are learned from the consignment
+        let known = wallet
+            .list_contracts()
+            .into_iter()
+            .map(|c| c.id)
+            .collect::<HashSet<_>>();
+        let seal_witness = fascia.seal_witness().clone();
+        let bundles = fascia
+            .into_bundles()
+            .into_iter()
+            .filter(|(cid, _)| known.contains(cid))
+            .collect::<BTreeMap<_, _>>();
+        if !bundles.is_empty() {
+            wallet.consume_fascia(
+                Fascia::new(seal_witness, NonEmptyOrdMap::from_checked(bundles)),
+                self.txid(),
+            );
+        }
+        if let Some(consignment) = consignment {
+            wallet.accept_transfer(consignment, None);
+        }
+        wallet.sync();
+    }
+}
@@ -0,0 +1,64 @@
+use super::*;
+
+/// Initial timestamp of the fake clock
+pub const TEST_CLOCK_START: i64 = 1_700_000_000;
+
+/// Seeded RNG and fake clock used by wallet creation, seal blinding and contract issuance, so
+/// that a test can be replayed by setting the printed seed in the `SEED` env variable
+pub struct TestContext {
+    rng: StdRng,
+    clock: i64,
+}
+
+thread_local! {
+    static TEST_CONTEXT: RefCell<TestContext> = RefCell::new(TestContext::from_env());
+}
+
+impl TestContext {
+    fn from_env() -> Self {
+        let seed = match std::env::var("SEED") {
+            Ok(seed) => seed.parse().expect("invalid SEED"),
+            Err(VarError::NotPresent) => rand::random(),
+            Err(e) => panic!("invalid SEED: {e}"),
+        };
+        Self::with_seed(seed)
+    }
+
+    pub fn with_seed(seed: u64) -> Self {
+        // each test runs in a thread named after it, this way tests sharing the same seed don't
+        // generate the same wallets
+        let thread = std::thread::current();
+        let test_name = thread.name().unwrap_or_default();
+        println!("test context seed: {seed} ({test_name})");
+        let rng_seed = sha256::Hash::hash(format!("{seed}:{test_name}").as_bytes());
+        Self {
+            rng: StdRng::from_seed(rng_seed.to_byte_array()),
+            clock: TEST_CLOCK_START,
+        }
+    }
+}
+
+/// Run `f` with a test context using the given seed, restoring the current one afterwards
+pub fn with_test_seed<T>(seed: u64, f: impl FnOnce() -> T) -> T {
+    // initialize the current context first, so its seed gets printed before the new one
+    let prev = TEST_CONTEXT.with(|ctx| ctx.replace(TestContext::with_seed(seed)));
+    let res = f();
+    TEST_CONTEXT.set(prev);
+    res
+}
+
+pub fn with_test_rng<T>(f: impl FnOnce(&mut StdRng) -> T) -> T {
+    TEST_CONTEXT.with_borrow_mut(|ctx| f(&mut ctx.rng))
+}
+
+pub fn test_blinding() -> u64 {
+    with_test_rng(|rng| rng.next_u64())
+}
+
+/// Current time of the fake clock, which ticks one second at each read
+pub fn test_now() -> i64 {
+    TEST_CONTEXT.with_borrow_mut(|ctx| {
+        ctx.clock += 1;
+        ctx.clock
+    })
+}
@@ -0,0 +1,219 @@
+use super::*;
+
+#[derive(Debug, EnumIter, Copy, Clone, PartialEq, Eq)]
+pub enum WalletBackend {
+    Bp,
+    Bdk,
+}
+
+impl WalletBackend {
+    pub fn supports(&self, descriptor_type: &DescriptorType) -> bool {
+        match self {
+            Self::Bp => matches!(descriptor_type, DescriptorType::Wpkh | DescriptorType::Tr),
+            Self::Bdk => true,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct WalletKind {
+    pub backend: WalletBackend,
+    pub descriptor_type: DescriptorType,
+}
+
+pub const BP_WPKH: WalletKind = WalletKind {
+    backend: WalletBackend::Bp,
+    descriptor_type: DescriptorType::Wpkh,
+};
+pub const BP_TR: WalletKind = WalletKind {
+    backend: WalletBackend::Bp,
+    descriptor_type: DescriptorType::Tr,
+};
+pub const BDK_WPKH: WalletKind = WalletKind {
+    backend: WalletBackend::Bdk,
+    descriptor_type: DescriptorType::Wpkh,
+};
+pub const BDK_TR: WalletKind = WalletKind {
+    backend: WalletBackend::Bdk,
+    descriptor_type: DescriptorType::Tr,
+};
+pub const BDK_WSH_MULTI: WalletKind = WalletKind {
+    backend: WalletBackend::Bdk,
+    descriptor_type: DescriptorType::WshMulti,
+};
+pub const BDK_TR_SCRIPT: WalletKind = WalletKind {
+    backend: WalletBackend::Bdk,
+    descriptor_type: DescriptorType::TrScript,
+};
+
+impl fmt::Display for WalletKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}-{}",
+            format!("{:?}", self.backend).to_lowercase(),
+            self.descriptor_type
+        )
+    }
+}
+
+/// All the wallet backend and descriptor type combinations
+pub fn wallet_kinds() -> Vec<WalletKind> {
+    WalletBackend::iter()
+        .flat_map(|backend| {
+            DescriptorType::iter()
+                .filter(move |dt| backend.supports(dt))
+                .map(move |descriptor_type| WalletKind {
+                    backend,
+                    descriptor_type,
+                })
+        })
+        .collect()
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct TransferCase {
+    pub transfer_type: TransferType,
+    pub wlt_1: WalletKind,
+    pub wlt_2: WalletKind,
+    pub asset_schema_1: AssetSchema,
+    pub asset_schema_2: AssetSchema,
+}
+
+impl fmt::Display for TransferCase {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}_{}_{}_{:?}_{:?}",
+            self.transfer_type, self.wlt_1, self.wlt_2, self.asset_schema_1, self.asset_schema_2
+        )
+    }
+}
+
+/// Number of `transfer_loop` tests the transfer matrix is split into
+pub const TRANSFER_SHARDS: usize = 8;
+
+/// Values of each transfer case parameter, in the `TransferCase` fields order
+pub struct TransferMatrixParams {
+    pub transfer_types: Vec<TransferType>,
+    pub wallet_kinds: Vec<WalletKind>,
+    pub asset_schemas: Vec<AssetSchema>,
+}
+
+impl TransferMatrixParams {
+    pub fn new() -> Self {
+        Self {
+            transfer_types: TransferType::iter().collect(),
+            wallet_kinds: wallet_kinds(),
+            asset_schemas: AssetSchema::iter().collect(),
+        }
+    }
+
+    /// Number of values of each parameter
+    pub fn sizes(&self) -> [usize; 5] {
+        [
+            self.transfer_types.len(),
+            self.wallet_kinds.len(),
+            self.wallet_kinds.len(),
+            self.asset_schemas.len(),
+            self.asset_schemas.len(),
+        ]
+    }
+
+    /// Value indexes of each parameter of the given case
+    pub fn indexes(&self, case: &TransferCase) -> [usize; 5] {
+        let pos = |kinds: &[WalletKind], kind| kinds.iter().position(|k| *k == kind).unwrap();
+        let schema_pos = |schema| {
+            self.asset_schemas
+                .iter()
+                .position(|s| *s == schema)
+                .unwrap()
+        };
+        [
+            self.transfer_types
+                .iter()
+                .position(|t| *t == case.transfer_type)
+                .unwrap(),
+            pos(&self.wallet_kinds, case.wlt_1),
+            pos(&self.wallet_kinds, case.wlt_2),
+            schema_pos(case.asset_schema_1),
+            schema_pos(case.asset_schema_2),
+        ]
+    }
+
+    fn case(&self, idx: [usize; 5]) -> TransferCase {
+        TransferCase {
+            transfer_type: self.transfer_types[idx[0]],
+            wlt_1: self.wallet_kinds[idx[1]],
+            wlt_2: self.wallet_kinds[idx[2]],
+            asset_schema_1: self.asset_schemas[idx[3]],
+            asset_schema_2: self.asset_schemas[idx[4]],
+        }
+    }
+}
+
+impl Default for TransferMatrixParams {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Every pair of values of two different parameters, as (param, value, param, value) indexes
+pub fn param_pairs(idx: &[usize; 5]) -> impl Iterator<Item = (usize, usize, usize, usize)> + '_ {
+    (0..idx.len()).flat_map(move |i| ((i + 1)..idx.len()).map(move |j| (i, idx[i], j, idx[j])))
+}
+
+/// Transfer cases covering, for every two parameters, each combination of their values (i.e. a
+/// pairwise covering set of the transfer type, wallet kinds and asset schemas product)
+pub fn transfer_matrix() -> Vec<TransferCase> {
+    let params = TransferMatrixParams::new();
+    let sizes = params.sizes();
+    let candidates = (0..sizes.iter().product())
+        .map(|mut n: usize| {
+            let mut idx = [0; 5];
+            for (i, size) in sizes.iter().enumerate().rev() {
+                idx[i] = n % size;
+                n /= size;
+            }
+            idx
+        })
+        .collect::<Vec<_>>();
+    let mut uncovered = candidates
+        .iter()
+        .flat_map(param_pairs)
+        .collect::<BTreeSet<_>>();
+
+    // greedily pick the first candidate covering the most uncovered pairs
+    let mut cases = vec![];
+    while !uncovered.is_empty() {
+        let (_, best) = candidates
+            .iter()
+            .map(|idx| {
+                (
+                    param_pairs(idx).filter(|p| uncovered.contains(p)).count(),
+                    idx,
+                )
+            })
+            .rev()
+            .max_by_key(|(covered, _)| *covered)
+            .unwrap();
+        param_pairs(best).for_each(|p| {
+            uncovered.remove(&p);
+        });
+        cases.push(params.case(*best));
+    }
+    cases
+}
+
+/// Transfer cases run by the given `transfer_loop` shard
+pub fn transfer_shard(shard: usize) -> Vec<TransferCase> {
+    assert!(
+        shard < TRANSFER_SHARDS,
+        "shard {shard} out of {TRANSFER_SHARDS}"
+    );
+    transfer_matrix()
+        .into_iter()
+        .skip(shard)
+        .step_by(TRANSFER_SHARDS)
+        .collect()
+}
@@ -0,0 +1,136 @@
+use super::*;
+
+#[derive(Clone)]
+pub enum MockResolvePubWitness {
+    Success(WitnessStatus),
+    Error(WitnessResolverError),
+}
+
+#[derive(Clone)]
+pub struct MockResolver {
+    pub pub_witnesses: HashMap<Txid, MockResolvePubWitness>,
+    pub check_chain_net_err: Option<WitnessResolverError>,
+}
+
+impl ResolveWitness for MockResolver {
+    fn resolve_witness(&self, witness_id: Txid) -> Result<WitnessStatus, WitnessResolverError> {
+        if let Some(res) = self.pub_witnesses.get(&witness_id) {
+            match res {
+                MockResolvePubWitness::Success(witness_status) => Ok(witness_status.clone()),
+                MockResolvePubWitness::Error(err) => Err(err.clone()),
+            }
+        } else {
+            Ok(WitnessStatus::Unresolved)
+        }
+    }
+
+    fn check_chain_net(&self, _: ChainNet) -> Result<(), WitnessResolverError> {
+        self.check_chain_net_err.clone().map_or(Ok(()), Err)
+    }
+}
+impl MockResolver {
+    pub fn with_new_transaction(&self, witness: Transaction) -> Self {
+        let mut resolver = self.clone();
+        let witness_id = witness.compute_txid();
+        resolver.pub_witnesses.insert(
+            witness_id,
+            MockResolvePubWitness::Success(WitnessStatus::Resolved(witness, WitnessOrd::Tentative)),
+        );
+        resolver
+    }
+}
+
+#[derive(Debug, EnumIter, Copy, Clone, PartialEq)]
+pub enum Scenario {
+    A,
+    B,
+    C,
+    D,
+}
+
+impl fmt::Display for Scenario {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{self:?}")
+    }
+}
+
+impl Scenario {
+    pub fn txs_folder(&self) -> String {
+        format!("tests/fixtures/txs_{self}/")
+    }
+
+    pub fn consignment_json(&self) -> Value {
+        let cons_path = format!("tests/fixtures/consignment_{self}.json");
+        let file = std::fs::File::open(cons_path).unwrap();
+        serde_json::from_reader(file).unwrap()
+    }
+
+    pub fn consignment_strict(&self) -> Vec<u8> {
+        std::fs::read(format!("tests/fixtures/consignment_{self}.rgb")).unwrap()
+    }
+
+    pub fn resolver(&self) -> MockResolver {
+        let mut txs = map![];
+        for entry in std::fs::read_dir(self.txs_folder()).unwrap() {
+            let file = std::fs::File::open(entry.unwrap().path()).unwrap();
+            let tx: Tx = serde_json::from_reader(file).unwrap();
+            let tx = tx_bp_to_bitcoin(tx);
+            txs.insert(tx.compute_txid(), tx);
+        }
+        MockResolver {
+            pub_witnesses: txs
+                .into_iter()
+                .map(|(txid, tx)| {
+                    (
+                        txid,
+                        MockResolvePubWitness::Success(WitnessStatus::Resolved(
+                            tx,
+                            WitnessOrd::Mined(
+                                // TODO: store actual values instead of the hardcoded WitnessPos
+                                WitnessPos::bitcoin(NonZeroU32::new(106).unwrap(), 1726062111)
+                                    .unwrap(),
+                            ),
+                        )),
+                    )
+                })
+                .collect(),
+            check_chain_net_err: None,
+        }
+    }
+}
+
+pub fn get_consignment_from_json(fname: &str) -> Transfer {
+    let cons_path = format!("tests/fixtures/{fname}.json");
+    let file = std::fs::File::open(cons_path).unwrap();
+    let consignment: Transfer = serde_json::from_reader(file).unwrap();
+    consignment
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Step {
+    Key(String),
+    Idx(usize),
+}
+
+pub type Path = Vec<Step>;
+
+pub fn get_entry_at_path_mut<'a>(root: &'a mut Value, path: &Path) -> &'a mut Value {
+    let mut curr = root;
+    for p in path {
+        match (curr, p) {
+            (Value::Object(map), Step::Key(k)) => {
+                curr = map.get_mut(k).unwrap();
+            }
+            (Value::Object(map), Step::Idx(i)) => {
+                curr = map.values_mut().nth(*i).unwrap();
+            }
+            (Value::Array(arr), Step::Idx(i)) => {
+                curr = arr.get_mut(*i).unwrap();
+            }
+            _ => {
+                unreachable!()
+            }
+        }
+    }
+    curr
+}
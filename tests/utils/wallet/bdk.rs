@@ -5,9 +5,72 @@ pub const INDEXER_BATCH_SIZE: usize = 5;
 pub const INDEXER_PARALLEL_REQUESTS: usize = 5;
 pub const KEYCHAIN_EXTERNAL: u8 = 0;
 pub const KEYCHAIN_INTERNAL: u8 = 1;
+pub const TAPRET_TWEAKS_FILE: &str = "tapret_tweaks.json";
+pub const PURPOSE_BIP48: u32 = 48;
+pub const MULTISIG_THRESHOLD: usize = 2;
 
 pub type BdkWalletImpl = PersistedWallet<Store<ChangeSet>>;
-pub type BdkTestWallet = TestWallet<BdkWalletImpl, Store<ChangeSet>>;
+pub type BdkTestWallet = TestWallet<BdkWalletImpl, BdkAux>;
+
+pub struct BdkAux {
+    db: Store<ChangeSet>,
+    /// External and internal descriptors, needed to reopen the wallet
+    descriptors: (String, String),
+    /// Wallets of the other parties of a multi-key descriptor, only used to sign
+    cosigners: Vec<BdkWallet>,
+}
+
+/// Account key of the given master key, private only if requested
+fn account_key(xpriv: &Xpriv, descriptor_type: &DescriptorType, private: bool) -> String {
+    let secp = Secp256k1::new();
+    let path = match descriptor_type {
+        DescriptorType::Wpkh => format!("{PURPOSE_BIP84}'/{COIN_RGB_TESTNET}'/0'"),
+        DescriptorType::Tr | DescriptorType::TrScript => {
+            format!("{PURPOSE_BIP86}'/{COIN_RGB_TESTNET}'/0'")
+        }
+        // BIP48 script type 2 is native segwit multisig
+        DescriptorType::WshMulti => format!("{PURPOSE_BIP48}'/{COIN_RGB_TESTNET}'/0'/2'"),
+    };
+    let account_xpriv = xpriv
+        .derive_priv(
+            &secp,
+            &BdkDerivationPath::from_str(&format!("m/{path}")).unwrap(),
+        )
+        .unwrap();
+    let origin = format!("[{}/{path}]", xpriv.fingerprint(&secp));
+    if private {
+        format!("{origin}{account_xpriv}")
+    } else {
+        format!("{origin}{}", BdkXpub::from_priv(&secp, &account_xpriv))
+    }
+}
+
+/// Descriptor for the given keychain, where only the key of the given signer is private
+fn bdk_descriptor(
+    descriptor_type: &DescriptorType,
+    xprivs: &[Xpriv],
+    signer: usize,
+    keychain: u8,
+) -> String {
+    let keys = xprivs
+        .iter()
+        .enumerate()
+        .map(|(i, xpriv)| {
+            format!(
+                "{}/{keychain}/*",
+                account_key(xpriv, descriptor_type, i == signer)
+            )
+        })
+        .collect::<Vec<_>>();
+    match descriptor_type {
+        DescriptorType::Wpkh => format!("wpkh({})", keys[0]),
+        DescriptorType::Tr => format!("tr({})", keys[0]),
+        DescriptorType::WshMulti => {
+            format!("wsh(sortedmulti({MULTISIG_THRESHOLD},{}))", keys.join(","))
+        }
+        DescriptorType::TrScript => format!("tr({},pk({}))", keys[0], keys[1]),
+    }
+}
 
 pub enum BdkIndexer {
     Electrum(Box<BdkElectrumClient<ElectrumClient>>),
@@ -39,53 +102,141 @@ impl BdkTestWallet {
     ) -> Self {
         std::fs::create_dir_all(&wallet_dir).unwrap();
         let db_path = wallet_dir.join("bdk.db");
-        let mut db = Store::<ChangeSet>::create(b"RGB", db_path).unwrap();
-
-        let bdk_wallet = BdkWallet::create(descriptor, change_descriptor)
-            .network(network)
-            .create_wallet(&mut db)
-            .unwrap();
+        let (mut db, changeset) = Store::<ChangeSet>::load_or_create(b"RGB", db_path).unwrap();
+        let bdk_wallet = if changeset.is_some() {
+            BdkWallet::load()
+                .descriptor(KeychainKind::External, Some(descriptor.clone()))
+                .descriptor(KeychainKind::Internal, Some(change_descriptor.clone()))
+                .extract_keys()
+                .check_network(network)
+                .load_wallet(&mut db)
+                .unwrap()
+                .unwrap()
+        } else {
+            BdkWallet::create(descriptor.clone(), change_descriptor.clone())
+                .network(network)
+                .create_wallet(&mut db)
+                .unwrap()
+        };
 
         let stock_path = wallet_dir.join("stock");
-        let mut stock = Stock::in_memory();
-        stock
-            .make_persistent(FsBinStore::new(stock_path).unwrap(), true)
-            .unwrap();
+        let stock = if stock_path.exists() {
+            Stock::load(FsBinStore::new(stock_path).unwrap(), true).unwrap()
+        } else {
+            let mut stock = Stock::in_memory();
+            stock
+                .make_persistent(FsBinStore::new(stock_path).unwrap(), true)
+                .unwrap();
+            stock
+        };
 
         let wallet = RgbWallet::new(stock, bdk_wallet);
 
-        Self {
+        let media_store = MediaStore::Local(wallet_dir.join(MEDIA_DATA_DIR));
+        let mut wallet = Self {
             wallet,
-            aux: db,
+            aux: BdkAux {
+                db,
+                descriptors: (descriptor, change_descriptor),
+                cosigners: vec![],
+            },
             wallet_dir,
             instance: INSTANCE_1,
             network,
+            resolver_url: None,
+            chain: chain_context(),
+            sent_transfers: HashMap::new(),
+            reject_list_policy: RejectListPolicy::default(),
+            media_store,
+            pfa_keys: HashMap::new(),
+        };
+        // bdk doesn't know about tapret tweaks, add back the ones saved in the wallet dir
+        for (terminal, tapret_commitment) in wallet.tapret_tweaks() {
+            wallet
+                .wallet
+                .wallet_mut()
+                .add_tapret_tweak(terminal, tapret_commitment)
+                .unwrap();
         }
+        wallet
     }
 
-    pub fn with_descriptor(descriptor_type: &DescriptorType) -> Self {
-        if descriptor_type != &DescriptorType::Wpkh {
-            panic!("cannot use bdk in tapret mode");
-        }
-        let mut seed = vec![0u8; 128];
-        rand::thread_rng().fill_bytes(&mut seed);
+    /// Close the wallet and open it again from its directory, as after a restart
+    pub fn reopen(mut self) -> Self {
+        let cosigners = std::mem::take(&mut self.aux.cosigners);
+        let (descriptor, change_descriptor) = self.aux.descriptors.clone();
+        let network = self.network;
+        let wallet_dir = self.wallet_dir.clone();
+        let instance = self.instance;
+        let resolver_url = self.resolver_url.clone();
+        let pfa_keys = std::mem::take(&mut self.pfa_keys);
+        drop(self);
+        let mut wallet = Self::new(network, wallet_dir, descriptor, change_descriptor);
+        wallet.aux.cosigners = cosigners;
+        wallet.instance = instance;
+        wallet.resolver_url = resolver_url;
+        wallet.pfa_keys = pfa_keys;
+        wallet
+    }
 
+    pub fn with_descriptor(descriptor_type: &DescriptorType) -> Self {
         let network = Network::Regtest;
 
-        let xpriv = Xpriv::new_master(network, &seed).unwrap();
-        let descriptor = format!(
-            "wpkh({}/{PURPOSE_BIP84}'/{COIN_RGB_TESTNET}'/0'/{KEYCHAIN_EXTERNAL}/*)",
-            xpriv
-        );
-        let change_descriptor = format!(
-            "wpkh({}/{PURPOSE_BIP84}'/{COIN_RGB_TESTNET}'/0'/{KEYCHAIN_INTERNAL}/*)",
-            xpriv
-        );
+        // the first key belongs to the wallet, the other ones to its cosigners
+        let xprivs = (0..=descriptor_type.cosigners())
+            .map(|_| {
+                let mut seed = vec![0u8; 128];
+                with_test_rng(|rng| rng.fill_bytes(&mut seed));
+                Xpriv::new_master(network, &seed).unwrap()
+            })
+            .collect::<Vec<_>>();
+        let descriptor = bdk_descriptor(descriptor_type, &xprivs, 0, KEYCHAIN_EXTERNAL);
+        let change_descriptor = bdk_descriptor(descriptor_type, &xprivs, 0, KEYCHAIN_INTERNAL);
 
         let wallet_dir = PathBuf::from(TEST_DATA_DIR)
             .join(INTEGRATION_DATA_DIR)
-            .join(xpriv.fingerprint(&Secp256k1::new()).to_string());
-        Self::new(network, wallet_dir, descriptor, change_descriptor)
+            .join(xprivs[0].fingerprint(&Secp256k1::new()).to_string());
+        // a replayed seed produces the same wallet dir, which needs to be recreated
+        if wallet_dir.exists() {
+            std::fs::remove_dir_all(&wallet_dir).unwrap();
+        }
+        let mut wallet = Self::new(network, wallet_dir, descriptor, change_descriptor);
+        wallet.aux.cosigners = (1..xprivs.len())
+            .map(|signer| {
+                BdkWallet::create(
+                    bdk_descriptor(descriptor_type, &xprivs, signer, KEYCHAIN_EXTERNAL),
+                    bdk_descriptor(descriptor_type, &xprivs, signer, KEYCHAIN_INTERNAL),
+                )
+                .network(network)
+                .create_wallet_no_persist()
+                .unwrap()
+            })
+            .collect();
+        wallet
+    }
+
+    pub fn cosigners(&self) -> usize {
+        self.aux.cosigners.len()
+    }
+
+    /// Sign with the keys of a single cosigner
+    pub fn cosign_with(&self, psbt: &mut Psbt, cosigner: usize) {
+        let sign_options = SignOptions {
+            try_finalize: false,
+            ..Default::default()
+        };
+        self.aux.cosigners[cosigner]
+            .sign(psbt, sign_options)
+            .unwrap();
+    }
+
+    /// Whether the PSBT already holds enough signatures to be finalized
+    pub fn can_finalize(&self, psbt: &Psbt) -> bool {
+        let mut psbt = psbt.clone();
+        self.wallet
+            .wallet()
+            .finalize_psbt(&mut psbt, SignOptions::default())
+            .unwrap()
     }
 
     pub fn keychain(&self, keychain_kind: KeychainKind) -> u8 {
@@ -94,6 +245,73 @@ impl BdkTestWallet {
             KeychainKind::Internal => KEYCHAIN_INTERNAL,
         }
     }
+
+    pub fn is_taproot(&self) -> bool {
+        matches!(
+            self.wallet
+                .wallet()
+                .public_descriptor(KeychainKind::External),
+            BdkDescriptor::Tr(_)
+        )
+    }
+
+    fn tap_internal_key(&self, keychain_kind: KeychainKind, index: u32) -> InternalPk {
+        let descriptor = self
+            .wallet
+            .wallet()
+            .public_descriptor(keychain_kind)
+            .at_derivation_index(index)
+            .unwrap();
+        let BdkDescriptor::Tr(tr) = descriptor else {
+            panic!("not a taproot wallet");
+        };
+        let internal_key = tr
+            .internal_key()
+            .derive_public_key(&Secp256k1::verification_only())
+            .unwrap();
+        untweakedpublickey_to_internal_pk(internal_key.inner.x_only_public_key().0)
+    }
+
+    /// Terminal of the revealed wallet address with the given taproot internal key
+    fn tap_terminal(&self, internal_pk: InternalPk) -> Option<Terminal> {
+        if !self.is_taproot() {
+            return None;
+        }
+        [KeychainKind::External, KeychainKind::Internal]
+            .into_iter()
+            .flat_map(|keychain_kind| {
+                let last_index = self.wallet.wallet().derivation_index(keychain_kind);
+                last_index
+                    .into_iter()
+                    .flat_map(|last_index| 0..=last_index)
+                    .map(move |index| (keychain_kind, index))
+            })
+            .find(|(keychain_kind, index)| {
+                self.tap_internal_key(*keychain_kind, *index) == internal_pk
+            })
+            .map(|(keychain_kind, index)| Terminal::new(self.keychain(keychain_kind), index))
+    }
+
+    /// Tapret tweaks added to the wallet, as saved in the wallet directory
+    pub fn tapret_tweaks(&self) -> Vec<(Terminal, TapretCommitment)> {
+        let path = self.wallet_dir.join(TAPRET_TWEAKS_FILE);
+        if !path.exists() {
+            return vec![];
+        }
+        let tweaks: Vec<(u8, u32, String)> =
+            serde_json::from_str(&std::fs::read_to_string(path).unwrap()).unwrap();
+        tweaks
+            .into_iter()
+            .map(|(keychain, index, commitment)| {
+                let commitment =
+                    SmallBlob::try_from(Vec::<u8>::from_hex(&commitment).unwrap()).unwrap();
+                (
+                    Terminal::new(keychain, index),
+                    TapretCommitment::from_strict_serialized(commitment).unwrap(),
+                )
+            })
+            .collect()
+    }
 }
 
 impl TestWalletExt for BdkTestWallet {
@@ -106,7 +324,7 @@ impl TestWalletExt for BdkTestWallet {
             .wallet
             .wallet_mut()
             .reveal_next_address(KeychainKind::External);
-        self.wallet.wallet_mut().persist(&mut self.aux).unwrap();
+        self.wallet.wallet_mut().persist(&mut self.aux.db).unwrap();
         DerivedAddr::new(
             address_bitcoin_to_bp(addr_info.address),
             Keychain::from(KEYCHAIN_EXTERNAL),
@@ -125,14 +343,30 @@ impl TestWalletExt for BdkTestWallet {
         let request = self.wallet.wallet().start_full_scan().build();
         let update: Update = client.full_scan(request).into();
         self.wallet.wallet_mut().apply_update(update).unwrap();
-        self.wallet.wallet_mut().persist(&mut self.aux).unwrap();
+        self.wallet.wallet_mut().persist(&mut self.aux.db).unwrap();
     }
 
-    fn sign_finalize(&self, psbt: &mut Self::Psbt) {
-        self.wallet
+    fn sign(&self, psbt: &mut Self::Psbt) {
+        let sign_options = SignOptions {
+            try_finalize: false,
+            ..Default::default()
+        };
+        self.wallet.wallet().sign(psbt, sign_options).unwrap();
+    }
+
+    fn cosign(&self, psbt: &mut Self::Psbt) {
+        for cosigner in 0..self.cosigners() {
+            self.cosign_with(psbt, cosigner);
+        }
+    }
+
+    fn finalize(&self, psbt: &mut Self::Psbt) {
+        let finalized = self
+            .wallet
             .wallet()
-            .sign(psbt, SignOptions::default())
+            .finalize_psbt(psbt, SignOptions::default())
             .unwrap();
+        assert!(finalized, "missing signatures");
     }
 
     fn extract(&self, psbt: &Self::Psbt) -> Tx {
@@ -140,7 +374,52 @@ impl TestWalletExt for BdkTestWallet {
     }
 
     fn tap_address(&mut self) -> (BpAddress, InternalPk, NormalIndex) {
-        todo!("wait for taproot tweaks support")
+        let addr_info = self
+            .wallet
+            .wallet_mut()
+            .reveal_next_address(KeychainKind::External);
+        self.wallet.wallet_mut().persist(&mut self.aux.db).unwrap();
+        (
+            address_bitcoin_to_bp(addr_info.address),
+            self.tap_internal_key(KeychainKind::External, addr_info.index),
+            NormalIndex::try_from_index(addr_info.index).unwrap(),
+        )
+    }
+
+    fn persist_tapret_tweak(&mut self, terminal: Terminal, tapret_commitment: TapretCommitment) {
+        // bdk doesn't know about tapret tweaks, keep them next to the wallet DB
+        let mut tweaks = self.tapret_tweaks();
+        if tweaks.contains(&(terminal, tapret_commitment.clone())) {
+            return;
+        }
+        tweaks.push((terminal, tapret_commitment));
+        let tweaks: Vec<(u8, u32, String)> = tweaks
+            .into_iter()
+            .map(|(terminal, commitment)| {
+                let commitment = commitment.to_strict_serialized::<U16>().unwrap();
+                (
+                    terminal.keychain,
+                    terminal.index,
+                    commitment.release().to_hex(),
+                )
+            })
+            .collect();
+        std::fs::write(
+            self.wallet_dir.join(TAPRET_TWEAKS_FILE),
+            serde_json::to_string(&tweaks).unwrap(),
+        )
+        .unwrap();
+        self.wallet.wallet_mut().persist(&mut self.aux.db).unwrap();
+    }
+
+    fn persist_transfer_tapret_tweak(&mut self, consignment: &Transfer, txid: Txid) {
+        let Some((internal_pk, tapret_commitment)) = transfer_tapret_commitment(consignment, txid)
+        else {
+            return;
+        };
+        if let Some(terminal) = self.tap_terminal(internal_pk) {
+            self.persist_tapret_tweak(terminal, tapret_commitment);
+        }
     }
 
     fn pay(
@@ -148,9 +427,88 @@ impl TestWalletExt for BdkTestWallet {
         invoice: RgbInvoice,
         params: TransferParams,
     ) -> (Self::Psbt, Self::PsbtMeta, Transfer) {
-        self.wallet
+        let (psbt, meta, consignment) = self
+            .wallet
             .pay::<ProprietaryKey, BdkOutput>(&invoice, params)
+            .unwrap();
+        // paying may have revealed addresses or added a tapret tweak on change
+        self.wallet.wallet_mut().persist(&mut self.aux.db).unwrap();
+        self.persist_transfer_tapret_tweak(&consignment, psbt.unsigned_tx.compute_txid());
+        (psbt, meta, consignment)
+    }
+
+    fn construct_rgb_psbt(
+        &mut self,
+        invoice: &RgbInvoice,
+        params: TransferParams,
+    ) -> (Self::Psbt, PsbtMeta) {
+        let res = self
+            .wallet
+            .construct_psbt::<ProprietaryKey, BdkOutput>(invoice, params)
+            .unwrap();
+        // building may have revealed a change address
+        self.wallet.wallet_mut().persist(&mut self.aux.db).unwrap();
+        res
+    }
+
+    fn complete_rgb_transfer(
+        &mut self,
+        invoice: &RgbInvoice,
+        psbt: &mut Self::Psbt,
+        meta: PsbtMeta,
+    ) -> Transfer {
+        let consignment = self
+            .wallet
+            .transfer(invoice, psbt, meta.beneficiary_vout)
+            .unwrap();
+        self.persist_transfer_tapret_tweak(&consignment, psbt.unsigned_tx.compute_txid());
+        consignment
+    }
+
+    fn psbt_inputs_fee(&self, psbt: &Self::Psbt) -> (BTreeSet<Outpoint>, u64) {
+        let inputs = psbt
+            .unsigned_tx
+            .input
+            .iter()
+            .map(|txin| txin.previous_output)
+            .collect();
+        (inputs, psbt.fee().unwrap().to_sat())
+    }
+
+    fn psbt_unsigned_tx(&self, psbt: &Self::Psbt) -> Transaction {
+        psbt.unsigned_tx.clone()
+    }
+
+    fn max_input_vsize(&self) -> f64 {
+        let satisfaction_wu = self
+            .wallet
+            .wallet()
+            .public_descriptor(KeychainKind::External)
+            .max_weight_to_satisfy()
+            .unwrap()
+            .to_wu();
+        (TxIn::default().segwit_weight().to_wu() + satisfaction_wu) as f64 / 4.0
+    }
+
+    fn construct_btc_psbt(
+        &mut self,
+        inputs: Vec<Outpoint>,
+        beneficiaries: Vec<(Address, u64)>,
+        fee: u64,
+    ) -> Self::Psbt {
+        let mut builder = self.wallet.wallet_mut().build_tx();
+        builder
+            .add_utxos(&inputs)
             .unwrap()
+            .manually_selected_only()
+            .fee_absolute(BtcAmount::from_sat(fee));
+        for (address, sats) in beneficiaries {
+            builder.add_recipient(address.script_pubkey(), BtcAmount::from_sat(sats));
+        }
+        let psbt = builder.finish().unwrap();
+        // building may have revealed a change address
+        self.wallet.wallet_mut().persist(&mut self.aux.db).unwrap();
+        psbt
     }
 
     fn list_coins(&self) -> HashMap<(Address, Terminal), Vec<Coin>> {
@@ -8,6 +8,26 @@ pub enum WalletAccount {
     Public(XpubAccount),
 }
 
+/// Signer holding the keys of a watch-only wallet, only exchanging serialized PSBTs with it
+pub struct ExternalSigner {
+    signer: TestnetSigner,
+}
+
+impl ExternalSigner {
+    pub fn new(xpriv_account: XprivAccount) -> Self {
+        Self {
+            signer: TestnetSigner::new(xpriv_account),
+        }
+    }
+
+    pub fn sign(&self, psbt: &[u8]) -> Vec<u8> {
+        let mut psbt = BpPsbt::deserialize(psbt).unwrap();
+        let sig_count = psbt.sign(&self.signer).unwrap();
+        assert!(sig_count > 0, "nothing to sign");
+        psbt.serialize(psbt.version)
+    }
+}
+
 impl BpTestWallet {
     pub fn new(
         descriptor_type: Option<&DescriptorType>,
@@ -36,6 +56,7 @@ impl BpTestWallet {
             let descriptor = match dt {
                 DescriptorType::Wpkh => RgbDescr::Wpkh(WpkhDescr::with_key(xpub_derivable)),
                 DescriptorType::Tr => RgbDescr::TapretKey(TapretKey::with_key(xpub_derivable)),
+                dt => panic!("cannot use bp with {dt} descriptors"),
             };
             let mut bp_wallet = BpWallet::new_layer1(descriptor.clone(), network);
             let bp_wallet_provider = FsTextStore::new(bp_dir).unwrap();
@@ -70,12 +91,19 @@ impl BpTestWallet {
             WalletAccount::Public(_) => None,
         };
 
+        let media_store = MediaStore::Local(wallet_dir.join(MEDIA_DATA_DIR));
         let mut wallet = Self {
             wallet,
             aux: signer,
             wallet_dir,
             instance,
             network: network_bp_to_bitcoin(network),
+            resolver_url: None,
+            chain: chain_context(),
+            sent_transfers: HashMap::new(),
+            reject_list_policy: RejectListPolicy::default(),
+            media_store,
+            pfa_keys: HashMap::new(),
         };
 
         wallet.sync();
@@ -115,7 +143,7 @@ impl BpTestWallet {
         if let Some(rng) = rng {
             rng.fill_bytes(&mut seed);
         } else {
-            rand::thread_rng().fill_bytes(&mut seed);
+            with_test_rng(|rng| rng.fill_bytes(&mut seed));
         }
 
         let (xpriv_account, wallet_dir) = Self::gen_keys(&seed);
@@ -132,6 +160,41 @@ impl BpTestWallet {
         (wallet, seed)
     }
 
+    /// Wallet stored under the given directory instead of the test data one
+    pub fn in_dir(descriptor_type: &DescriptorType, data_dir: &std::path::Path) -> Self {
+        let mut seed = vec![0u8; 128];
+        with_test_rng(|rng| rng.fill_bytes(&mut seed));
+        let (xpriv_account, wallet_dir) = Self::gen_keys(&seed);
+
+        Self::new(
+            Some(descriptor_type),
+            BpNetwork::Regtest,
+            data_dir.join(wallet_dir.file_name().unwrap()),
+            WalletAccount::Private(xpriv_account),
+            INSTANCE_1,
+            true,
+            vec![Keychain::OUTER, Keychain::INNER],
+        )
+    }
+
+    /// Watch-only wallet, along with the signer holding its keys
+    pub fn watch_only(descriptor_type: &DescriptorType) -> (Self, ExternalSigner) {
+        let mut seed = vec![0u8; 128];
+        with_test_rng(|rng| rng.fill_bytes(&mut seed));
+        let (xpriv_account, wallet_dir) = Self::gen_keys(&seed);
+
+        let wallet = Self::new(
+            Some(descriptor_type),
+            BpNetwork::Regtest,
+            wallet_dir,
+            WalletAccount::Public(xpriv_account.to_xpub_account()),
+            INSTANCE_1,
+            true,
+            vec![Keychain::OUTER, Keychain::INNER],
+        );
+        (wallet, ExternalSigner::new(xpriv_account))
+    }
+
     pub fn new_mainnet() -> Self {
         let xpub_account = XpubAccount::from_str(
             "[c32338a7/86h/0h/0h]xpub6CmiK1xc7YwL472qm4zxeURFX8yMCSasioXujBjVMMzA3AKZr6KLQEmkzDge1Ezn2p43ZUysyx6gfajFVVnhtQ1AwbXEHrioLioXXgj2xW5"
@@ -174,6 +237,43 @@ impl BpTestWallet {
         self.wallet.wallet().descriptor()
     }
 
+    pub fn is_watch_only(&self) -> bool {
+        self.aux.is_none()
+    }
+
+    /// Have the PSBT signed by the external signer, checking the RGB data survives the
+    /// serialization round-trip, then finalize it
+    pub fn sign_finalize_external(&self, psbt: &mut BpPsbt, signer: &ExternalSigner) {
+        let serialized = BpPsbt::serialize(psbt, psbt.version);
+        let signed = BpPsbt::deserialize(signer.sign(&serialized)).unwrap();
+        assert_eq!(signed.to_unsigned_tx(), psbt.to_unsigned_tx());
+        assert_eq!(signed.proprietary, psbt.proprietary);
+        for (signed_input, input) in signed.inputs().zip(psbt.inputs()) {
+            assert_eq!(signed_input.proprietary, input.proprietary);
+        }
+        for (signed_output, output) in signed.outputs().zip(psbt.outputs()) {
+            assert_eq!(signed_output.proprietary, output.proprietary);
+        }
+        assert_eq!(signed.rgb_close_method(), psbt.rgb_close_method());
+        *psbt = signed;
+        psbt.finalize(self.descriptor());
+    }
+
+    pub fn pay_full_external(
+        &mut self,
+        invoice: RgbInvoice,
+        sats: Option<u64>,
+        fee: Option<u64>,
+        signer: &ExternalSigner,
+    ) -> (Transfer, Tx) {
+        self.sync();
+        let (mut psbt, _, consignment) = self.pay_invoice(invoice, None, sats, fee);
+        self.sign_finalize_external(&mut psbt, signer);
+        let tx = self.extract(&psbt);
+        self.broadcast_tx(&tx);
+        (consignment, tx)
+    }
+
     fn get_next_internal_pk(&mut self) -> (InternalPk, NormalIndex) {
         let keychain = self.keychain();
         let index = self.get_next_index(keychain, true);
@@ -198,40 +298,21 @@ impl BpTestWallet {
         TestWallet<W2, D2>: TestWalletExt,
         <TestWallet<W2, D2> as TestWalletExt>::Psbt: Serialize,
     {
-        let transition_signer = |witness_bundle: &mut WitnessBundle| {
-            for KnownTransition { opid, transition } in
-                witness_bundle.bundle_mut().known_transitions.iter_mut()
-            {
-                let transition_id: [u8; 32] = opid.as_ref().into_inner();
-                let msg = Message::from_digest(transition_id);
-                let signature = secret_key.sign_ecdsa(msg);
-                transition.signature =
-                    Some(Bytes64::from_array(signature.serialize_compact()).into());
-            }
-        };
-
         let schema_id = self.schema_id(contract_id);
         assert_eq!(schema_id, AssetSchema::Pfa.schema().schema_id());
-        let invoice = recv_wlt.invoice(contract_id, schema_id, amount, transfer_type);
-        let (mut consignment, tx, psbt, psbt_meta) = self.pay_full(invoice, None, None, true, None);
-        let bp_txid = tx.txid();
-        let txid = txid_bp_to_bitcoin(bp_txid);
-        consignment.modify_bundle(txid, transition_signer);
-        self.accept_transfer(consignment.clone(), None);
-        let output_seal: OutputSeal =
-            ExplicitSeal::new(Outpoint::new(txid, psbt_meta.change_vout.unwrap()));
-        for cid in psbt.rgb_contract_ids().unwrap() {
-            if cid == contract_id {
-                continue;
+        self.add_pfa_key(contract_id, secret_key);
+        // other PFA contracts of the same issuer moved by the transfer get signed too
+        let public_key = secret_key.public_key(&Secp256k1::new());
+        let pubkey = CompressedPublicKey::from_slice(&public_key.serialize()).unwrap();
+        for contract in self.list_contracts() {
+            if AssetSchema::from(contract.schema_id) == AssetSchema::Pfa
+                && self.pfa_pubkey(contract.id) == pubkey
+            {
+                self.add_pfa_key(contract.id, secret_key);
             }
-            let mut extra_cons = self.consign_transfer(cid, [output_seal], [], [], Some(bp_txid));
-            let changed = extra_cons.modify_bundle(txid, transition_signer);
-            assert!(changed);
-            self.accept_transfer(extra_cons.clone(), None);
         }
-        self.mine_tx(&txid, false);
-        recv_wlt.accept_transfer(consignment.clone(), None);
-        self.sync();
+        let invoice = recv_wlt.invoice(contract_id, schema_id, amount, transfer_type);
+        self.send_to_invoice(recv_wlt, invoice, None, None, None);
     }
 
     pub fn inflate_ifa(
@@ -284,7 +365,8 @@ impl BpTestWallet {
         }
         let mut beneficiaries = vec![];
         for (vout, inflation_amount) in inflation_amounts.into_iter().enumerate() {
-            let seal = BuilderSeal::Revealed(GraphSeal::new_random_vout(vout as u32));
+            let seal =
+                BuilderSeal::Revealed(GraphSeal::with_blinded_vout(vout as u32, test_blinding()));
             beneficiaries.push(seal);
             asset_transition_builder = asset_transition_builder
                 .add_fungible_state("assetOwner", seal, inflation_amount)
@@ -293,7 +375,8 @@ impl BpTestWallet {
 
         if inflation_change > 0 {
             let change_vout = num_psbt_beneficiaries as u32 - 1;
-            let seal = BuilderSeal::Revealed(GraphSeal::new_random_vout(change_vout));
+            let seal =
+                BuilderSeal::Revealed(GraphSeal::with_blinded_vout(change_vout, test_blinding()));
             beneficiaries.push(seal);
             asset_transition_builder = asset_transition_builder
                 .add_fungible_state(fname!("inflationAllowance"), seal, inflation_change)
@@ -309,6 +392,12 @@ impl BpTestWallet {
         psbt.set_opret_host();
         psbt.set_rgb_close_method(CloseMethod::OpretFirst);
         psbt.set_as_unmodifiable();
+        // the MPC entropy comes from the test context too, so the commitment is reproducible
+        psbt.outputs_mut()
+            .find(|o| o.is_opret_host())
+            .unwrap()
+            .set_mpc_entropy(test_blinding())
+            .unwrap();
         let fascia = psbt.rgb_commit().unwrap();
         let txid = psbt.txid();
         let tx = self.sign_finalize_extract(&mut psbt);
@@ -382,7 +471,7 @@ impl BpTestWallet {
             }
         }
         let burn_amt_by_type = burn_amount_by_type.unwrap_or_default();
-        let seal = BuilderSeal::Revealed(GraphSeal::new_random_vout(0));
+        let seal = BuilderSeal::Revealed(GraphSeal::with_blinded_vout(0, test_blinding()));
         for (assignment_type, amt) in &amt_by_type {
             let burn_amt = burn_amt_by_type.get(assignment_type).unwrap_or(amt);
             let change = amt - burn_amt;
@@ -953,6 +1042,16 @@ impl BpTestWallet {
         coloring_info: ColoringInfo,
         fee: Option<u64>,
         rgb_change: Option<Outpoint>,
+    ) -> (ConsignmentsMap, Tx, BpPsbtMeta, Option<TweakInfo>) {
+        self.pay_full_flexible_custom(coloring_info, fee, rgb_change, None)
+    }
+
+    pub fn pay_full_flexible_custom(
+        &mut self,
+        coloring_info: ColoringInfo,
+        fee: Option<u64>,
+        rgb_change: Option<Outpoint>,
+        signer: Option<&ExternalSigner>,
     ) -> (ConsignmentsMap, Tx, BpPsbtMeta, Option<TweakInfo>) {
         let beneficiaries = coloring_info
             .asset_info_map
@@ -987,7 +1086,12 @@ impl BpTestWallet {
             }
         }
 
-        let tx = self.sign_finalize_extract(&mut psbt);
+        let tx = if let Some(signer) = signer {
+            self.sign_finalize_external(&mut psbt, signer);
+            self.extract(&psbt)
+        } else {
+            self.sign_finalize_extract(&mut psbt)
+        };
 
         self.broadcast_tx(&tx);
         let txid = tx.txid();
@@ -1001,6 +1105,279 @@ impl BpTestWallet {
 
         (consignment_map, tx, psbt_meta, tweak_info)
     }
+
+    /// Pay all the given invoices with a single transaction, returning the consignments for
+    /// each invoice (in the same order), each one revealing only the seal of its recipient.
+    ///
+    /// Invoices can be for different fungible contracts and mix blinded and witness
+    /// beneficiaries; witness ones receive `sats` (or 2000 if not provided) each.
+    pub fn pay_many(
+        &mut self,
+        invoices: Vec<RgbInvoice>,
+        sats: Option<u64>,
+        fee: Option<u64>,
+    ) -> (Vec<Transfer>, Tx) {
+        self.sync();
+
+        let mut asset_info_map: HashMap<ContractId, AssetColoringInfo> = HashMap::new();
+        let mut witness_scripts = HashSet::new();
+        for invoice in &invoices {
+            let contract_id = invoice.contract.expect("invoice without contract");
+            assert_ne!(
+                self.asset_schema(contract_id),
+                AssetSchema::Uda,
+                "UDA invoices are not supported"
+            );
+            let amount = match invoice.assignment_state {
+                Some(InvoiceState::Amount(amount)) => amount.value(),
+                _ => panic!("invoice without amount"),
+            };
+            let destination = match invoice.beneficiary.into_inner() {
+                Beneficiary::BlindedSeal(secret_seal) => AssetDestination::Blinded(secret_seal),
+                Beneficiary::WitnessVout(pay2vout, tap_internal_key) => {
+                    // recipients are told apart by script, so each one needs its own
+                    assert!(
+                        witness_scripts.insert(pay2vout.to_script()),
+                        "witness invoices must have distinct addresses"
+                    );
+                    AssetDestination::Witness(WitnessInfo {
+                        derived_address: DerivedAddr::new(
+                            address_bitcoin_to_bp(pay2vout.into_address(self.network())),
+                            Keychain::from(KEYCHAIN_EXTERNAL),
+                            NormalIndex::ZERO,
+                        ),
+                        tap_internal_key: tap_internal_key.map(untweakedpublickey_to_internal_pk),
+                        amount_sats: Some(sats.unwrap_or(2000)),
+                    })
+                }
+            };
+            asset_info_map
+                .entry(contract_id)
+                .or_insert_with(|| AssetColoringInfo {
+                    input_outpoints: self.contract_outpoints(contract_id).into_iter().collect(),
+                    assignments: vec![],
+                })
+                .assignments
+                .push(AssetAssignment {
+                    destination,
+                    amount,
+                });
+        }
+        let coloring_info = ColoringInfo {
+            asset_info_map,
+            static_blinding: None,
+            nonce: None,
+            close_method: self.close_method(),
+        };
+        let (_, tx, _, _) = self.pay_full_flexible(coloring_info, fee, None);
+
+        let txid = tx.txid();
+        let consignments = invoices
+            .into_iter()
+            .map(|invoice| {
+                let contract_id = invoice.contract.unwrap();
+                match invoice.beneficiary.into_inner() {
+                    Beneficiary::BlindedSeal(secret_seal) => {
+                        self.consign_transfer(contract_id, [], [secret_seal], [], Some(txid))
+                    }
+                    Beneficiary::WitnessVout(pay2vout, _) => {
+                        let script_pubkey = script_buf_to_script_pubkey(pay2vout.to_script());
+                        let vout = tx
+                            .outputs()
+                            .position(|o| o.script_pubkey == script_pubkey)
+                            .unwrap() as u32;
+                        let seal = ExplicitSeal::new(Outpoint::new(txid_bp_to_bitcoin(txid), vout));
+                        self.consign_transfer(contract_id, [seal], [], [], Some(txid))
+                    }
+                }
+            })
+            .collect();
+
+        (consignments, tx)
+    }
+
+    /// Spend the given output of an unconfirmed transaction with a child paying enough fees to
+    /// bring the package to the given fee rate.
+    ///
+    /// All the RGB state assigned to the spent outputs is moved to the only output of the child
+    /// (besides the opret commitment), so nothing gets burned. When the output doesn't cover the
+    /// fee, the biggest uncolored UTXO of the wallet is spent too.
+    pub fn cpfp(
+        &mut self,
+        outpoint: Outpoint,
+        parent_tx: &Tx,
+        parent_fee: u64,
+        package_feerate: f64,
+    ) -> Tx {
+        self.sync();
+        let parent_vsize = tx_bp_to_bitcoin(parent_tx.clone()).vsize() as f64;
+        assert_eq!(outpoint.txid, txid_bp_to_bitcoin(parent_tx.txid()));
+
+        let unspents = self.list_unspents();
+        let input_scripts = self
+            .list_coins()
+            .into_iter()
+            .flat_map(|((address, _), coins)| {
+                let script_pubkey = address.script_pubkey();
+                coins
+                    .into_iter()
+                    .map(move |c| (c.outpoint, script_pubkey.clone()))
+            })
+            .collect::<HashMap<_, _>>();
+        let is_colored = |wlt: &Self, outpoint: Outpoint| {
+            wlt.wallet
+                .stock()
+                .contracts_assigning([outpoint])
+                .unwrap()
+                .next()
+                .is_some()
+        };
+        assert!(
+            unspents.contains_key(&outpoint),
+            "{outpoint} is not spendable"
+        );
+
+        let witness_info = self.get_witness_info(None, None);
+        let output_script = address_bp_to_bitcoin(witness_info.address()).script_pubkey();
+        let colored = is_colored(self, outpoint);
+        let opret = colored && self.close_method() == CloseMethod::OpretFirst;
+        let child_fee = |inputs: &[Outpoint]| {
+            let mut child_vsize = TX_OVERHEAD_VSIZE + output_vsize(&output_script);
+            for input in inputs {
+                child_vsize += input_vsize(&input_scripts[input]);
+            }
+            if opret {
+                child_vsize += OPRET_OUTPUT_VSIZE;
+            }
+            let child_vsize = child_vsize.ceil();
+            let package_fee = (package_feerate * (parent_vsize + child_vsize)).ceil() as u64;
+            // the child alone must pay at least the minimum relay fee
+            package_fee
+                .saturating_sub(parent_fee)
+                .max(child_vsize.ceil() as u64)
+        };
+        // keep the output well above the dust limit
+        let min_output = 1000;
+        let mut inputs = vec![outpoint];
+        let mut fee = child_fee(&inputs);
+        if unspents[&outpoint] < fee + min_output {
+            let (extra, _) = unspents
+                .iter()
+                .filter(|(o, _)| o.txid != outpoint.txid && !is_colored(self, **o))
+                .max_by_key(|(_, amt)| **amt)
+                .expect("no uncolored UTXO to pay the CPFP fee");
+            inputs.push(*extra);
+            fee = child_fee(&inputs);
+        }
+        let input_sats: u64 = inputs.iter().map(|o| unspents[o]).sum();
+        assert!(
+            input_sats >= fee + min_output,
+            "not enough funds to pay the CPFP fee"
+        );
+
+        if !colored {
+            let (mut psbt, _) =
+                self.construct_psbt(inputs, vec![witness_info.btc_beneficiary()], Some(fee));
+            let tx = self.sign_finalize_extract(&mut psbt);
+            self.broadcast_tx(&tx);
+            return tx;
+        }
+        self.move_to_self(inputs, &[], witness_info, fee)
+    }
+
+    /// Spend the given outpoints to a single wallet output, moving there all the RGB state they
+    /// hold. Allocations of the given fungible contracts get merged into a single one.
+    fn move_to_self(
+        &mut self,
+        inputs: Vec<Outpoint>,
+        merged: &[ContractId],
+        witness_info: WitnessInfo,
+        fee: u64,
+    ) -> Tx {
+        let (mut psbt, mut psbt_meta) = self.construct_psbt(
+            inputs.clone(),
+            vec![witness_info.btc_beneficiary()],
+            Some(fee),
+        );
+
+        // the only output acts as RGB change, receiving the state of all contracts
+        let output = psbt
+            .outputs_mut()
+            .find(|o| o.script == witness_info.script_pubkey())
+            .unwrap();
+        output.tap_internal_key = witness_info.tap_internal_key;
+        psbt_meta.change_vout = Some(output.vout());
+        psbt_meta.change_terminal = Some(witness_info.derived_address.terminal);
+        let asset_info_map = merged
+            .iter()
+            .map(|contract_id| {
+                let asset_coloring_info = AssetColoringInfo {
+                    input_outpoints: inputs.clone(),
+                    assignments: vec![],
+                };
+                (*contract_id, asset_coloring_info)
+            })
+            .collect();
+        let coloring_info = ColoringInfo {
+            asset_info_map,
+            static_blinding: None,
+            nonce: None,
+            close_method: self.close_method(),
+        };
+        let (fascia, _, _, _) = self.color_psbt(&mut psbt, &mut psbt_meta, coloring_info, None);
+        if psbt.rgb_close_method().unwrap().unwrap() == CloseMethod::TapretFirst {
+            let tapret_commitment = psbt
+                .dbc_output::<TapretProof>()
+                .unwrap()
+                .tapret_commitment()
+                .unwrap();
+            self.add_tapret_tweak(witness_info.terminal(), tapret_commitment);
+        }
+
+        let tx = self.sign_finalize_extract(&mut psbt);
+        self.broadcast_tx(&tx);
+        self.consume_fascia(fascia, tx.txid());
+        tx
+    }
+
+    /// Merge the allocations of the given fungible contracts into a single output, spending at
+    /// most `max_inputs` colored outpoints (the ones holding more allocations first)
+    pub fn consolidate(&mut self, contract_ids: &[ContractId], max_inputs: usize) -> Tx {
+        self.sync();
+        let mut allocations = BTreeMap::<Outpoint, usize>::new();
+        for contract_id in contract_ids {
+            assert!(
+                !matches!(self.asset_schema(*contract_id), AssetSchema::Uda),
+                "cannot consolidate non-fungible contract {contract_id}"
+            );
+            let unspents = self.list_unspent_outpoints();
+            for allocation in self.contract_fungible_allocations(*contract_id, false) {
+                let outpoint = allocation.seal.outpoint().unwrap();
+                if unspents.contains(&outpoint) {
+                    *allocations.entry(outpoint).or_default() += 1;
+                }
+            }
+        }
+        let mut colored = allocations.into_iter().collect::<Vec<_>>();
+        colored.sort_by_key(|(outpoint, count)| (Reverse(*count), *outpoint));
+        let mut inputs = colored
+            .into_iter()
+            .take(max_inputs)
+            .map(|(outpoint, _)| outpoint)
+            .collect::<Vec<_>>();
+        assert!(!inputs.is_empty(), "nothing to consolidate");
+
+        // keep the output well above the dust limit
+        let fee = DEFAULT_FEE_ABS;
+        let min_output = 1000;
+        let unspents = self.list_unspents();
+        let input_sats: u64 = inputs.iter().map(|o| unspents[o]).sum();
+        if input_sats < fee + min_output {
+            inputs.extend(self.select_coins(fee + min_output - input_sats, &ColoredCoins::Exclude));
+        }
+        let witness_info = self.get_witness_info(None, None);
+        self.move_to_self(inputs, contract_ids, witness_info, fee)
+    }
 }
 
 impl TestWalletExt for BpTestWallet {
@@ -1027,8 +1404,19 @@ impl TestWalletExt for BpTestWallet {
             .unwrap();
     }
 
-    fn sign_finalize(&self, psbt: &mut Self::Psbt) {
-        let _sig_count = psbt.sign(self.aux.as_ref().unwrap()).unwrap();
+    fn sign(&self, psbt: &mut Self::Psbt) {
+        let signer = self
+            .aux
+            .as_ref()
+            .expect("watch-only wallet, use an external signer");
+        let _sig_count = psbt.sign(signer).unwrap();
+    }
+
+    fn cosign(&self, _psbt: &mut Self::Psbt) {
+        // bp wallets are single-sig
+    }
+
+    fn finalize(&self, psbt: &mut Self::Psbt) {
         psbt.finalize(self.descriptor());
     }
 
@@ -1046,6 +1434,14 @@ impl TestWalletExt for BpTestWallet {
         (address, tap_internal_key, index)
     }
 
+    fn persist_tapret_tweak(&mut self, _terminal: Terminal, _tapret_commitment: TapretCommitment) {
+        // the bp wallet stores tweaks in its own persistent data
+    }
+
+    fn persist_transfer_tapret_tweak(&mut self, _consignment: &Transfer, _txid: Txid) {
+        // the bp wallet stores tweaks in its own persistent data
+    }
+
     fn pay(
         &mut self,
         invoice: RgbInvoice,
@@ -1056,6 +1452,63 @@ impl TestWalletExt for BpTestWallet {
             .unwrap()
     }
 
+    fn construct_rgb_psbt(
+        &mut self,
+        invoice: &RgbInvoice,
+        params: TransferParams,
+    ) -> (Self::Psbt, PsbtMeta) {
+        self.wallet
+            .construct_psbt::<PropKey, Output>(invoice, params)
+            .unwrap()
+    }
+
+    fn complete_rgb_transfer(
+        &mut self,
+        invoice: &RgbInvoice,
+        psbt: &mut Self::Psbt,
+        meta: PsbtMeta,
+    ) -> Transfer {
+        self.wallet
+            .transfer(invoice, psbt, meta.beneficiary_vout)
+            .unwrap()
+    }
+
+    fn psbt_inputs_fee(&self, psbt: &Self::Psbt) -> (BTreeSet<Outpoint>, u64) {
+        let inputs = psbt
+            .inputs()
+            .map(|input| outpoint_bp_to_bitcoin(input.previous_outpoint))
+            .collect();
+        (inputs, psbt.fee().unwrap().sats())
+    }
+
+    fn psbt_unsigned_tx(&self, psbt: &Self::Psbt) -> Transaction {
+        psbt.unsigned_tx()
+    }
+
+    fn max_input_vsize(&self) -> f64 {
+        // bp wallets use key path spending only, so every input spends the same kind of script
+        let address = self
+            .wallet
+            .wallet()
+            .addresses(self.keychain())
+            .next()
+            .expect("address iterator always can produce address");
+        input_vsize(&address_bp_to_bitcoin(address.addr).script_pubkey())
+    }
+
+    fn construct_btc_psbt(
+        &mut self,
+        inputs: Vec<Outpoint>,
+        beneficiaries: Vec<(Address, u64)>,
+        fee: u64,
+    ) -> Self::Psbt {
+        let beneficiaries = beneficiaries
+            .into_iter()
+            .map(|(address, sats)| (address_bitcoin_to_bp(address), Some(sats)))
+            .collect();
+        self.construct_psbt(inputs, beneficiaries, Some(fee)).0
+    }
+
     fn list_coins(&self) -> HashMap<(Address, Terminal), Vec<Coin>> {
         self.wallet
             .wallet()
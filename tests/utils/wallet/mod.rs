@@ -65,10 +65,29 @@ impl<W: WalletProvider> Filter<'_, W> {
     }
 }
 
-#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[derive(Debug, EnumIter, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub enum DescriptorType {
     Wpkh,
     Tr,
+    /// 2-of-3 wsh multisig
+    WshMulti,
+    /// Tapret key with a script tree, holding a leaf spendable by a cosigner
+    TrScript,
+}
+
+impl DescriptorType {
+    pub fn is_taproot(&self) -> bool {
+        matches!(self, Self::Tr | Self::TrScript)
+    }
+
+    /// Number of keys, besides the wallet one, appearing in the descriptor
+    pub fn cosigners(&self) -> usize {
+        match self {
+            Self::Wpkh | Self::Tr => 0,
+            Self::WshMulti => 2,
+            Self::TrScript => 1,
+        }
+    }
 }
 
 impl fmt::Display for DescriptorType {
@@ -90,7 +109,7 @@ pub enum ReorgType {
     Revert,
 }
 
-#[derive(Debug, Copy, Clone, PartialEq)]
+#[derive(Debug, EnumIter, Copy, Clone, PartialEq)]
 pub enum TransferType {
     Blinded,
     Witness,
@@ -102,6 +121,7 @@ impl fmt::Display for TransferType {
     }
 }
 
+#[derive(Clone, Copy, Debug)]
 pub enum InvoiceType {
     Blinded(Option<Outpoint>),
     Witness,
@@ -117,6 +137,73 @@ impl From<TransferType> for InvoiceType {
     }
 }
 
+/// How coin selection treats outpoints holding RGB state
+#[derive(Clone, Debug, Default, PartialEq)]
+pub enum ColoredCoins {
+    /// Never spend colored outpoints
+    #[default]
+    Exclude,
+    /// Spend the given colored outpoints, besides the uncolored ones needed, burning their state
+    Include(Vec<Outpoint>),
+}
+
+/// Fee paid by a transfer transaction
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TxFee {
+    /// Absolute fee, in sats
+    Abs(u64),
+    /// Fee rate, in sat/vB, applied to the estimated size of the transaction
+    Rate(f64),
+}
+
+impl Default for TxFee {
+    fn default() -> Self {
+        Self::Abs(DEFAULT_FEE_ABS)
+    }
+}
+
+// vsize of version, locktime, input and output counts plus the segwit marker and flag
+pub const TX_OVERHEAD_VSIZE: f64 = 10.5;
+// OP_RETURN output with a 32-byte push
+pub const OPRET_OUTPUT_VSIZE: f64 = 43.0;
+
+/// Estimated vsize of an input spending the given script, signatures of maximum length
+pub fn input_vsize(script_pubkey: &ScriptBuf) -> f64 {
+    // outpoint, script length, sequence
+    let base = 32.0 + 4.0 + 1.0 + 4.0;
+    let witness_wu = if script_pubkey.is_p2wpkh() {
+        // items count, signature, pubkey
+        1 + 73 + 34
+    } else if script_pubkey.is_p2tr() {
+        // items count, schnorr signature (key path)
+        1 + 65
+    } else if script_pubkey.is_p2wsh() {
+        // items count, empty dummy, 2 signatures, 2-of-3 multisig witness script
+        1 + 1 + 2 * 73 + 1 + 105
+    } else {
+        panic!("unsupported input script {script_pubkey}")
+    };
+    base + witness_wu as f64 / 4.0
+}
+
+pub fn output_vsize(script_pubkey: &ScriptBuf) -> f64 {
+    // amount, script length, script
+    8.0 + 1.0 + script_pubkey.len() as f64
+}
+
+/// Fee of a transaction, given the amounts of the outputs it spends
+pub fn tx_fee(tx: &Tx, prevouts: &HashMap<Outpoint, u64>) -> u64 {
+    let tx = tx_bp_to_bitcoin(tx.clone());
+    let input_sats: u64 = tx.input.iter().map(|i| prevouts[&i.previous_output]).sum();
+    let output_sats: u64 = tx.output.iter().map(|o| o.value.to_sat()).sum();
+    input_sats - output_sats
+}
+
+/// Fee rate (sat/vB) of a transaction, given the amounts of the outputs it spends
+pub fn tx_feerate(tx: &Tx, prevouts: &HashMap<Outpoint, u64>) -> f64 {
+    tx_fee(tx, prevouts) as f64 / tx_bp_to_bitcoin(tx.clone()).vsize() as f64
+}
+
 #[derive(Clone, Debug, Eq, Hash, PartialEq)]
 pub struct WitnessInfo {
     pub derived_address: DerivedAddr,
@@ -166,12 +253,8 @@ impl AssetDestination {
             AssetDestination::Blinded(secret_seal) => BuilderSeal::Concealed(*secret_seal),
             AssetDestination::Witness(_witness_info) => {
                 let vout = vout.expect("must be provided in this case");
-                let graph_seal = if let Some(blinding) = static_blinding {
-                    GraphSeal::with_blinded_vout(vout, blinding)
-                } else {
-                    GraphSeal::new_random_vout(vout)
-                };
-                BuilderSeal::Revealed(graph_seal)
+                let blinding = static_blinding.unwrap_or_else(test_blinding);
+                BuilderSeal::Revealed(GraphSeal::with_blinded_vout(vout, blinding))
             }
         }
     }
@@ -231,6 +314,20 @@ type ConsignmentsMap = HashMap<ContractId, Transfer>;
 /// Info needed to add a tapret tweak to a terminal
 type TweakInfo = (WitnessInfo, TapretCommitment);
 
+/// Sign all the transitions of the given witness bundle, returning whether there were any
+fn sign_pfa_bundle(consignment: &mut Transfer, txid: Txid, secret_key: SecretKey) -> bool {
+    consignment.modify_bundle(txid, |witness_bundle: &mut WitnessBundle| {
+        for KnownTransition { opid, transition } in
+            witness_bundle.bundle_mut().known_transitions.iter_mut()
+        {
+            let transition_id: [u8; 32] = opid.as_ref().into_inner();
+            let msg = Message::from_digest(transition_id);
+            let signature = secret_key.sign_ecdsa(msg);
+            transition.signature = Some(Bytes64::from_array(signature.serialize_compact()).into());
+        }
+    })
+}
+
 #[derive(Debug, EnumIter, Copy, Clone, PartialEq)]
 pub enum AssetSchema {
     Nia,
@@ -247,6 +344,16 @@ impl fmt::Display for AssetSchema {
 }
 
 impl AssetSchema {
+    pub fn schema_id(&self) -> SchemaId {
+        match self {
+            Self::Nia => NIA_SCHEMA_ID,
+            Self::Uda => UDA_SCHEMA_ID,
+            Self::Cfa => CFA_SCHEMA_ID,
+            Self::Pfa => PFA_SCHEMA_ID,
+            Self::Ifa => IFA_SCHEMA_ID,
+        }
+    }
+
     fn schema(&self) -> Schema {
         match self {
             Self::Nia => NonInflatableAsset::schema(),
@@ -802,12 +909,120 @@ impl Report {
 }
 
 pub fn get_builder_seal(outpoint: Outpoint, blinding: Option<u64>) -> BuilderSeal<BlindSeal<Txid>> {
-    let blind_seal = if let Some(blinding) = blinding {
-        BlindSeal::with_blinding(outpoint.txid, outpoint.vout, blinding)
-    } else {
-        BlindSeal::new_random(outpoint.txid, outpoint.vout)
+    let blinding = blinding.unwrap_or_else(test_blinding);
+    BuilderSeal::from(BlindSeal::with_blinding(
+        outpoint.txid,
+        outpoint.vout,
+        blinding,
+    ))
+}
+
+/// Invoice for the given amount of a contract, or for its token if it's a UDA. Without an amount
+/// the invoice is open, letting the sender choose how much to pay.
+pub fn build_invoice(
+    beneficiary: XChainNet<Beneficiary>,
+    contract_id: ContractId,
+    schema_id: SchemaId,
+    amount: Option<u64>,
+) -> RgbInvoice {
+    let mut builder = RgbInvoiceBuilder::new(beneficiary)
+        .set_contract(contract_id)
+        .set_schema(schema_id);
+
+    if matches!(schema_id.into(), AssetSchema::Uda) {
+        if amount != Some(1) {
+            panic!("UDA amount must be 1");
+        }
+        builder = builder.clone().set_allocation(UDA_FIXED_INDEX, 1).unwrap();
+    } else if let Some(amount) = amount {
+        builder = builder.clone().set_amount_raw(amount);
+    }
+    builder.finish()
+}
+
+/// Set the amount chosen by the sender on an open invoice
+pub fn with_invoice_amount(mut invoice: RgbInvoice, amount: u64) -> RgbInvoice {
+    assert!(
+        invoice.assignment_state.is_none(),
+        "invoice already has an amount"
+    );
+    invoice.assignment_state = Some(InvoiceState::Amount(amount.into()));
+    invoice
+}
+
+/// Resolver treating the given witness as tentative, taking its TX from the consignment, so that
+/// a consignment can be validated before its witness gets broadcast
+pub struct OffchainResolver<'a, 'cons, const TRANSFER: bool> {
+    pub witness_id: Txid,
+    pub consignment: &'cons Consignment<TRANSFER>,
+    pub fallback: &'a AnyResolver,
+}
+
+impl<const TRANSFER: bool> ResolveWitness for OffchainResolver<'_, '_, TRANSFER> {
+    fn resolve_witness(&self, witness_id: Txid) -> Result<WitnessStatus, WitnessResolverError> {
+        if witness_id != self.witness_id {
+            return self.fallback.resolve_witness(witness_id);
+        }
+        self.consignment
+            .bundled_witnesses()
+            .find(|bw| bw.witness_id() == witness_id)
+            .and_then(|p| p.pub_witness.tx().cloned())
+            .map_or_else(
+                || self.fallback.resolve_witness(witness_id),
+                |tx| Ok(WitnessStatus::Resolved(tx, WitnessOrd::Tentative)),
+            )
+    }
+    fn check_chain_net(&self, _: ChainNet) -> Result<(), WitnessResolverError> {
+        Ok(())
+    }
+}
+
+/// Tapret commitment the given witness of the transfer puts on one of its outputs, along with the
+/// internal key of that output
+pub fn transfer_tapret_commitment(
+    consignment: &Transfer,
+    txid: Txid,
+) -> Option<(InternalPk, TapretCommitment)> {
+    let wbundle = consignment
+        .bundles
+        .iter()
+        .find(|wb| wb.witness_id() == txid)?;
+    let DbcProof::Tapret(tapret_proof) = &wbundle.anchor.dbc_proof else {
+        return None;
     };
-    BuilderSeal::from(blind_seal)
+    let protocol_id = mpc::ProtocolId::from(consignment.contract_id());
+    let message = mpc::Message::from(wbundle.bundle.bundle_id());
+    let commitment = wbundle
+        .anchor
+        .mpc_proof
+        .convolve(protocol_id, message)
+        .unwrap();
+    let nonce = tapret_proof.path_proof.nonce();
+    Some((
+        untweakedpublickey_to_internal_pk(tapret_proof.internal_pk),
+        TapretCommitment::with(commitment, nonce),
+    ))
+}
+
+/// Fungible amounts assigned by the given bundle to each output of its witness transaction,
+/// concealed seals excluded
+pub fn bundle_vout_amounts(bundle: &TransitionBundle) -> BTreeMap<u32, u64> {
+    let mut amounts = BTreeMap::new();
+    for assign in bundle
+        .known_transitions
+        .iter()
+        .flat_map(|kt| kt.transition.assignments.values())
+        .filter_map(|assigns| match assigns {
+            TypedAssigns::Fungible(assigns) => Some(assigns.iter()),
+            _ => None,
+        })
+        .flatten()
+    {
+        if let Some((seal, state)) = assign.to_revealed() {
+            *amounts.entry(seal.vout.to_u32()).or_default() += state.as_u64();
+        }
+    }
+    amounts
 }
 
 fn get_bp_indexer(indexer_url: &str) -> AnyIndexer {
@@ -887,6 +1102,22 @@ pub struct TestWallet<W: WalletProvider, D> {
     wallet_dir: PathBuf,
     instance: u8,
     network: Network,
+    resolver_url: Option<String>,
+    chain: Arc<ChainContext>,
+    sent_transfers: HashMap<Txid, SentTransfer>,
+    reject_list_policy: RejectListPolicy,
+    media_store: MediaStore,
+    /// Issuer keys of PFA contracts, used to sign their transitions on every transfer
+    pfa_keys: HashMap<ContractId, SecretKey>,
+}
+
+/// Transfer broadcast by a wallet, kept to allow replacing its witness transaction
+#[derive(Clone, Debug)]
+pub struct SentTransfer {
+    pub invoice: RgbInvoice,
+    pub sats: Option<u64>,
+    pub fee: u64,
+    pub tx: Tx,
 }
 
 pub trait TestWalletExt {
@@ -898,7 +1129,19 @@ pub trait TestWalletExt {
 
     fn sync(&mut self);
 
-    fn sign_finalize(&self, psbt: &mut Self::Psbt);
+    /// Sign with the wallet keys only
+    fn sign(&self, psbt: &mut Self::Psbt);
+
+    /// Sign with the keys of all the wallet cosigners
+    fn cosign(&self, psbt: &mut Self::Psbt);
+
+    fn finalize(&self, psbt: &mut Self::Psbt);
+
+    fn sign_finalize(&self, psbt: &mut Self::Psbt) {
+        self.sign(psbt);
+        self.cosign(psbt);
+        self.finalize(psbt);
+    }
 
     fn extract(&self, psbt: &Self::Psbt) -> Tx;
 
@@ -909,12 +1152,49 @@ pub trait TestWalletExt {
 
     fn tap_address(&mut self) -> (BpAddress, InternalPk, NormalIndex);
 
+    fn persist_tapret_tweak(&mut self, terminal: Terminal, tapret_commitment: TapretCommitment);
+
+    /// Persist the tapret tweak the given witness of the transfer puts on a wallet output, if any
+    fn persist_transfer_tapret_tweak(&mut self, consignment: &Transfer, txid: Txid);
+
     fn pay(
         &mut self,
         invoice: RgbInvoice,
         params: TransferParams,
     ) -> (Self::Psbt, Self::PsbtMeta, Transfer);
 
+    /// Build the PSBT paying the invoice, without updating the stock
+    fn construct_rgb_psbt(
+        &mut self,
+        invoice: &RgbInvoice,
+        params: TransferParams,
+    ) -> (Self::Psbt, PsbtMeta);
+
+    /// Commit to the RGB data of a PSBT built by [`Self::construct_rgb_psbt`], updating the stock,
+    /// and return the consignment for the beneficiary
+    fn complete_rgb_transfer(
+        &mut self,
+        invoice: &RgbInvoice,
+        psbt: &mut Self::Psbt,
+        meta: PsbtMeta,
+    ) -> Transfer;
+
+    /// Outpoints spent by an unsigned PSBT and the fee it pays
+    fn psbt_inputs_fee(&self, psbt: &Self::Psbt) -> (BTreeSet<Outpoint>, u64);
+
+    fn psbt_unsigned_tx(&self, psbt: &Self::Psbt) -> Transaction;
+
+    /// Estimated vsize of an input spending a wallet output, signatures of maximum length
+    fn max_input_vsize(&self) -> f64;
+
+    /// Build a PSBT spending exactly the given inputs, sending any change back to the wallet
+    fn construct_btc_psbt(
+        &mut self,
+        inputs: Vec<Outpoint>,
+        beneficiaries: Vec<(Address, u64)>,
+        fee: u64,
+    ) -> Self::Psbt;
+
     fn list_coins(&self) -> HashMap<(Address, Terminal), Vec<Coin>>;
 
     fn list_unspents(&self) -> HashMap<Outpoint, u64>;
@@ -955,7 +1235,10 @@ where
 
     pub fn get_utxo(&mut self, sats: Option<u64>) -> Outpoint {
         let address = self.get_address();
-        let txid = Txid::from_str(&fund_wallet(address.to_string(), sats, self.instance)).unwrap();
+        let txid = self
+            .chain
+            .fund_wallet(address.to_string(), sats, self.instance);
+        let txid = Txid::from_str(&txid).unwrap();
         self.sync();
         let mut vout = None;
         let utxos = self.list_unspent_outpoints();
@@ -972,6 +1255,11 @@ where
         }
     }
 
+    /// Chain context the wallet was created in
+    pub fn chain(&self) -> &Arc<ChainContext> {
+        &self.chain
+    }
+
     pub fn change_instance(&mut self, instance: u8) {
         self.instance = instance;
     }
@@ -987,11 +1275,49 @@ where
     }
 
     pub fn indexer_url(&self) -> String {
-        indexer_url(self.instance, self.network())
+        self.chain.indexer_url(self.instance, self.network())
     }
 
     pub fn get_resolver(&self) -> AnyResolver {
-        get_resolver(&self.indexer_url())
+        get_resolver(self.resolver_url.as_ref().unwrap_or(&self.indexer_url()))
+    }
+
+    /// Make the resolver go through the given proxy, wallet sync keeps using the indexer
+    pub fn use_indexer_proxy(&mut self, proxy: Option<&IndexerProxy>) {
+        self.resolver_url = proxy.map(|p| p.url());
+    }
+
+    /// Make accepting transfers honour the reject list of their contract
+    pub fn set_reject_list_policy(&mut self, policy: RejectListPolicy) {
+        self.reject_list_policy = policy;
+    }
+
+    pub fn set_media_store(&mut self, media_store: MediaStore) {
+        self.media_store = media_store;
+    }
+
+    pub fn media_store(&self) -> &MediaStore {
+        &self.media_store
+    }
+
+    /// Issuer key of the given PFA contract, if held by the wallet
+    pub fn pfa_key(&self, contract_id: ContractId) -> Option<SecretKey> {
+        self.pfa_keys.get(&contract_id).copied()
+    }
+
+    /// Issuer public key of the given PFA contract
+    pub fn pfa_pubkey(&self, contract_id: ContractId) -> CompressedPublicKey {
+        let pubkey = self
+            .contract_data(contract_id)
+            .global("pubkey")
+            .next()
+            .expect("PFA requires global state `pubkey`");
+        CompressedPublicKey::from_slice(pubkey.unwrap_bytes()).unwrap()
+    }
+
+    /// Let the wallet sign the transfers of the given PFA contract with its issuer key
+    pub fn add_pfa_key(&mut self, contract_id: ContractId, secret_key: SecretKey) {
+        self.pfa_keys.insert(contract_id, secret_key);
     }
 
     pub fn broadcast_tx(&self, tx: &Tx) {
@@ -1019,7 +1345,7 @@ where
     pub fn mine_tx(&self, txid: &Txid, resume: bool) {
         let mut attempts = 10;
         loop {
-            mine_custom(resume, self.instance, 1);
+            self.chain.mine(resume, self.instance, 1).unwrap();
             if self.get_tx_height(txid).is_some() {
                 break;
             }
@@ -1084,7 +1410,7 @@ where
         builder = asset_info.add_inflation_allowance(builder, blinding);
         builder = asset_info.add_link_right(builder, blinding);
 
-        let created_at = created_at.unwrap_or_else(|| Utc::now().timestamp());
+        let created_at = created_at.unwrap_or_else(test_now);
         let contract = builder.issue_contract_raw(created_at).unwrap();
         let resolver = self.get_resolver();
         self.import_contract(&contract, resolver);
@@ -1117,6 +1443,20 @@ where
         self.issue_with_info(asset_info, vec![outpoint.copied()], None, None)
     }
 
+    /// Issue a PFA with a new issuer key, kept by the wallet so its transfers get signed
+    /// automatically
+    pub fn issue_pfa_with_signer(
+        &mut self,
+        issued_supply: u64,
+        outpoint: Option<&Outpoint>,
+    ) -> ContractId {
+        let (secret_key, public_key) = with_test_rng(|rng| Secp256k1::new().generate_keypair(rng));
+        let pubkey = CompressedPublicKey::from_slice(&public_key.serialize()).unwrap();
+        let contract_id = self.issue_pfa(issued_supply, outpoint, pubkey);
+        self.add_pfa_key(contract_id, secret_key);
+        contract_id
+    }
+
     pub fn issue_ifa(
         &mut self,
         issued_supply: u64,
@@ -1133,10 +1473,12 @@ where
         static_blinding: Option<u64>,
     ) -> SecretSeal {
         let outpoint = outpoint.unwrap_or_else(|| self.get_utxo(None));
-        let seal = GraphSeal::from(match static_blinding {
-            Some(bli) => BlindSeal::with_blinding(outpoint.txid, outpoint.vout, bli),
-            None => BlindSeal::new_random(outpoint.txid, outpoint.vout),
-        });
+        let blinding = static_blinding.unwrap_or_else(test_blinding);
+        let seal = GraphSeal::from(BlindSeal::with_blinding(
+            outpoint.txid,
+            outpoint.vout,
+            blinding,
+        ));
         self.wallet.stock_mut().store_secret_seal(seal).unwrap();
         seal.to_secret_seal()
     }
@@ -1147,6 +1489,19 @@ where
         schema_id: SchemaId,
         amount: u64,
         invoice_type: impl Into<InvoiceType>,
+    ) -> RgbInvoice {
+        self.invoice_with(contract_id, schema_id, Some(amount), invoice_type, None)
+    }
+
+    /// Invoice with an open amount (if `amount` is `None`) and an expiry timestamp, which RGB
+    /// compares with the real time
+    pub fn invoice_with(
+        &mut self,
+        contract_id: ContractId,
+        schema_id: SchemaId,
+        amount: Option<u64>,
+        invoice_type: impl Into<InvoiceType>,
+        expiry: Option<i64>,
     ) -> RgbInvoice {
         let beneficiary = match invoice_type.into() {
             InvoiceType::Blinded(outpoint) => {
@@ -1167,22 +1522,14 @@ where
             }
         };
 
-        let mut builder = RgbInvoiceBuilder::new(XChainNet::bitcoin(self.network(), beneficiary))
-            .set_contract(contract_id)
-            .set_schema(schema_id);
-
-        if matches!(schema_id.into(), AssetSchema::Uda) {
-            if amount != 1 {
-                panic!("UDA amount must be 1");
-            }
-            builder = builder
-                .clone()
-                .set_allocation(UDA_FIXED_INDEX, amount)
-                .unwrap();
-        } else {
-            builder = builder.clone().set_amount_raw(amount);
-        }
-        builder.finish()
+        let mut invoice = build_invoice(
+            XChainNet::bitcoin(self.network(), beneficiary),
+            contract_id,
+            schema_id,
+            amount,
+        );
+        invoice.expiry = expiry;
+        invoice
     }
 
     pub fn consign_transfer(
@@ -1200,9 +1547,157 @@ where
             .unwrap()
     }
 
+    /// Unspent wallet outpoints holding state of the given contract
+    pub fn contract_outpoints(&self, contract_id: ContractId) -> BTreeSet<Outpoint> {
+        let outpoints: Vec<Outpoint> = match self.asset_schema(contract_id) {
+            AssetSchema::Uda => self
+                .contract_data_allocations(contract_id)
+                .iter()
+                .map(|a| a.seal.outpoint().unwrap())
+                .collect(),
+            _ => self
+                .contract_fungible_allocations(contract_id, false)
+                .iter()
+                .map(|a| a.seal.outpoint().unwrap())
+                .collect(),
+        };
+        let unspents = self.list_unspents();
+        outpoints
+            .into_iter()
+            .filter(|o| unspents.contains_key(o))
+            .collect()
+    }
+
+    /// Unspent wallet outpoints holding RGB state of any contract, tentative state included
+    pub fn colored_outpoints(&self) -> BTreeSet<Outpoint> {
+        let unspents = self.list_unspent_outpoints();
+        let stock = self.stock();
+        stock
+            .contracts_assigning(unspents.iter().copied())
+            .unwrap()
+            .flat_map(|contract_id| {
+                stock
+                    .contract_assignments_for(contract_id, unspents.iter().copied())
+                    .unwrap()
+                    .into_keys()
+            })
+            .map(|seal| seal.to_outpoint())
+            .collect()
+    }
+
+    /// Select inputs worth at least the given amount, biggest first, spending colored outpoints
+    /// only when explicitly included
+    pub fn select_coins(&self, target_sats: u64, colored: &ColoredCoins) -> Vec<Outpoint> {
+        let unspents = self.list_unspents();
+        let colored_outpoints = self.colored_outpoints();
+        let mut selected = vec![];
+        let mut selected_sats = 0;
+        if let ColoredCoins::Include(outpoints) = colored {
+            for outpoint in outpoints {
+                assert!(
+                    colored_outpoints.contains(outpoint),
+                    "{outpoint} is not colored"
+                );
+                selected.push(*outpoint);
+                selected_sats += unspents[outpoint];
+            }
+        }
+        let mut candidates = unspents
+            .into_iter()
+            .filter(|(o, _)| !colored_outpoints.contains(o))
+            .collect::<Vec<_>>();
+        candidates.sort_by_key(|(o, sats)| (Reverse(*sats), *o));
+        for (outpoint, sats) in candidates {
+            if selected_sats >= target_sats {
+                break;
+            }
+            selected.push(outpoint);
+            selected_sats += sats;
+        }
+        assert!(
+            selected_sats >= target_sats,
+            "not enough uncolored funds ({selected_sats} < {target_sats})"
+        );
+        selected
+    }
+
+    /// Send bitcoins to the given address, never spending colored outpoints unless explicitly
+    /// included
+    pub fn send_btc(
+        &mut self,
+        address: &Address,
+        sats: u64,
+        fee: Option<u64>,
+        colored: ColoredCoins,
+    ) -> Tx {
+        self.sync();
+        let fee = fee.unwrap_or(DEFAULT_FEE_ABS);
+        let inputs = self.select_coins(sats + fee, &colored);
+        let mut psbt = self.construct_btc_psbt(inputs.clone(), vec![(address.clone(), sats)], fee);
+        let tx = self.sign_finalize_extract(&mut psbt);
+        let spent = tx_bp_to_bitcoin(tx.clone())
+            .input
+            .iter()
+            .map(|txin| txin.previous_output)
+            .collect::<BTreeSet<_>>();
+        assert_eq!(
+            spent,
+            inputs.into_iter().collect(),
+            "TX doesn't spend the selected coins"
+        );
+        self.broadcast_tx(&tx);
+        tx
+    }
+
+    /// Estimated vsize of the transaction the wallet builds to pay the given invoice with the
+    /// given params, including the inputs added by coin selection
+    pub fn estimate_rgb_tx_vsize(&mut self, invoice: &RgbInvoice, params: TransferParams) -> f64 {
+        // building the PSBT doesn't update the stock
+        let (psbt, _) = self.construct_rgb_psbt(invoice, params);
+        let tx = self.psbt_unsigned_tx(&psbt);
+        let mut vsize = TX_OVERHEAD_VSIZE + tx.input.len() as f64 * self.max_input_vsize();
+        for output in &tx.output {
+            // the opret commitment is pushed only when committing
+            vsize += if output.script_pubkey.is_op_return() {
+                OPRET_OUTPUT_VSIZE
+            } else {
+                output_vsize(&output.script_pubkey)
+            };
+        }
+        if self.close_method() == CloseMethod::OpretFirst
+            && !tx.output.iter().any(|o| o.script_pubkey.is_op_return())
+        {
+            vsize += OPRET_OUTPUT_VSIZE;
+        }
+        // vsize is the weight divided by 4, rounded up
+        vsize.ceil()
+    }
+
+    /// Absolute fee to pay for the given invoice, sending the given sats to a witness beneficiary
+    pub fn invoice_fee(&mut self, invoice: &RgbInvoice, sats: u64, fee: TxFee) -> u64 {
+        match fee {
+            TxFee::Abs(fee) => fee,
+            TxFee::Rate(feerate) => {
+                // a higher fee may need more inputs, raise it until they cover it
+                let mut fee = 0;
+                loop {
+                    let vsize =
+                        self.estimate_rgb_tx_vsize(invoice, TransferParams::with(fee, sats));
+                    let new_fee = (vsize * feerate).ceil() as u64;
+                    if new_fee <= fee {
+                        return fee;
+                    }
+                    fee = new_fee;
+                }
+            }
+        }
+    }
+
+    /// Pay the given invoice, with the `amount` chosen by the sender if the invoice is open
     pub fn pay_invoice(
         &mut self,
         invoice: RgbInvoice,
+        amount: Option<u64>,
         sats: Option<u64>,
         fee: Option<u64>,
     ) -> (
@@ -1210,8 +1705,27 @@ where
         <Self as TestWalletExt>::PsbtMeta,
         Transfer,
     ) {
-        let fee = fee.unwrap_or(DEFAULT_FEE_ABS);
+        let fee = fee.map(TxFee::Abs).unwrap_or_default();
+        self.pay_invoice_with_fee(invoice, amount, sats, fee)
+    }
+
+    pub fn pay_invoice_with_fee(
+        &mut self,
+        invoice: RgbInvoice,
+        amount: Option<u64>,
+        sats: Option<u64>,
+        fee: TxFee,
+    ) -> (
+        <Self as TestWalletExt>::Psbt,
+        <Self as TestWalletExt>::PsbtMeta,
+        Transfer,
+    ) {
+        let invoice = match amount {
+            Some(amount) => with_invoice_amount(invoice, amount),
+            None => invoice,
+        };
         let sats = sats.unwrap_or(2000);
+        let fee = self.invoice_fee(&invoice, sats, fee);
         let params = TransferParams::with(fee, sats);
         self.pay(invoice, params)
     }
@@ -1229,10 +1743,93 @@ where
         <Self as TestWalletExt>::Psbt,
         <Self as TestWalletExt>::PsbtMeta,
     ) {
+        let fee = fee.map(TxFee::Abs).unwrap_or_default();
+        self.pay_full_with_fee(invoice, sats, fee, broadcast, report)
+    }
+
+    /// Replace the unconfirmed witness transaction of a transfer with one paying the given fee
+    /// rate and spending the same inputs, returning the new consignment for the recipient
+    pub fn bump_fee(&mut self, txid: Txid, new_feerate: f64) -> (Transfer, Tx) {
+        assert!(
+            self.get_tx_height(&txid).is_none(),
+            "cannot replace mined TX {txid}"
+        );
+        let sent = self
+            .sent_transfers
+            .get(&txid)
+            .cloned()
+            .unwrap_or_else(|| panic!("TX {txid} is not a transfer sent by this wallet"));
+        let sent_tx = tx_bp_to_bitcoin(sent.tx.clone());
+        let vsize = sent_tx.vsize() as u64;
+        let fee = (vsize as f64 * new_feerate).ceil() as u64;
+        // the replacement also pays for its own relay, at the minimum relay fee rate
+        assert!(
+            fee >= sent.fee + vsize,
+            "fee rate {new_feerate} is too low to replace TX {txid}"
+        );
         self.sync();
 
+        // the replaced transaction inputs still hold the state to transfer, check the new PSBT
+        // spends exactly them before the stock and the chain see the replacement
+        let inputs = sent_tx
+            .input
+            .iter()
+            .map(|txin| txin.previous_output)
+            .collect::<BTreeSet<_>>();
+        let params = TransferParams::with(fee, sent.sats.unwrap_or(2000));
+        let (mut psbt, meta) = self.construct_rgb_psbt(&sent.invoice, params);
+        let (psbt_inputs, psbt_fee) = self.psbt_inputs_fee(&psbt);
+        assert_eq!(psbt_inputs, inputs, "replacement spends different inputs");
+        assert_eq!(psbt_fee, fee);
+
+        let consignment = self.complete_rgb_transfer(&sent.invoice, &mut psbt, meta);
+        let tx = self.sign_finalize_extract(&mut psbt);
+        println!(
+            "replacement txid: {}, consignment: {}",
+            tx.txid(),
+            consignment.consignment_id()
+        );
+        self.broadcast_tx(&tx);
+        self.sent_transfers.remove(&txid);
+        self.sent_transfers.insert(
+            txid_bp_to_bitcoin(tx.txid()),
+            SentTransfer {
+                fee,
+                tx: tx.clone(),
+                ..sent
+            },
+        );
+        // the replaced witness is not known to the indexer anymore
+        self.sync_and_update_witnesses(None);
+        (consignment, tx)
+    }
+
+    /// Transfers broadcast by this wallet whose witness hasn't been replaced
+    pub fn sent_transfers(&self) -> &HashMap<Txid, SentTransfer> {
+        &self.sent_transfers
+    }
+
+    pub fn pay_full_with_fee(
+        &mut self,
+        invoice: RgbInvoice,
+        sats: Option<u64>,
+        fee: TxFee,
+        broadcast: bool,
+        report: Option<&Report>,
+    ) -> (
+        Transfer,
+        Tx,
+        <Self as TestWalletExt>::Psbt,
+        <Self as TestWalletExt>::PsbtMeta,
+    ) {
+        self.sync();
+
+        let fee = self.invoice_fee(&invoice, sats.unwrap_or(2000), fee);
+        let sent_invoice = invoice.clone();
+
         let pay_start = Instant::now();
-        let (mut psbt, psbt_meta, consignment) = self.pay_invoice(invoice, sats, fee);
+        let (mut psbt, psbt_meta, consignment) =
+            self.pay_invoice_with_fee(invoice, None, sats, TxFee::Abs(fee));
         let pay_duration = pay_start.elapsed();
         if let Some(report) = report {
             report.write_duration(pay_duration);
@@ -1272,6 +1869,15 @@ where
 
         if broadcast {
             self.broadcast_tx(&tx);
+            self.sent_transfers.insert(
+                txid_bp_to_bitcoin(tx.txid()),
+                SentTransfer {
+                    invoice: sent_invoice,
+                    sats,
+                    fee,
+                    tx: tx.clone(),
+                },
+            );
         }
 
         (consignment, tx, psbt, psbt_meta)
@@ -1288,28 +1894,52 @@ where
         report: Option<&Report>,
         resolver: &impl ResolveWitness,
     ) -> Status {
+        let validation_status = self
+            .try_accept_transfer_custom(consignment, report, resolver)
+            .unwrap();
+        assert_eq!(validation_status.validity(), Validity::Valid);
+        validation_status
+    }
+
+    /// Validate the consignment and, only if valid, accept it into the stock
+    pub fn try_accept_transfer_custom(
+        &mut self,
+        consignment: Transfer,
+        report: Option<&Report>,
+        resolver: &impl ResolveWitness,
+    ) -> Result<Status, ValidationError> {
         self.sync();
         let validate_start = Instant::now();
-        let validated_consignment = consignment
-            .clone()
-            .validate(
-                &resolver,
-                &ValidationConfig {
-                    chain_net: self.chain_net(),
-                    trusted_typesystem: AssetSchema::from(consignment.schema_id()).types(),
-                    build_opouts_dag: true,
-                    ..Default::default()
-                },
-            )
-            .unwrap();
+        let validated_consignment = consignment.clone().validate(
+            &resolver,
+            &ValidationConfig {
+                chain_net: self.chain_net(),
+                trusted_typesystem: AssetSchema::from(consignment.schema_id()).types(),
+                build_opouts_dag: true,
+                ..Default::default()
+            },
+        )?;
         let validate_duration = validate_start.elapsed();
         if let Some(report) = report {
             report.write_duration(validate_duration);
         }
 
-        let validation_status = validated_consignment.clone().into_validation_status();
-        let validity = validation_status.validity();
-        assert_eq!(validity, Validity::Valid);
+        let mut validation_status = validated_consignment.clone().into_validation_status();
+        if validation_status.validity() != Validity::Valid {
+            return Ok(validation_status);
+        }
+        if self.reject_list_policy != RejectListPolicy::Ignore
+            && let Some(issue) = check_reject_list(&consignment)
+        {
+            if self.reject_list_policy == RejectListPolicy::Refuse {
+                return Err(ValidationError::InvalidConsignment(Failure::Custom(
+                    issue.to_string(),
+                )));
+            }
+            validation_status
+                .warnings
+                .push(Warning::Custom(issue.to_string()));
+        }
         let accept_start = Instant::now();
         self.wallet
             .stock_mut()
@@ -1319,21 +1949,23 @@ where
         if let Some(report) = report {
             report.write_duration(accept_duration);
         }
-        validation_status
+        Ok(validation_status)
     }
 
     pub fn add_tapret_tweak(&mut self, terminal: Terminal, tapret_commitment: TapretCommitment) {
         self.wallet
             .wallet_mut()
-            .add_tapret_tweak(terminal, tapret_commitment)
+            .add_tapret_tweak(terminal, tapret_commitment.clone())
             .unwrap();
+        self.persist_tapret_tweak(terminal, tapret_commitment);
     }
 
     pub fn try_add_tapret_tweak(&mut self, consignment: Transfer, txid: &Txid) {
         self.wallet
             .wallet_mut()
-            .try_add_tapret_tweak(consignment, txid)
+            .try_add_tapret_tweak(consignment.clone(), txid)
             .unwrap();
+        self.persist_transfer_tapret_tweak(&consignment, *txid);
     }
 
     pub fn contract_data(
@@ -1408,6 +2040,11 @@ where
         self.wallet.history(contract_id).unwrap()
     }
 
+    /// Witness ordering currently stored in the stock for the given witness of a contract
+    pub fn stock_witness_ord(&self, contract_id: ContractId, txid: Txid) -> Option<WitnessOrd> {
+        self.contract_data(contract_id).state.witness_ord(txid)
+    }
+
     pub fn list_contracts(&self) -> Vec<ContractInfo> {
         self.wallet.stock().contracts().unwrap().collect()
     }
@@ -1566,8 +2203,9 @@ where
         TestWallet<W2, D2>: TestWalletExt,
         <TestWallet<W2, D2> as TestWalletExt>::Psbt: Serialize,
     {
+        let invoice_type = invoice_type.into();
         let schema_id = self.schema_id(contract_id);
-        let invoice = recv_wlt.invoice(contract_id, schema_id, amount, invoice_type.into());
+        let invoice = recv_wlt.invoice(contract_id, schema_id, amount, invoice_type);
         self.send_to_invoice(recv_wlt, invoice, Some(sats), None, report)
     }
 
@@ -1583,13 +2221,56 @@ where
         TestWallet<W2, D2>: TestWalletExt,
         <TestWallet<W2, D2> as TestWalletExt>::Psbt: Serialize,
     {
-        let (consignment, tx, _, _) = self.pay_full(invoice, sats, fee, true, report);
-        self.mine_tx(&txid_bp_to_bitcoin(tx.txid()), false);
+        let (mut consignment, tx, _, _) = self.pay_full(invoice, sats, fee, true, report);
+        let txid = txid_bp_to_bitcoin(tx.txid());
+        self.sign_pfa_transfer(&mut consignment, txid);
+        self.mine_tx(&txid, false);
         recv_wlt.accept_transfer(consignment.clone(), report);
         self.sync();
         (consignment, tx)
     }
 
+    /// Sign the PFA transitions of the given witness, both in the consignment and in the other
+    /// PFA contracts moved by the witness, updating the stock with the signed transitions
+    pub fn sign_pfa_transfer(&mut self, consignment: &mut Transfer, txid: Txid) {
+        let contract_id = consignment.contract_id();
+        if let Some(secret_key) = self.pfa_key(contract_id) {
+            assert!(sign_pfa_bundle(consignment, txid, secret_key));
+            self.accept_transfer(consignment.clone(), None);
+        }
+        for contract in self.list_contracts() {
+            let Some(secret_key) = self.pfa_key(contract.id) else {
+                continue;
+            };
+            if contract.id == contract_id {
+                continue;
+            }
+            // the wallet may not know the witness outputs yet, look for them in the whole stock
+            let outputs = self
+                .contract_data(contract.id)
+                .fungible("assetOwner", AllocationFilter::Stock.filter_for(self))
+                .unwrap()
+                .filter(|fa| fa.seal.txid() == Some(txid))
+                .map(|fa| ExplicitSeal::new(fa.seal.outpoint().unwrap()))
+                .collect::<Vec<OutputSeal>>();
+            if outputs.is_empty() {
+                continue;
+            }
+            let mut extra_cons =
+                self.consign_transfer(contract.id, outputs, [], [], Some(txid_bitcoin_to_bp(txid)));
+            assert!(sign_pfa_bundle(&mut extra_cons, txid, secret_key));
+            self.accept_transfer(extra_cons, None);
+        }
+    }
+
+    /// Amount of the IFA inflation rights owned by the wallet
+    pub fn inflation_allowance(&self, contract_id: ContractId) -> u64 {
+        self.contract_wrapper::<InflatableFungibleAsset>(contract_id)
+            .inflation_allocations(AllocationFilter::Wallet.filter_for(self))
+            .map(|oa| oa.state.value())
+            .sum()
+    }
+
     pub fn send_ifa<W2: WalletProvider, D2>(
         &mut self,
         recv_wlt: &mut TestWallet<W2, D2>,
@@ -1706,11 +2387,20 @@ where
     }
 
     pub fn update_witnesses(&mut self, after_height: u32, force_witnesses: Vec<Txid>) {
+        self.try_update_witnesses(after_height, force_witnesses)
+            .unwrap();
+    }
+
+    pub fn try_update_witnesses(
+        &mut self,
+        after_height: u32,
+        force_witnesses: Vec<Txid>,
+    ) -> Result<UpdateRes, String> {
         let resolver = self.get_resolver();
         self.wallet
             .stock_mut()
             .update_witnesses(resolver, after_height, force_witnesses)
-            .unwrap();
+            .map_err(|e| e.to_string())
     }
 
     pub fn get_outpoint_unsafe_history(
@@ -1,8 +1,6 @@
 //! Tests in this module are about the integrity of some utility methods in this test suite.
 //! RGB functionality tests shouldn't be added here.
 
-pub mod utils;
-
 use utils::*;
 
 #[cfg(not(feature = "altered"))]
@@ -349,3 +347,373 @@ fn flexible_tapret_no_change(#[case] recv_descriptor: DescriptorType) {
 
     wlt_2.check_allocations(contract_id, schema_id, vec![issued_amt], false);
 }
+
+// anyone can spend it, the ID (1 to 16) makes it unique
+#[cfg(not(feature = "altered"))]
+fn sim_witness_script(id: u8) -> ScriptBuf {
+    ScriptBuf::from_bytes(vec![0x50 + id, 0x75, 0x51])
+}
+
+#[cfg(not(feature = "altered"))]
+fn sim_script(id: u8) -> ScriptBuf {
+    ScriptBuf::new_p2wsh(&sim_witness_script(id).wscript_hash())
+}
+
+#[cfg(not(feature = "altered"))]
+fn sim_spend(prev: Outpoint, prev_id: u8, outputs: Vec<(ScriptBuf, u64)>) -> Transaction {
+    Transaction {
+        version: TxVersion::TWO,
+        lock_time: BtcLockTime::ZERO,
+        input: vec![TxIn {
+            previous_output: prev,
+            script_sig: ScriptBuf::new(),
+            sequence: Sequence::ENABLE_RBF_NO_LOCKTIME,
+            witness: Witness::from_slice(&[sim_witness_script(prev_id).as_bytes()]),
+        }],
+        output: outputs
+            .into_iter()
+            .map(|(script_pubkey, sats)| TxOut {
+                value: BtcAmount::from_sat(sats),
+                script_pubkey,
+            })
+            .collect(),
+    }
+}
+
+#[cfg(not(feature = "altered"))]
+#[test]
+fn sim_chain_mempool_and_rbf() {
+    let chain = SimChain::new();
+    let script = sim_script(1);
+    let other_script = sim_script(2);
+
+    assert_eq!(chain.height_of(INSTANCE_1).unwrap(), SIM_INITIAL_BLOCKS);
+    let txid = chain
+        .send_to_script(INSTANCE_1, script.clone(), 10_000)
+        .unwrap();
+    assert_eq!(chain.mempool(INSTANCE_1), vec![txid]);
+    // instance 1 is not connected to other nodes
+    assert!(chain.mempool(INSTANCE_2).is_empty());
+    chain.mine_blocks(INSTANCE_1, 1).unwrap();
+    let (_, status) = chain.get_tx(INSTANCE_1, txid).unwrap();
+    assert_eq!(status.unwrap().height, SIM_INITIAL_BLOCKS + 1);
+    let utxo = Outpoint::new(txid, 0);
+    assert_eq!(chain.unspent(INSTANCE_1, &script).len(), 1);
+
+    // inputs must satisfy the script they spend
+    let mut unsigned = sim_spend(utxo, 1, vec![(other_script.clone(), 9_500)]);
+    unsigned.input[0].witness = Witness::new();
+    assert_eq!(
+        chain.broadcast(INSTANCE_1, unsigned.clone()),
+        Err(SimChainError::InvalidScript(unsigned.compute_txid(), 0))
+    );
+    let tx_1 = sim_spend(utxo, 1, vec![(other_script.clone(), 9_500)]);
+    let txid_1 = chain.broadcast(INSTANCE_1, tx_1.clone()).unwrap();
+    assert_eq!(
+        chain.broadcast(INSTANCE_1, tx_1),
+        Err(SimChainError::AlreadyKnown(txid_1))
+    );
+    // a replacement must pay a higher fee
+    let tx_2 = sim_spend(utxo, 1, vec![(other_script.clone(), 9_600)]);
+    let txid_2 = tx_2.compute_txid();
+    assert_eq!(
+        chain.broadcast(INSTANCE_1, tx_2),
+        Err(SimChainError::InsufficientFee(txid_2))
+    );
+    // descendants of the replaced transaction get evicted too
+    let child = sim_spend(Outpoint::new(txid_1, 0), 2, vec![(script.clone(), 9_000)]);
+    chain.broadcast(INSTANCE_1, child).unwrap();
+    let tx_3 = sim_spend(utxo, 1, vec![(other_script.clone(), 8_000)]);
+    let txid_3 = chain.broadcast(INSTANCE_1, tx_3).unwrap();
+    assert_eq!(chain.mempool(INSTANCE_1), vec![txid_3]);
+    chain.mine_blocks(INSTANCE_1, 1).unwrap();
+    assert!(chain.unspent(INSTANCE_1, &script).is_empty());
+    assert_eq!(chain.spender(INSTANCE_1, utxo).unwrap().0, txid_3);
+    assert!(matches!(
+        chain.broadcast(INSTANCE_1, sim_spend(utxo, 1, vec![(script, 1_000)])),
+        Err(SimChainError::Conflict(_))
+    ));
+}
+
+#[cfg(not(feature = "altered"))]
+#[test]
+fn sim_chain_reorg() {
+    let chain = SimChain::new();
+    let script = sim_script(3);
+
+    // instance 3 syncs from instance 2 while connected
+    assert_eq!(
+        chain.height_of(INSTANCE_2).unwrap(),
+        chain.height_of(INSTANCE_3).unwrap()
+    );
+    let txid = chain
+        .send_to_script(INSTANCE_2, script.clone(), 50_000)
+        .unwrap();
+    chain.mine_blocks(INSTANCE_2, 1).unwrap();
+    assert!(chain.get_tx(INSTANCE_3, txid).unwrap().1.is_some());
+    let fork_height = chain.height_of(INSTANCE_2).unwrap();
+
+    chain.disconnect(INSTANCE_2, INSTANCE_3);
+    let spend = sim_spend(Outpoint::new(txid, 0), 3, vec![(script.clone(), 49_000)]);
+    let spend_txid = chain.broadcast(INSTANCE_2, spend.clone()).unwrap();
+    chain.mine_blocks(INSTANCE_2, 1).unwrap();
+    assert!(chain.get_tx(INSTANCE_3, spend_txid).is_none());
+    chain.mine_blocks(INSTANCE_3, 3).unwrap();
+    assert_ne!(
+        chain.block_hash(INSTANCE_2, fork_height + 1),
+        chain.block_hash(INSTANCE_3, fork_height + 1)
+    );
+
+    // the longest branch wins and disconnected transactions go back to the mempool
+    chain.connect(INSTANCE_2, INSTANCE_3).unwrap();
+    assert_eq!(chain.height_of(INSTANCE_2).unwrap(), fork_height + 3);
+    assert_eq!(
+        chain.block_hash(INSTANCE_2, fork_height + 3),
+        chain.block_hash(INSTANCE_3, fork_height + 3)
+    );
+    assert_eq!(chain.get_tx(INSTANCE_2, spend_txid).unwrap().1, None);
+    assert_eq!(chain.mempool(INSTANCE_3), vec![spend_txid]);
+    chain.mine_blocks(INSTANCE_3, 1).unwrap();
+    let status = chain.get_tx(INSTANCE_2, spend_txid).unwrap().1.unwrap();
+    assert_eq!(status.height, fork_height + 4);
+}
+
+#[cfg(not(feature = "altered"))]
+#[test]
+fn sim_chain_branches() {
+    let chain = Arc::new(SimChain::new());
+    let script = sim_script(4);
+
+    let txid = chain
+        .send_to_script(INSTANCE_2, script.clone(), 50_000)
+        .unwrap();
+    chain.mine_blocks(INSTANCE_2, 1).unwrap();
+    let spend = sim_spend(Outpoint::new(txid, 0), 4, vec![(script, 49_000)]);
+    let spend_txid = chain.broadcast(INSTANCE_2, spend).unwrap();
+    chain.mine_blocks(INSTANCE_2, 1).unwrap();
+
+    // competing branches forked before the funding transaction
+    let mut reorg = ReorgSim::with_chain(chain.clone(), INSTANCE_2, 2);
+    let fork_height = reorg.fork_height();
+    let branch_a = reorg.branch(vec![vec![txid], vec![], vec![spend_txid]]);
+    let branch_b = reorg.branch(vec![vec![txid, spend_txid]]);
+    let branch_c = reorg.empty_branch(5);
+    let tx_height = |txid| chain.get_tx(INSTANCE_2, txid).unwrap().1.unwrap().height;
+
+    for _ in 0..2 {
+        reorg.activate(branch_a);
+        assert_eq!(chain.height_of(INSTANCE_2).unwrap(), fork_height + 3);
+        assert_eq!(tx_height(txid), fork_height + 1);
+        assert_eq!(tx_height(spend_txid), fork_height + 3);
+        // connected nodes follow the active branch
+        assert_eq!(
+            chain.block_hash(INSTANCE_2, fork_height + 3),
+            chain.block_hash(INSTANCE_3, fork_height + 3)
+        );
+
+        // shorter branches get activated too
+        reorg.activate(branch_b);
+        assert_eq!(chain.height_of(INSTANCE_3).unwrap(), fork_height + 1);
+        assert_eq!(tx_height(spend_txid), fork_height + 1);
+
+        // transactions of the other branches are unknown
+        reorg.activate(branch_c);
+        assert_eq!(chain.height_of(INSTANCE_2).unwrap(), fork_height + 5);
+        assert!(chain.get_tx(INSTANCE_2, txid).is_none());
+        assert!(chain.get_tx(INSTANCE_2, spend_txid).is_none());
+        assert!(chain.mempool(INSTANCE_3).is_empty());
+    }
+
+    reorg.move_txs(&[txid, spend_txid], branch_c, 4);
+    assert_eq!(reorg.tx_height(branch_a, txid), None);
+    assert_eq!(reorg.tx_height(branch_c, spend_txid), Some(fork_height + 5));
+    reorg.activate(branch_c);
+    assert_eq!(tx_height(spend_txid), fork_height + 5);
+    reorg.activate(branch_a);
+    assert_eq!(chain.height_of(INSTANCE_2).unwrap(), fork_height + 3);
+    assert!(chain.get_tx(INSTANCE_2, txid).is_none());
+
+    assert_eq!(
+        chain.fork(INSTANCE_2, u32::MAX),
+        Err(SimChainError::InvalidHeight(u32::MAX))
+    );
+    assert_eq!(
+        chain.reorg_to(INSTANCE_2, 42),
+        Err(SimChainError::UnknownBranch(42))
+    );
+}
+
+#[cfg(not(feature = "altered"))]
+#[test]
+fn sim_indexers() {
+    let chain = Arc::new(SimChain::new());
+    let server = IndexerServer::start(chain.clone(), INSTANCE_1);
+    let esplora = EsploraClient::from_builder(EsploraBuilder::new(&server.esplora_url()));
+    let electrum = ElectrumClient::new(&server.electrum_url()).unwrap();
+    let script = sim_script(4);
+
+    assert_eq!(esplora.get_height().unwrap(), SIM_INITIAL_BLOCKS);
+    assert_eq!(
+        esplora.get_block_hash(0).unwrap(),
+        genesis_block(Network::Regtest).block_hash()
+    );
+    assert_eq!(
+        electrum.block_header(0).unwrap(),
+        genesis_block(Network::Regtest).header
+    );
+
+    let txid = chain
+        .send_to_script(INSTANCE_1, script.clone(), 20_000)
+        .unwrap();
+    assert!(!esplora.get_tx_status(&txid).unwrap().confirmed);
+    assert_eq!(electrum.script_get_history(&script).unwrap()[0].height, 0);
+
+    chain.mine_blocks(INSTANCE_1, 1).unwrap();
+    let height = SIM_INITIAL_BLOCKS + 1;
+    let status = esplora.get_tx_status(&txid).unwrap();
+    assert_eq!(status.block_height, Some(height));
+    assert_eq!(esplora.scripthash_txs(&script, None).unwrap()[0].txid, txid);
+    let history = electrum.script_get_history(&script).unwrap();
+    assert_eq!(history[0].tx_hash, txid);
+    assert_eq!(history[0].height, height as i32);
+
+    // merkle proofs must match the header served for the block
+    let merkle = electrum
+        .transaction_get_merkle(&txid, height as usize)
+        .unwrap();
+    assert_eq!(merkle.block_height, height as usize);
+    assert_eq!(merkle.pos, 1);
+    let header = electrum.block_header(height as usize).unwrap();
+    assert_eq!(
+        header.merkle_root,
+        chain.block(INSTANCE_1, height).unwrap().header.merkle_root
+    );
+
+    // broadcast through esplora, read back through electrum
+    let spend = sim_spend(Outpoint::new(txid, 0), 4, vec![(script.clone(), 19_000)]);
+    esplora.broadcast(&spend).unwrap();
+    assert_eq!(
+        electrum.transaction_get(&spend.compute_txid()).unwrap(),
+        spend
+    );
+    // double spends are refused like bitcoind would do
+    let double_spend = sim_spend(Outpoint::new(txid, 0), 4, vec![(script, 19_500)]);
+    assert!(electrum.transaction_broadcast(&double_spend).is_err());
+}
+
+#[cfg(not(feature = "altered"))]
+#[test]
+fn sim_chain_contexts_are_isolated() {
+    initialize();
+    let ctx_a = ChainContext::simulated();
+    let ctx_b = ChainContext::simulated();
+    assert!(ctx_a.is_isolated());
+    let url = ctx_a.indexer_url(INSTANCE_1, Network::Regtest);
+    assert_ne!(url, ctx_b.indexer_url(INSTANCE_1, Network::Regtest));
+
+    let height_b = ctx_b.height(INSTANCE_1);
+    ctx_a.mine(false, INSTANCE_1, 3).unwrap();
+    assert_eq!(ctx_a.height(INSTANCE_1), height_b + 3);
+    assert_eq!(ctx_b.height(INSTANCE_1), height_b);
+
+    // stopping mining on a chain doesn't affect the others
+    ctx_a.stop_mining();
+    ctx_b.mine(false, INSTANCE_1, 1).unwrap();
+    assert_eq!(ctx_b.height(INSTANCE_1), height_b + 1);
+    ctx_a.mine(true, INSTANCE_1, 1).unwrap();
+    assert_eq!(ctx_a.height(INSTANCE_1), height_b + 4);
+
+    // nobody else can resume mining on an isolated chain
+    ctx_a.stop_mining();
+    let err = ctx_a.mine(false, INSTANCE_1, 1).unwrap_err();
+    assert!(matches!(
+        err,
+        MiningError::NeverResumed { stop_mining_at } if stop_mining_at.len() == 1
+            && stop_mining_at[0].starts_with(file!())
+    ));
+    assert_eq!(ctx_a.height(INSTANCE_1), height_b + 4);
+}
+
+#[cfg(not(feature = "altered"))]
+#[test]
+fn seeded_scenario_is_reproducible() {
+    initialize();
+    let seed = rand::random();
+
+    // each run gets a new thread, named the same, so it starts from a new simulated chain
+    let run = || {
+        std::thread::Builder::new()
+            .name(s!("seeded_scenario"))
+            .spawn(move || {
+                with_test_seed(seed, || {
+                    let mut wlt_1 = BpTestWallet::with_descriptor(&DescriptorType::Wpkh);
+                    let wlt_2 = BpTestWallet::with_descriptor(&DescriptorType::Tr);
+                    let keys = [
+                        wlt_1.descriptor().to_string(),
+                        wlt_2.descriptor().to_string(),
+                    ];
+
+                    let utxo = wlt_1.get_utxo(None);
+                    let inflation_utxo = wlt_1.get_utxo(None);
+                    let contract_id =
+                        wlt_1.issue_ifa(600, Some(&utxo), vec![(inflation_utxo, 300)]);
+                    // transfers paid by rgb-api blind their change seals with its own RNG, so
+                    // check an inflation, whose seals are all blinded by the test context
+                    let tx = wlt_1.inflate_ifa(contract_id, vec![inflation_utxo], vec![100]);
+                    let txids = [
+                        utxo.txid,
+                        inflation_utxo.txid,
+                        txid_bp_to_bitcoin(tx.txid()),
+                    ];
+                    (keys, contract_id, txids)
+                })
+            })
+            .unwrap()
+            .join()
+            .unwrap()
+    };
+
+    assert_eq!(run(), run());
+}
+
+#[cfg(not(feature = "altered"))]
+#[test]
+fn transfer_matrix_coverage() {
+    let params = TransferMatrixParams::new();
+    assert!(
+        params
+            .wallet_kinds
+            .iter()
+            .all(|k| k.backend.supports(&k.descriptor_type))
+    );
+    assert!(WalletBackend::iter().all(|b| params.wallet_kinds.iter().any(|k| k.backend == b)));
+    assert!(
+        DescriptorType::iter()
+            .all(|dt| params.wallet_kinds.iter().any(|k| k.descriptor_type == dt))
+    );
+
+    // every pair of values of two different parameters is covered
+    let cases = transfer_matrix();
+    let sizes = params.sizes();
+    let covered = cases
+        .iter()
+        .map(|c| params.indexes(c))
+        .flat_map(|idx| param_pairs(&idx).collect::<Vec<_>>())
+        .collect::<HashSet<_>>();
+    let pairs = (0..sizes.len())
+        .flat_map(|i| ((i + 1)..sizes.len()).map(move |j| sizes[i] * sizes[j]))
+        .sum::<usize>();
+    assert_eq!(covered.len(), pairs);
+    assert!(cases.len() < sizes.iter().product());
+
+    let names = cases.iter().map(|c| c.to_string()).collect::<HashSet<_>>();
+    assert_eq!(names.len(), cases.len());
+
+    // the shards split the cases
+    let sharded = (0..TRANSFER_SHARDS)
+        .flat_map(transfer_shard)
+        .map(|c| c.to_string())
+        .collect::<Vec<_>>();
+    assert_eq!(sharded.len(), cases.len());
+    assert_eq!(sharded.into_iter().collect::<HashSet<_>>(), names);
+}
@@ -1,96 +1,5 @@
-pub mod utils;
-
 use utils::*;
 
-#[derive(Clone)]
-enum MockResolvePubWitness {
-    Success(WitnessStatus),
-    Error(WitnessResolverError),
-}
-
-#[derive(Clone)]
-struct MockResolver {
-    pub_witnesses: HashMap<Txid, MockResolvePubWitness>,
-    check_chain_net_err: Option<WitnessResolverError>,
-}
-
-impl ResolveWitness for MockResolver {
-    fn resolve_witness(&self, witness_id: Txid) -> Result<WitnessStatus, WitnessResolverError> {
-        if let Some(res) = self.pub_witnesses.get(&witness_id) {
-            match res {
-                MockResolvePubWitness::Success(witness_status) => Ok(witness_status.clone()),
-                MockResolvePubWitness::Error(err) => Err(err.clone()),
-            }
-        } else {
-            Ok(WitnessStatus::Unresolved)
-        }
-    }
-
-    fn check_chain_net(&self, _: ChainNet) -> Result<(), WitnessResolverError> {
-        self.check_chain_net_err.clone().map_or(Ok(()), Err)
-    }
-}
-impl MockResolver {
-    pub fn with_new_transaction(&self, witness: Transaction) -> Self {
-        let mut resolver = self.clone();
-        let witness_id = witness.compute_txid();
-        resolver.pub_witnesses.insert(
-            witness_id,
-            MockResolvePubWitness::Success(WitnessStatus::Resolved(witness, WitnessOrd::Tentative)),
-        );
-        resolver
-    }
-}
-
-#[derive(Debug, EnumIter, Copy, Clone, PartialEq)]
-enum Scenario {
-    A,
-    B,
-    C,
-    D,
-}
-
-impl fmt::Display for Scenario {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "{self:?}")
-    }
-}
-
-impl Scenario {
-    fn txs_folder(&self) -> String {
-        format!("tests/fixtures/txs_{self}/")
-    }
-
-    fn resolver(&self) -> MockResolver {
-        let mut txs = map![];
-        for entry in std::fs::read_dir(self.txs_folder()).unwrap() {
-            let file = std::fs::File::open(entry.unwrap().path()).unwrap();
-            let tx: Tx = serde_json::from_reader(file).unwrap();
-            let tx = tx_bp_to_bitcoin(tx);
-            txs.insert(tx.compute_txid(), tx);
-        }
-        MockResolver {
-            pub_witnesses: txs
-                .into_iter()
-                .map(|(txid, tx)| {
-                    (
-                        txid,
-                        MockResolvePubWitness::Success(WitnessStatus::Resolved(
-                            tx,
-                            WitnessOrd::Mined(
-                                // TODO: store actual values instead of the hardcoded WitnessPos
-                                WitnessPos::bitcoin(NonZeroU32::new(106).unwrap(), 1726062111)
-                                    .unwrap(),
-                            ),
-                        )),
-                    )
-                })
-                .collect(),
-            check_chain_net_err: None,
-        }
-    }
-}
-
 fn replace_transition_in_bundle(
     witness_bundle: &mut WitnessBundle,
     old_opid: OpId,
@@ -477,13 +386,6 @@ fn validate_consignment_generate() {
     }
 }
 
-fn get_consignment_from_json(fname: &str) -> Transfer {
-    let cons_path = format!("tests/fixtures/{fname}.json");
-    let file = std::fs::File::open(cons_path).unwrap();
-    let consignment: Transfer = serde_json::from_reader(file).unwrap();
-    consignment
-}
-
 #[cfg(not(feature = "altered"))]
 #[test]
 fn validate_consignment_success() {
@@ -1171,7 +1073,7 @@ fn validate_consignment_commitments_fail() {
         .iter()
         .map(|a| {
             let (seal, state) = a.to_revealed().unwrap();
-            rgb::Assign::ConfidentialSeal {
+            Assign::ConfidentialSeal {
                 seal: seal.to_secret_seal(),
                 state,
             }
@@ -2680,35 +2582,6 @@ fn validate_consignment_ifa() {
     }
 }
 
-#[derive(Clone, Debug, Eq, PartialEq)]
-enum Step {
-    Key(String),
-    Idx(usize),
-}
-
-type Path = Vec<Step>;
-
-fn get_entry_at_path_mut<'a>(root: &'a mut Value, path: &Path) -> &'a mut Value {
-    let mut curr = root;
-    for p in path {
-        match (curr, p) {
-            (Value::Object(map), Step::Key(k)) => {
-                curr = map.get_mut(k).unwrap();
-            }
-            (Value::Object(map), Step::Idx(i)) => {
-                curr = map.values_mut().nth(*i).unwrap();
-            }
-            (Value::Array(arr), Step::Idx(i)) => {
-                curr = arr.get_mut(*i).unwrap();
-            }
-            _ => {
-                unreachable!()
-            }
-        }
-    }
-    curr
-}
-
 #[cfg(not(feature = "altered"))]
 #[test]
 fn validate_consignment_typesystem_fail() {
@@ -3252,6 +3125,15 @@ fn validate_consignment_strict_roundtrip() {
     }
 }
 
+proptest! {
+    // bounded version of the consignment fuzz target, set PROPTEST_CASES to change the runs
+    #[cfg(not(feature = "altered"))]
+    #[test]
+    fn validate_consignment_fuzz(data in prop::collection::vec(any::<u8>(), 0..512)) {
+        fuzz_consignment(&data);
+    }
+}
+
 #[cfg(not(feature = "altered"))]
 #[test]
 fn validate_consignment_unknown_rgbisa_opcode() {